@@ -1,6 +1,7 @@
 use super::token;
 
 use std::any::Any;
+use std::fmt;
 
 #[derive(Hash, Clone)]
 pub enum NodeKind {
@@ -24,7 +25,7 @@ impl NodeKind {
             }
         }
     }
-    fn string(self) -> String {
+    pub fn string(self) -> String {
         match self {
             NodeKind::ExpressionNode{expressionKind} => {
                 expressionKind.string()
@@ -46,26 +47,183 @@ impl NodeKind {
     }
 }
 
+/// Where an identifier's value lives, filled in by the resolver. The parser
+/// leaves every identifier `Unresolved`, which the evaluator looks up by name.
+#[derive(Hash, Clone, Copy, PartialEq, Debug)]
+pub enum Binding {
+    Unresolved,
+    Global,
+    /// `depth` counts frames outward from the innermost one; `slot` indexes into that frame.
+    Local{depth: usize, slot: usize}
+}
+
 #[derive(Hash, Clone)]
 pub enum ExpressionKind {
-    Identifier{token: token::Token, value: String},
+    Identifier{token: token::Token, value: String, binding: Binding},
     PrefixExpression{token: token::Token, operator: String, right: Option<Box<ExpressionKind>>},
     InfixExpression{token: token::Token, left: Option<Box<ExpressionKind>>, operator: String, right: Option<Box<ExpressionKind>>},
     BooleanExpression{token: token::Token, value: bool}, 
     IfExpression{token: token::Token, condition: Option<Box<ExpressionKind>>, consequence: Option<Box<StatementKind>>, alternative: Option<Box<StatementKind>>}, 
-    FunctionLiteral{token: token::Token, parameters: Vec<ExpressionKind>, body: Box<StatementKind>},
+    /// `annotations` runs parallel to `parameters`.
+    FunctionLiteral{token: token::Token, parameters: Vec<ExpressionKind>, annotations: Vec<Option<TypeAnnotation>>, return_type: Option<TypeAnnotation>, body: Box<StatementKind>},
     CallExpression{token: token::Token, function: Box<ExpressionKind>, arguments: Vec<Box<ExpressionKind>>},
     StringLiteral{token: token::Token, value: String},
     IntegerLiteral{token: token::Token, value: u32},
     WhileLiteral{token: token::Token, condition: Box<ExpressionKind>, consequence: Box<StatementKind>},
     ArrayLiteral{token: token::Token, elements: Vec<Box<ExpressionKind>>},
-    IndexExpression{token: token::Token, left: Box<ExpressionKind>, index: Option<Box<ExpressionKind>>}
+    IndexExpression{token: token::Token, left: Box<ExpressionKind>, index: Option<Box<ExpressionKind>>, optional: bool},
+    HashLiteral{token: token::Token, pairs: Vec<(Box<ExpressionKind>, Box<ExpressionKind>)>},
+    MatchExpression{token: token::Token, subject: Box<ExpressionKind>, arms: Vec<MatchArm>},
+    NullLiteral{token: token::Token},
+    SlotExpression{token: token::Token, left: Box<ExpressionKind>, name: String, optional: bool}
+}
+
+/// A single `pattern [if guard] => body` arm of a match expression.
+#[derive(Hash, Clone)]
+pub struct MatchArm {
+    pub token: token::Token,
+    pub pattern: PatternKind,
+    pub guard: Option<Box<ExpressionKind>>,
+    pub body: Box<ExpressionKind>
+}
+
+/// A type written after a `let` name or a parameter, or after `->` for a
+/// function's result. Names are only interpreted by `lodestone check`.
+#[derive(Hash, Clone)]
+pub struct TypeAnnotation {
+    pub token: token::Token,
+    pub name: String
+}
+
+#[derive(Hash, Clone)]
+#[allow(clippy::enum_variant_names)]
+pub enum PatternKind {
+    WildcardPattern{token: token::Token},
+    LiteralPattern{token: token::Token, value: Box<ExpressionKind>},
+    BindingPattern{token: token::Token, name: String},
+    ArrayPattern{token: token::Token, elements: Vec<PatternKind>},
+    HashPattern{token: token::Token, pairs: Vec<(ExpressionKind, PatternKind)>},
+    OrPattern{token: token::Token, alternatives: Vec<PatternKind>},
+    /// `Shape.Rect(w, h)`. Without parentheses (`Shape.Rect`) only the
+    /// variant is checked, whatever its fields hold.
+    VariantPattern{token: token::Token, enum_name: String, variant: String, fields: Option<Vec<PatternKind>>}
+}
+
+/// One variant of an `enum` declaration: `Rect(w, h)`, or `Empty` with no fields.
+#[derive(Hash, Clone)]
+pub struct EnumVariant {
+    pub token: token::Token,
+    pub name: String,
+    pub fields: Vec<String>
+}
+
+impl fmt::Display for EnumVariant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.fields.is_empty() {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{}({})", self.name, self.fields.join(", "))
+        }
+    }
+}
+
+impl PatternKind {
+    /// Names bound by the pattern, in first-occurrence order. This order is
+    /// the layout of the frame a matching arm runs in.
+    pub fn binding_names(&self) -> Vec<String> {
+        let mut names = vec![];
+        self.collect_binding_names(&mut names);
+        names
+    }
+
+    fn collect_binding_names(&self, names: &mut Vec<String>) {
+        match self {
+            PatternKind::BindingPattern{name, ..} => {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            },
+            PatternKind::ArrayPattern{elements, ..} => {
+                for e in elements {
+                    e.collect_binding_names(names);
+                }
+            },
+            PatternKind::HashPattern{pairs, ..} => {
+                for (_, p) in pairs {
+                    p.collect_binding_names(names);
+                }
+            },
+            PatternKind::OrPattern{alternatives, ..} => {
+                for a in alternatives {
+                    a.collect_binding_names(names);
+                }
+            },
+            PatternKind::VariantPattern{fields: Some(fields), ..} => {
+                for f in fields {
+                    f.collect_binding_names(names);
+                }
+            },
+            PatternKind::VariantPattern{fields: None, ..} |
+            PatternKind::WildcardPattern{..} |
+            PatternKind::LiteralPattern{..} => {}
+        }
+    }
+
+    pub fn string(self) -> String {
+        match self {
+            PatternKind::WildcardPattern{..} => {
+                String::from("_")
+            },
+            PatternKind::LiteralPattern{value, ..} => {
+                value.string()
+            },
+            PatternKind::BindingPattern{name, ..} => {
+                name
+            },
+            PatternKind::ArrayPattern{elements, ..} => {
+                let mut out = String::from("[");
+                let mut eles_vec = vec![];
+                for e in elements {
+                    eles_vec.push(e.string());
+                }
+                out.push_str(&eles_vec.join(", "));
+                out.push(']');
+                out
+            },
+            PatternKind::HashPattern{pairs, ..} => {
+                let mut out = String::from("{");
+                let mut pairs_vec = vec![];
+                for (k, v) in pairs {
+                    pairs_vec.push(format!("{}: {}", k.string(), v.string()));
+                }
+                out.push_str(&pairs_vec.join(", "));
+                out.push('}');
+                out
+            },
+            PatternKind::OrPattern{alternatives, ..} => {
+                let mut alts_vec = vec![];
+                for a in alternatives {
+                    alts_vec.push(a.string());
+                }
+                alts_vec.join(" | ")
+            },
+            PatternKind::VariantPattern{enum_name, variant, fields, ..} => {
+                match fields {
+                    Some(fields) => {
+                        let fields: Vec<String> = fields.into_iter().map(|f| f.string()).collect();
+                        format!("{}.{}({})", enum_name, variant, fields.join(", "))
+                    },
+                    None => format!("{}.{}", enum_name, variant)
+                }
+            }
+        }
+    }
 }
 
 impl ExpressionKind {
     fn token_literal(self) -> String {
         match self {
-            ExpressionKind::Identifier{token, value} => {
+            ExpressionKind::Identifier{token, ..} => {
                 token.literal.clone()
             },
             ExpressionKind::PrefixExpression{token, operator, right} => {
@@ -80,7 +238,7 @@ impl ExpressionKind {
             ExpressionKind::BooleanExpression{token, value} => {
                 token.literal.clone()
             }
-            ExpressionKind::FunctionLiteral{token, parameters, body} => {
+            ExpressionKind::FunctionLiteral{token, ..} => {
                 token.literal.clone()
             },
             ExpressionKind::CallExpression{token, function, arguments} => {
@@ -98,14 +256,26 @@ impl ExpressionKind {
             ExpressionKind::ArrayLiteral{token, elements} => {
                 token.literal.clone()
             },
-            ExpressionKind::IndexExpression{token, left, index} => {
+            ExpressionKind::IndexExpression{token, ..} => {
+                token.literal.clone()
+            },
+            ExpressionKind::HashLiteral{token, ..} => {
+                token.literal.clone()
+            },
+            ExpressionKind::MatchExpression{token, ..} => {
+                token.literal.clone()
+            },
+            ExpressionKind::NullLiteral{token} => {
+                token.literal.clone()
+            },
+            ExpressionKind::SlotExpression{token, ..} => {
                 token.literal.clone()
             }
         }
     }
-    fn string(self) -> String {
+    pub fn string(self) -> String {
         match self {
-            ExpressionKind::Identifier{token, value} => {
+            ExpressionKind::Identifier{value, ..} => {
                 value
             },
             ExpressionKind::PrefixExpression{token, operator, right} => {
@@ -168,7 +338,7 @@ impl ExpressionKind {
                 }
                 out
             },
-            ExpressionKind::FunctionLiteral{token, parameters, body} => {
+            ExpressionKind::FunctionLiteral{token, parameters, body, ..} => {
                 let mut out = String::from("");
                 out.push_str(&token.literal.clone());
                 out.push_str("(");
@@ -220,10 +390,13 @@ impl ExpressionKind {
                 out.push_str("]");
                 out
             },
-            ExpressionKind::IndexExpression{token, left, index} => {
+            ExpressionKind::IndexExpression{left, index, optional, ..} => {
                 let mut out = String::from("");
                 out.push_str("(");
                 out.push_str(&left.string());
+                if optional {
+                    out.push('?');
+                }
                 out.push_str("[");
                 match index {
                     Some(i) => {
@@ -234,16 +407,72 @@ impl ExpressionKind {
                 out.push_str("])");
                 out
             },
+            ExpressionKind::HashLiteral{pairs, ..} => {
+                let mut out = String::from("{");
+                let mut pairs_vec = vec![];
+                for (k, v) in pairs {
+                    pairs_vec.push(format!("{}: {}", k.string(), v.string()));
+                }
+                out.push_str(&pairs_vec.join(", "));
+                out.push('}');
+                out
+            },
+            ExpressionKind::MatchExpression{subject, arms, ..} => {
+                let mut out = String::from("match ");
+                out.push_str(&subject.string());
+                out.push_str(" {");
+                let mut arms_vec = vec![];
+                for arm in arms {
+                    let mut a = arm.pattern.string();
+                    if let Some(g) = arm.guard {
+                        a.push_str(" if ");
+                        a.push_str(&g.string());
+                    }
+                    a.push_str(" => ");
+                    a.push_str(&arm.body.string());
+                    arms_vec.push(a);
+                }
+                out.push_str(&arms_vec.join(", "));
+                out.push('}');
+                out
+            },
+            ExpressionKind::NullLiteral{..} => {
+                String::from("null")
+            },
+            ExpressionKind::SlotExpression{left, name, optional, ..} => {
+                let mut out = String::from("(");
+                out.push_str(&left.string());
+                out.push_str(if optional { "?." } else { "." });
+                out.push_str(&name);
+                out.push(')');
+                out
+            },
         }
     }
 }
 
 #[derive(Hash, Clone)]
 pub enum StatementKind {
-    LetStatement{token: token::Token, name: ExpressionKind, value: Option<Box<ExpressionKind>>}, 
+    /// `constant` is set for `const`, whose binding can't be redeclared in its scope.
+    LetStatement{token: token::Token, name: ExpressionKind, annotation: Option<TypeAnnotation>, value: Option<Box<ExpressionKind>>, constant: bool}, 
     ReturnStatement{token: token::Token, return_value: Option<Box<StatementKind>>},
     ExpressionStatement{token: token::Token, expression: Option<Box<ExpressionKind>>},
-    BlockStatement{token: token::Token, statements: Vec<Box<StatementKind>>}
+    BlockStatement{token: token::Token, statements: Vec<Box<StatementKind>>},
+    /// `struct Point { x, y }` binds `name` to a constructor taking the fields in order.
+    StructStatement{token: token::Token, name: ExpressionKind, fields: Vec<String>},
+    /// `enum Shape { Circle(r), Rect(w, h) }` binds `name` to the enum, whose
+    /// slots are the variant constructors.
+    EnumStatement{token: token::Token, name: ExpressionKind, variants: Vec<EnumVariant>},
+    /// `p.x = value` writes a slot. `token` is the `=`; `target` is always a slot expression.
+    AssignStatement{token: token::Token, target: ExpressionKind, value: ExpressionKind},
+    /// `delete p.x` removes a slot.
+    DeleteStatement{token: token::Token, target: ExpressionKind},
+    /// `import "lib/math.ld" as m` binds `name` to the module's exports.
+    /// Without `as` (`alias` unset) the name is the file's stem, `math`.
+    ImportStatement{token: token::Token, path: String, name: ExpressionKind, alias: bool},
+    /// `export let ...`, `export const ...`, `export struct ...` or `export enum ...`
+    /// at the top level of a module makes the binding visible to importers.
+    ExportStatement{token: token::Token, statement: Box<StatementKind>}
 }
 
 impl StatementKind {
@@ -252,9 +481,9 @@ impl StatementKind {
         String::from("")    
     }
     
-    fn string(self) -> String {
+    pub fn string(self) -> String {
         match self {
-            StatementKind::LetStatement{token, name, value} => {
+            StatementKind::LetStatement{token, name, value, ..} => {
                 let mut out = String::from("");
                 out.push_str(&token.literal.clone());
                 out.push_str(" ");
@@ -298,6 +527,40 @@ impl StatementKind {
                 }
                 out
             },
+            StatementKind::StructStatement{token, name, fields} => {
+                format!("{} {} {{ {} }}", token.literal, name.string(), fields.join(", "))
+            },
+            StatementKind::EnumStatement{token, name, variants} => {
+                let variants: Vec<String> = variants.iter().map(|v| v.to_string()).collect();
+                format!("{} {} {{ {} }}", token.literal, name.string(), variants.join(", "))
+            },
+            StatementKind::AssignStatement{target, value, ..} => {
+                format!("{} = {}", target.string(), value.string())
+            },
+            StatementKind::DeleteStatement{token, target} => {
+                format!("{} {}", token.literal, target.string())
+            },
+            StatementKind::ImportStatement{token, path, name, alias} => {
+                if alias {
+                    format!("{} {:?} as {}", token.literal, path, name.string())
+                } else {
+                    format!("{} {:?}", token.literal, path)
+                }
+            },
+            StatementKind::ExportStatement{token, statement} => {
+                format!("{} {}", token.literal, statement.string())
+            },
+        }
+    }
+
+    /// The name a `let`, `const`, `struct`, `enum` or `import` binds.
+    pub fn declared_name(&self) -> Option<&str> {
+        match self {
+            StatementKind::LetStatement{name: ExpressionKind::Identifier{value, ..}, ..} |
+            StatementKind::StructStatement{name: ExpressionKind::Identifier{value, ..}, ..} |
+            StatementKind::EnumStatement{name: ExpressionKind::Identifier{value, ..}, ..} |
+            StatementKind::ImportStatement{name: ExpressionKind::Identifier{value, ..}, ..} => Some(value),
+            _ => None
         }
     }
 
@@ -312,14 +575,14 @@ mod tests {
 
     #[test]
     fn test_indentifer_string() {
-        let indentifer = ExpressionKind::Identifier{token: token::Token { literal: String::from("let"), t_type: token::LET}, value: String::from("5")};
+        let indentifer = ExpressionKind::Identifier{token: token::Token { literal: String::from("let"), t_type: token::LET, span: token::Span::default()}, value: String::from("5"), binding: Binding::Unresolved};
         assert_eq!(indentifer.string(), "5");
     }
 
     #[test]
     fn test_prefix_expression_string() {
-        let exp = ExpressionKind::Identifier{token: token::Token { literal: String::from("let"), t_type: token::LET}, value: String::from("5")};
-        let prefix = ExpressionKind::PrefixExpression{token: token::Token { literal: String::from("let"), t_type: token::LET},
+        let exp = ExpressionKind::Identifier{token: token::Token { literal: String::from("let"), t_type: token::LET, span: token::Span::default()}, value: String::from("5"), binding: Binding::Unresolved};
+        let prefix = ExpressionKind::PrefixExpression{token: token::Token { literal: String::from("let"), t_type: token::LET, span: token::Span::default()},
             operator: String::from("+"),
             right: Some(Box::new(exp))};
         assert_eq!(prefix.string(), "(+5)");
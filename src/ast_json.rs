@@ -0,0 +1,819 @@
+//! Conversion between the AST and the JSON form used by `lodestone ast --json`.
+//!
+//! Every node is an object whose `"type"` is the name of its AST variant and
+//! whose `"span"` is the `{"line", "column"}` of the token the parser recorded
+//! for it (the operator, for infix expressions). The remaining members are the
+//! variant's fields; see `docs/ast-json.md` for the full schema. Tokens are not serialized: `from_json` rebuilds them from the
+//! node type and span, which is all the printer and evaluator look at.
+
+use super::ast::NodeKind;
+use super::ast::ExpressionKind;
+use super::ast::StatementKind;
+use super::ast::PatternKind;
+use super::ast::MatchArm;
+use super::ast::Binding;
+use super::ast::TypeAnnotation;
+use super::ast::EnumVariant;
+use super::json;
+use super::json::JsonValue;
+use super::lexer;
+use super::lexer::Lexer;
+use super::token;
+use super::token::Span;
+use super::token::Token;
+use super::token::TokenType;
+
+/// Bumped whenever a field is renamed or removed.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Serializes a program (or a single statement or expression) to JSON.
+pub fn to_json(node: &NodeKind) -> JsonValue {
+    match node {
+        NodeKind::ProgramNode{statements} => {
+            JsonValue::Object(vec![
+                (String::from("type"), string("Program")),
+                (String::from("version"), JsonValue::Number(SCHEMA_VERSION as f64)),
+                (String::from("statements"), JsonValue::Array(statements.iter().map(statement_to_json).collect()))
+            ])
+        },
+        NodeKind::StatementNode{statementKind} => statement_to_json(statementKind),
+        NodeKind::ExpressionNode{expressionKind} => expression_to_json(expressionKind)
+    }
+}
+
+/// Serializes to indented JSON text.
+pub fn to_json_string(node: &NodeKind) -> String {
+    to_json(node).pretty()
+}
+
+/// Rebuilds a program from the output of `to_json`.
+pub fn from_json(value: &JsonValue) -> Result<NodeKind, String> {
+    match node_type(value)? {
+        "Program" => {
+            match value.get("version").and_then(|v| v.as_f64()) {
+                Some(v) if v == SCHEMA_VERSION as f64 => {},
+                Some(v) => return Err(format!("unsupported schema version {}", v)),
+                None => return Err(String::from("Program: missing field \"version\""))
+            }
+            let statements = array(value, "statements")?.iter().map(statement_from_json).collect::<Result<Vec<_>, _>>()?;
+            Ok(NodeKind::ProgramNode{statements})
+        },
+        _ => Err(String::from("expected a Program node at the top level"))
+    }
+}
+
+pub fn from_json_str(input: &str) -> Result<NodeKind, String> {
+    from_json(&json::parse(input)?)
+}
+
+fn string(s: &str) -> JsonValue {
+    JsonValue::Str(String::from(s))
+}
+
+fn node(node_type: &str, token: &Token, mut fields: Vec<(&str, JsonValue)>) -> JsonValue {
+    let mut members = vec![
+        (String::from("type"), string(node_type)),
+        (String::from("span"), JsonValue::Object(vec![
+            (String::from("line"), JsonValue::Number(token.span.line as f64)),
+            (String::from("column"), JsonValue::Number(token.span.column as f64))
+        ]))
+    ];
+    for (k, v) in fields.drain(..) {
+        members.push((String::from(k), v));
+    }
+    JsonValue::Object(members)
+}
+
+fn optional<T, F: Fn(&T) -> JsonValue>(value: &Option<T>, f: F) -> JsonValue {
+    match value {
+        Some(v) => f(v),
+        None => JsonValue::Null
+    }
+}
+
+fn statement_to_json(statement: &StatementKind) -> JsonValue {
+    match statement {
+        StatementKind::LetStatement{token, name, annotation, value, constant} => {
+            let name = match name {
+                ExpressionKind::Identifier{value, ..} => string(value),
+                other => expression_to_json(other)
+            };
+            let mut fields = vec![("name", name)];
+            if let Some(a) = annotation {
+                fields.push(("annotation", annotation_to_json(a)));
+            }
+            fields.push(("value", optional(value, |v| expression_to_json(v))));
+            if *constant {
+                fields.push(("constant", JsonValue::Bool(true)));
+            }
+            node("LetStatement", token, fields)
+        },
+        StatementKind::ReturnStatement{token, return_value} => {
+            // The parser wraps the returned expression in an expression statement.
+            let value = match return_value {
+                Some(rv) => match **rv {
+                    StatementKind::ExpressionStatement{ref expression, ..} => optional(expression, |e| expression_to_json(e)),
+                    ref other => statement_to_json(other)
+                },
+                None => JsonValue::Null
+            };
+            node("ReturnStatement", token, vec![("value", value)])
+        },
+        StatementKind::ExpressionStatement{token, expression} => {
+            node("ExpressionStatement", token, vec![
+                ("expression", optional(expression, |e| expression_to_json(e)))
+            ])
+        },
+        StatementKind::BlockStatement{token, statements} => {
+            node("BlockStatement", token, vec![
+                ("statements", JsonValue::Array(statements.iter().map(|s| statement_to_json(s)).collect()))
+            ])
+        },
+        StatementKind::StructStatement{token, name, fields} => {
+            let name = match name {
+                ExpressionKind::Identifier{value, ..} => string(value),
+                other => expression_to_json(other)
+            };
+            node("StructStatement", token, vec![
+                ("name", name),
+                ("fields", JsonValue::Array(fields.iter().map(|f| string(f)).collect()))
+            ])
+        },
+        StatementKind::EnumStatement{token, name, variants} => {
+            let name = match name {
+                ExpressionKind::Identifier{value, ..} => string(value),
+                other => expression_to_json(other)
+            };
+            let variants = variants.iter().map(|v| node("EnumVariant", &v.token, vec![
+                ("name", string(&v.name)),
+                ("fields", JsonValue::Array(v.fields.iter().map(|f| string(f)).collect()))
+            ])).collect();
+            node("EnumStatement", token, vec![
+                ("name", name),
+                ("variants", JsonValue::Array(variants))
+            ])
+        },
+        StatementKind::AssignStatement{token, target, value} => {
+            node("AssignStatement", token, vec![
+                ("target", expression_to_json(target)),
+                ("value", expression_to_json(value))
+            ])
+        },
+        StatementKind::DeleteStatement{token, target} => {
+            node("DeleteStatement", token, vec![("target", expression_to_json(target))])
+        },
+        StatementKind::ImportStatement{token, path, name, alias} => {
+            let name = match name {
+                ExpressionKind::Identifier{value, ..} => string(value),
+                other => expression_to_json(other)
+            };
+            let mut fields = vec![("path", string(path)), ("name", name)];
+            if *alias {
+                fields.push(("alias", JsonValue::Bool(true)));
+            }
+            node("ImportStatement", token, fields)
+        },
+        StatementKind::ExportStatement{token, statement} => {
+            node("ExportStatement", token, vec![("statement", statement_to_json(statement))])
+        }
+    }
+}
+
+fn expression_to_json(expression: &ExpressionKind) -> JsonValue {
+    match expression {
+        ExpressionKind::Identifier{token, value, ..} => {
+            node("Identifier", token, vec![("name", string(value))])
+        },
+        ExpressionKind::PrefixExpression{token, operator, right} => {
+            node("PrefixExpression", token, vec![
+                ("operator", string(operator)),
+                ("right", optional(right, |r| expression_to_json(r)))
+            ])
+        },
+        ExpressionKind::InfixExpression{token, left, operator, right} => {
+            node("InfixExpression", token, vec![
+                ("operator", string(operator)),
+                ("left", optional(left, |l| expression_to_json(l))),
+                ("right", optional(right, |r| expression_to_json(r)))
+            ])
+        },
+        ExpressionKind::BooleanExpression{token, value} => {
+            node("BooleanExpression", token, vec![("value", JsonValue::Bool(*value))])
+        },
+        ExpressionKind::IfExpression{token, condition, consequence, alternative} => {
+            node("IfExpression", token, vec![
+                ("condition", optional(condition, |c| expression_to_json(c))),
+                ("consequence", optional(consequence, |c| statement_to_json(c))),
+                ("alternative", optional(alternative, |a| statement_to_json(a)))
+            ])
+        },
+        ExpressionKind::FunctionLiteral{token, parameters, annotations, return_type, body} => {
+            let parameters = parameters.iter().enumerate().map(|(i, p)| {
+                match (expression_to_json(p), annotations.get(i)) {
+                    (JsonValue::Object(mut members), Some(Some(a))) => {
+                        members.push((String::from("annotation"), annotation_to_json(a)));
+                        JsonValue::Object(members)
+                    },
+                    (json, _) => json
+                }
+            }).collect();
+            let mut fields = vec![("parameters", JsonValue::Array(parameters))];
+            if let Some(r) = return_type {
+                fields.push(("returns", annotation_to_json(r)));
+            }
+            fields.push(("body", statement_to_json(body)));
+            node("FunctionLiteral", token, fields)
+        },
+        ExpressionKind::CallExpression{token, function, arguments} => {
+            node("CallExpression", token, vec![
+                ("function", expression_to_json(function)),
+                ("arguments", JsonValue::Array(arguments.iter().map(|a| expression_to_json(a)).collect()))
+            ])
+        },
+        ExpressionKind::StringLiteral{token, value} => {
+            node("StringLiteral", token, vec![("value", string(value))])
+        },
+        ExpressionKind::IntegerLiteral{token, value} => {
+            node("IntegerLiteral", token, vec![("value", JsonValue::Number(*value as f64))])
+        },
+        ExpressionKind::WhileLiteral{token, condition, consequence} => {
+            node("WhileLiteral", token, vec![
+                ("condition", expression_to_json(condition)),
+                ("body", statement_to_json(consequence))
+            ])
+        },
+        ExpressionKind::ArrayLiteral{token, elements} => {
+            node("ArrayLiteral", token, vec![
+                ("elements", JsonValue::Array(elements.iter().map(|e| expression_to_json(e)).collect()))
+            ])
+        },
+        ExpressionKind::IndexExpression{token, left, index, optional: is_optional} => {
+            node("IndexExpression", token, vec![
+                ("left", expression_to_json(left)),
+                ("index", optional(index, |i| expression_to_json(i))),
+                ("optional", JsonValue::Bool(*is_optional))
+            ])
+        },
+        ExpressionKind::HashLiteral{token, pairs} => {
+            let pairs = pairs.iter().map(|(k, v)| JsonValue::Object(vec![
+                (String::from("key"), expression_to_json(k)),
+                (String::from("value"), expression_to_json(v))
+            ])).collect();
+            node("HashLiteral", token, vec![("pairs", JsonValue::Array(pairs))])
+        },
+        ExpressionKind::MatchExpression{token, subject, arms} => {
+            node("MatchExpression", token, vec![
+                ("subject", expression_to_json(subject)),
+                ("arms", JsonValue::Array(arms.iter().map(match_arm_to_json).collect()))
+            ])
+        },
+        ExpressionKind::NullLiteral{token} => {
+            node("NullLiteral", token, vec![])
+        },
+        ExpressionKind::SlotExpression{token, left, name, optional: is_optional} => {
+            node("SlotExpression", token, vec![
+                ("left", expression_to_json(left)),
+                ("name", string(name)),
+                ("optional", JsonValue::Bool(*is_optional))
+            ])
+        }
+    }
+}
+
+fn match_arm_to_json(arm: &MatchArm) -> JsonValue {
+    node("MatchArm", &arm.token, vec![
+        ("pattern", pattern_to_json(&arm.pattern)),
+        ("guard", optional(&arm.guard, |g| expression_to_json(g))),
+        ("body", expression_to_json(&arm.body))
+    ])
+}
+
+fn pattern_to_json(pattern: &PatternKind) -> JsonValue {
+    match pattern {
+        PatternKind::WildcardPattern{token} => {
+            node("WildcardPattern", token, vec![])
+        },
+        PatternKind::LiteralPattern{token, value} => {
+            node("LiteralPattern", token, vec![("value", expression_to_json(value))])
+        },
+        PatternKind::BindingPattern{token, name} => {
+            node("BindingPattern", token, vec![("name", string(name))])
+        },
+        PatternKind::ArrayPattern{token, elements} => {
+            node("ArrayPattern", token, vec![
+                ("elements", JsonValue::Array(elements.iter().map(pattern_to_json).collect()))
+            ])
+        },
+        PatternKind::HashPattern{token, pairs} => {
+            let pairs = pairs.iter().map(|(k, p)| JsonValue::Object(vec![
+                (String::from("key"), expression_to_json(k)),
+                (String::from("pattern"), pattern_to_json(p))
+            ])).collect();
+            node("HashPattern", token, vec![("pairs", JsonValue::Array(pairs))])
+        },
+        PatternKind::OrPattern{token, alternatives} => {
+            node("OrPattern", token, vec![
+                ("alternatives", JsonValue::Array(alternatives.iter().map(pattern_to_json).collect()))
+            ])
+        },
+        PatternKind::VariantPattern{token, enum_name, variant, fields} => {
+            node("VariantPattern", token, vec![
+                ("enum", string(enum_name)),
+                ("variant", string(variant)),
+                ("fields", optional(fields, |f| JsonValue::Array(f.iter().map(pattern_to_json).collect())))
+            ])
+        }
+    }
+}
+
+fn annotation_to_json(annotation: &TypeAnnotation) -> JsonValue {
+    node("TypeAnnotation", &annotation.token, vec![("name", string(&annotation.name))])
+}
+
+/// Annotations are left out of the JSON when absent, so reading one treats a
+/// missing member the same as `null`.
+fn annotation_from_json(value: &JsonValue, name: &str) -> Result<Option<TypeAnnotation>, String> {
+    match value.get(name) {
+        None | Some(JsonValue::Null) => Ok(None),
+        Some(a) => {
+            if node_type(a)? != "TypeAnnotation" {
+                return Err(format!("{}: field \"{}\" must be a TypeAnnotation", node_type(value).unwrap_or("node"), name));
+            }
+            let type_name = match string_field(a, "name")? {
+                ref n if n == "fun" || n == "null" => n.clone(),
+                n => check_name(a, "name", n)?
+            };
+            Ok(Some(TypeAnnotation { token: make_token(token::IDENT, &type_name, a)?, name: type_name }))
+        }
+    }
+}
+
+fn node_type(value: &JsonValue) -> Result<&str, String> {
+    value.get("type").and_then(|t| t.as_str()).ok_or_else(|| String::from("node is missing its \"type\""))
+}
+
+fn field<'a>(value: &'a JsonValue, name: &str) -> Result<&'a JsonValue, String> {
+    match value.get(name) {
+        Some(v) => Ok(v),
+        None => Err(format!("{}: missing field \"{}\"", node_type(value).unwrap_or("node"), name))
+    }
+}
+
+fn array<'a>(value: &'a JsonValue, name: &str) -> Result<&'a Vec<JsonValue>, String> {
+    field(value, name)?.as_array().ok_or_else(|| format!("{}: field \"{}\" must be an array", node_type(value).unwrap_or("node"), name))
+}
+
+fn string_array(value: &JsonValue, name: &str) -> Result<Vec<String>, String> {
+    array(value, name)?.iter().map(|s| {
+        s.as_str().map(String::from).ok_or_else(|| format!("{}: field \"{}\" must hold strings", node_type(value).unwrap_or("node"), name))
+    }).collect()
+}
+
+/// Like `string_array`, for fields that hold names.
+fn name_array(value: &JsonValue, name: &str) -> Result<Vec<String>, String> {
+    string_array(value, name)?.into_iter().map(|n| check_name(value, name, n)).collect()
+}
+
+/// Reads a field the printer writes out as an identifier, so it must lex as one.
+fn name_field(value: &JsonValue, name: &str) -> Result<String, String> {
+    let n = string_field(value, name)?;
+    check_name(value, name, n)
+}
+
+fn check_name(value: &JsonValue, name: &str, n: String) -> Result<String, String> {
+    if lexer::is_identifier(&n) && n != "_" && token::keywords(n.clone()).is_none() {
+        Ok(n)
+    } else {
+        Err(format!("{}: field \"{}\" is not a valid name: {:?}", node_type(value).unwrap_or("node"), name, n))
+    }
+}
+
+/// Reads a field the printer writes out between double quotes, which the
+/// lexer can't escape.
+fn text_field(value: &JsonValue, name: &str) -> Result<String, String> {
+    let text = string_field(value, name)?;
+    if text.contains('"') || text.contains('\0') {
+        return Err(format!("{}: field \"{}\" can't contain '\"' or NUL: {:?}", node_type(value).unwrap_or("node"), name, text));
+    }
+    Ok(text)
+}
+
+fn string_field(value: &JsonValue, name: &str) -> Result<String, String> {
+    field(value, name)?.as_str().map(String::from).ok_or_else(|| format!("{}: field \"{}\" must be a string", node_type(value).unwrap_or("node"), name))
+}
+
+fn bool_field(value: &JsonValue, name: &str) -> Result<bool, String> {
+    field(value, name)?.as_bool().ok_or_else(|| format!("{}: field \"{}\" must be a boolean", node_type(value).unwrap_or("node"), name))
+}
+
+/// Reads a field that may be `null`, converting it with `f` otherwise.
+fn nullable<T, F: Fn(&JsonValue) -> Result<T, String>>(value: &JsonValue, name: &str, f: F) -> Result<Option<T>, String> {
+    match field(value, name)? {
+        JsonValue::Null => Ok(None),
+        v => f(v).map(Some)
+    }
+}
+
+fn span(value: &JsonValue) -> Result<Span, String> {
+    let span = field(value, "span")?;
+    let part = |name: &str| -> Result<u32, String> {
+        match span.get(name).and_then(|n| n.as_f64()) {
+            Some(n) if n >= 0.0 && n <= u32::MAX as f64 => Ok(n as u32),
+            _ => Err(format!("{}: span needs a numeric \"{}\"", node_type(value).unwrap_or("node"), name))
+        }
+    };
+    Ok(Span { line: part("line")?, column: part("column")? })
+}
+
+fn make_token(t_type: TokenType, literal: &str, value: &JsonValue) -> Result<Token, String> {
+    Ok(Token { t_type, literal: String::from(literal), span: span(value)? })
+}
+
+/// Rebuilds an operator token by lexing its symbol, so custom operators get
+/// the same token type the parser would have produced.
+fn operator_token(operator: &str, value: &JsonValue) -> Result<Token, String> {
+    let mut lexer = Lexer::new(String::from(operator));
+    let mut token = lexer.next_token();
+    if token.literal != operator || lexer.next_token().t_type != token::EOF {
+        return Err(format!("{}: {:?} is not an operator", node_type(value).unwrap_or("node"), operator));
+    }
+    token.span = span(value)?;
+    Ok(token)
+}
+
+fn boxed_expression(value: &JsonValue) -> Result<Box<ExpressionKind>, String> {
+    expression_from_json(value).map(Box::new)
+}
+
+fn boxed_statement(value: &JsonValue) -> Result<Box<StatementKind>, String> {
+    statement_from_json(value).map(Box::new)
+}
+
+fn statement_from_json(value: &JsonValue) -> Result<StatementKind, String> {
+    match node_type(value)? {
+        "LetStatement" => {
+            let name = match field(value, "name")? {
+                JsonValue::Str(name) => ExpressionKind::Identifier{token: make_token(token::IDENT, name, value)?, value: name.clone(), binding: Binding::Unresolved},
+                other => expression_from_json(other)?
+            };
+            let constant = match value.get("constant") {
+                None => false,
+                Some(_) => bool_field(value, "constant")?
+            };
+            let token = if constant { make_token(token::CONST, "const", value)? } else { make_token(token::LET, "let", value)? };
+            Ok(StatementKind::LetStatement{
+                token,
+                name,
+                annotation: annotation_from_json(value, "annotation")?,
+                value: nullable(value, "value", boxed_expression)?,
+                constant
+            })
+        },
+        "ReturnStatement" => {
+            let token = make_token(token::RETURN, "return", value)?;
+            let return_value = nullable(value, "value", |v| {
+                Ok(Box::new(StatementKind::ExpressionStatement{token: token.clone(), expression: Some(boxed_expression(v)?)}))
+            })?;
+            Ok(StatementKind::ReturnStatement{token, return_value})
+        },
+        "ExpressionStatement" => {
+            let expression = nullable(value, "expression", boxed_expression)?;
+            // The statement's token is the first token of its expression,
+            // which is not always the expression's own token (`a + b`).
+            let token = match expression {
+                Some(ref e) => Token { span: span(value)?, ..expression_token(e) },
+                None => make_token(token::SEMICOLON, ";", value)?
+            };
+            Ok(StatementKind::ExpressionStatement{token, expression})
+        },
+        "BlockStatement" => {
+            Ok(StatementKind::BlockStatement{
+                token: make_token(token::LBRACE, "{", value)?,
+                statements: array(value, "statements")?.iter().map(boxed_statement).collect::<Result<_, _>>()?
+            })
+        },
+        "StructStatement" => {
+            let name = name_field(value, "name")?;
+            Ok(StatementKind::StructStatement{
+                token: make_token(token::STRUCT, "struct", value)?,
+                name: ExpressionKind::Identifier{token: make_token(token::IDENT, &name, value)?, value: name, binding: Binding::Unresolved},
+                fields: name_array(value, "fields")?
+            })
+        },
+        "EnumStatement" => {
+            let name = name_field(value, "name")?;
+            let variants = array(value, "variants")?.iter().map(|v| {
+                let variant = name_field(v, "name")?;
+                Ok(EnumVariant { token: make_token(token::IDENT, &variant, v)?, name: variant, fields: name_array(v, "fields")? })
+            }).collect::<Result<_, String>>()?;
+            Ok(StatementKind::EnumStatement{
+                token: make_token(token::ENUM, "enum", value)?,
+                name: ExpressionKind::Identifier{token: make_token(token::IDENT, &name, value)?, value: name, binding: Binding::Unresolved},
+                variants
+            })
+        },
+        "AssignStatement" => {
+            Ok(StatementKind::AssignStatement{
+                token: make_token(token::REASSIGN, "=", value)?,
+                target: expression_from_json(field(value, "target")?)?,
+                value: expression_from_json(field(value, "value")?)?
+            })
+        },
+        "DeleteStatement" => {
+            Ok(StatementKind::DeleteStatement{
+                token: make_token(token::DELETE, "delete", value)?,
+                target: expression_from_json(field(value, "target")?)?
+            })
+        },
+        "ImportStatement" => {
+            let name = name_field(value, "name")?;
+            let alias = match value.get("alias") {
+                None => false,
+                Some(_) => bool_field(value, "alias")?
+            };
+            Ok(StatementKind::ImportStatement{
+                token: make_token(token::IMPORT, "import", value)?,
+                path: text_field(value, "path")?,
+                name: ExpressionKind::Identifier{token: make_token(token::IDENT, &name, value)?, value: name, binding: Binding::Unresolved},
+                alias
+            })
+        },
+        "ExportStatement" => {
+            Ok(StatementKind::ExportStatement{
+                token: make_token(token::EXPORT, "export", value)?,
+                statement: boxed_statement(field(value, "statement")?)?
+            })
+        },
+        other => Err(format!("unknown statement type \"{}\"", other))
+    }
+}
+
+fn expression_token(expression: &ExpressionKind) -> Token {
+    match expression {
+        ExpressionKind::Identifier{token, ..} |
+        ExpressionKind::PrefixExpression{token, ..} |
+        ExpressionKind::InfixExpression{token, ..} |
+        ExpressionKind::BooleanExpression{token, ..} |
+        ExpressionKind::IfExpression{token, ..} |
+        ExpressionKind::FunctionLiteral{token, ..} |
+        ExpressionKind::CallExpression{token, ..} |
+        ExpressionKind::StringLiteral{token, ..} |
+        ExpressionKind::IntegerLiteral{token, ..} |
+        ExpressionKind::WhileLiteral{token, ..} |
+        ExpressionKind::ArrayLiteral{token, ..} |
+        ExpressionKind::IndexExpression{token, ..} |
+        ExpressionKind::HashLiteral{token, ..} |
+        ExpressionKind::MatchExpression{token, ..} |
+        ExpressionKind::NullLiteral{token} |
+        ExpressionKind::SlotExpression{token, ..} => token.clone()
+    }
+}
+
+fn expression_from_json(value: &JsonValue) -> Result<ExpressionKind, String> {
+    match node_type(value)? {
+        "Identifier" => {
+            let name = name_field(value, "name")?;
+            Ok(ExpressionKind::Identifier{token: make_token(token::IDENT, &name, value)?, value: name, binding: Binding::Unresolved})
+        },
+        "PrefixExpression" => {
+            let operator = string_field(value, "operator")?;
+            Ok(ExpressionKind::PrefixExpression{
+                token: operator_token(&operator, value)?,
+                operator,
+                right: nullable(value, "right", boxed_expression)?
+            })
+        },
+        "InfixExpression" => {
+            let operator = string_field(value, "operator")?;
+            Ok(ExpressionKind::InfixExpression{
+                token: operator_token(&operator, value)?,
+                left: nullable(value, "left", boxed_expression)?,
+                operator,
+                right: nullable(value, "right", boxed_expression)?
+            })
+        },
+        "BooleanExpression" => {
+            let b = bool_field(value, "value")?;
+            let token = if b { make_token(token::TRUE, "true", value)? } else { make_token(token::FALSE, "false", value)? };
+            Ok(ExpressionKind::BooleanExpression{token, value: b})
+        },
+        "IfExpression" => {
+            Ok(ExpressionKind::IfExpression{
+                token: make_token(token::IF, "if", value)?,
+                condition: nullable(value, "condition", boxed_expression)?,
+                consequence: nullable(value, "consequence", boxed_statement)?,
+                alternative: nullable(value, "alternative", boxed_statement)?
+            })
+        },
+        "FunctionLiteral" => {
+            let parameters = array(value, "parameters")?;
+            Ok(ExpressionKind::FunctionLiteral{
+                token: make_token(token::FUNCTION, "fun", value)?,
+                parameters: parameters.iter().map(expression_from_json).collect::<Result<_, _>>()?,
+                annotations: parameters.iter().map(|p| annotation_from_json(p, "annotation")).collect::<Result<_, _>>()?,
+                return_type: annotation_from_json(value, "returns")?,
+                body: boxed_statement(field(value, "body")?)?
+            })
+        },
+        "CallExpression" => {
+            Ok(ExpressionKind::CallExpression{
+                token: make_token(token::LPAREN, "(", value)?,
+                function: boxed_expression(field(value, "function")?)?,
+                arguments: array(value, "arguments")?.iter().map(boxed_expression).collect::<Result<_, _>>()?
+            })
+        },
+        "StringLiteral" => {
+            let s = text_field(value, "value")?;
+            Ok(ExpressionKind::StringLiteral{token: make_token(token::STRING, &s, value)?, value: s})
+        },
+        "IntegerLiteral" => {
+            let n = match field(value, "value")?.as_f64() {
+                Some(n) if n >= 0.0 && n <= u32::MAX as f64 && n.fract() == 0.0 => n as u32,
+                _ => return Err(String::from("IntegerLiteral: \"value\" must be an unsigned 32-bit integer"))
+            };
+            Ok(ExpressionKind::IntegerLiteral{token: make_token(token::INT, &n.to_string(), value)?, value: n})
+        },
+        "WhileLiteral" => {
+            Ok(ExpressionKind::WhileLiteral{
+                token: make_token(token::WHILE, "while", value)?,
+                condition: boxed_expression(field(value, "condition")?)?,
+                consequence: boxed_statement(field(value, "body")?)?
+            })
+        },
+        "ArrayLiteral" => {
+            Ok(ExpressionKind::ArrayLiteral{
+                token: make_token(token::LBRACKET, "[", value)?,
+                elements: array(value, "elements")?.iter().map(boxed_expression).collect::<Result<_, _>>()?
+            })
+        },
+        "IndexExpression" => {
+            let is_optional = bool_field(value, "optional")?;
+            let token = if is_optional { make_token(token::OPTIONAL_LBRACKET, "?[", value)? } else { make_token(token::LBRACKET, "[", value)? };
+            Ok(ExpressionKind::IndexExpression{
+                token,
+                left: boxed_expression(field(value, "left")?)?,
+                index: nullable(value, "index", boxed_expression)?,
+                optional: is_optional
+            })
+        },
+        "HashLiteral" => {
+            let pairs = array(value, "pairs")?.iter().map(|p| {
+                Ok((boxed_expression(field(p, "key")?)?, boxed_expression(field(p, "value")?)?))
+            }).collect::<Result<_, String>>()?;
+            Ok(ExpressionKind::HashLiteral{token: make_token(token::LBRACE, "{", value)?, pairs})
+        },
+        "MatchExpression" => {
+            Ok(ExpressionKind::MatchExpression{
+                token: make_token(token::MATCH, "match", value)?,
+                subject: boxed_expression(field(value, "subject")?)?,
+                arms: array(value, "arms")?.iter().map(match_arm_from_json).collect::<Result<_, _>>()?
+            })
+        },
+        "NullLiteral" => {
+            Ok(ExpressionKind::NullLiteral{token: make_token(token::NULL, "null", value)?})
+        },
+        "SlotExpression" => {
+            let is_optional = bool_field(value, "optional")?;
+            let token = if is_optional { make_token(token::OPTIONAL_SLOT, "?.", value)? } else { make_token(token::SLOT, ".", value)? };
+            Ok(ExpressionKind::SlotExpression{
+                token,
+                left: boxed_expression(field(value, "left")?)?,
+                name: name_field(value, "name")?,
+                optional: is_optional
+            })
+        },
+        other => Err(format!("unknown expression type \"{}\"", other))
+    }
+}
+
+fn match_arm_from_json(value: &JsonValue) -> Result<MatchArm, String> {
+    Ok(MatchArm {
+        token: make_token(token::FAT_ARROW, "=>", value)?,
+        pattern: pattern_from_json(field(value, "pattern")?)?,
+        guard: nullable(value, "guard", boxed_expression)?,
+        body: boxed_expression(field(value, "body")?)?
+    })
+}
+
+fn pattern_from_json(value: &JsonValue) -> Result<PatternKind, String> {
+    match node_type(value)? {
+        "WildcardPattern" => {
+            Ok(PatternKind::WildcardPattern{token: make_token(token::UNDERSCORE, "_", value)?})
+        },
+        "LiteralPattern" => {
+            let literal = expression_from_json(field(value, "value")?)?;
+            Ok(PatternKind::LiteralPattern{token: expression_token(&literal), value: Box::new(literal)})
+        },
+        "BindingPattern" => {
+            let name = name_field(value, "name")?;
+            Ok(PatternKind::BindingPattern{token: make_token(token::IDENT, &name, value)?, name})
+        },
+        "ArrayPattern" => {
+            Ok(PatternKind::ArrayPattern{
+                token: make_token(token::LBRACKET, "[", value)?,
+                elements: array(value, "elements")?.iter().map(pattern_from_json).collect::<Result<_, _>>()?
+            })
+        },
+        "HashPattern" => {
+            let pairs = array(value, "pairs")?.iter().map(|p| {
+                Ok((expression_from_json(field(p, "key")?)?, pattern_from_json(field(p, "pattern")?)?))
+            }).collect::<Result<_, String>>()?;
+            Ok(PatternKind::HashPattern{token: make_token(token::LBRACE, "{", value)?, pairs})
+        },
+        "OrPattern" => {
+            Ok(PatternKind::OrPattern{
+                token: make_token(token::PIPE, "|", value)?,
+                alternatives: array(value, "alternatives")?.iter().map(pattern_from_json).collect::<Result<_, _>>()?
+            })
+        },
+        "VariantPattern" => {
+            let enum_name = name_field(value, "enum")?;
+            Ok(PatternKind::VariantPattern{
+                token: make_token(token::IDENT, &enum_name, value)?,
+                enum_name,
+                variant: name_field(value, "variant")?,
+                fields: nullable(value, "fields", |f| {
+                    match f.as_array() {
+                        Some(fields) => fields.iter().map(pattern_from_json).collect(),
+                        None => Err(String::from("VariantPattern: field \"fields\" must be an array"))
+                    }
+                })?
+            })
+        },
+        other => Err(format!("unknown pattern type \"{}\"", other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::parser::Parser;
+    use super::super::printer::Printer;
+    use super::super::printer::DEFAULT_WIDTH;
+
+    fn parse(input: &str) -> NodeKind {
+        let mut p = Parser::new(Lexer::new(String::from(input)));
+        p.parse_program()
+    }
+
+    #[test]
+    fn test_round_trip_through_json() {
+        let input = "let add: fun := fun(a: int, b) -> int { return a + b; };
+let h := {\"k\": [1, true, null]};
+if (add(1, 2) > 2) { h?.k } else { -3 };
+h[\"k\"]?[0] |> add(1);
+match h { {\"k\": [x, _]} | [x] if x == 1 => \"one\\n\", _ => null };
+enum Shape { Circle(r), Empty }
+h.k = 2;
+delete h.k;
+import \"lib/math.ld\";
+import \"util\" as u;
+export const pi := 3;
+match s { Shape.Circle(1) => 1, Shape.Empty => 0 };";
+        let program = parse(input);
+        let text = to_json_string(&program);
+        let decoded = from_json_str(&text).unwrap();
+        let printer = Printer::new(DEFAULT_WIDTH);
+        assert_eq!(printer.print_program(&decoded), printer.print_program(&program));
+        assert_eq!(to_json_string(&decoded), text);
+    }
+
+    #[test]
+    fn test_schema_shape() {
+        let value = to_json(&parse("let x := 1 + y;"));
+        assert_eq!(value.to_string(), "{\"type\":\"Program\",\"version\":1,\"statements\":[\
+{\"type\":\"LetStatement\",\"span\":{\"line\":1,\"column\":1},\"name\":\"x\",\"value\":\
+{\"type\":\"InfixExpression\",\"span\":{\"line\":1,\"column\":12},\"operator\":\"+\",\
+\"left\":{\"type\":\"IntegerLiteral\",\"span\":{\"line\":1,\"column\":10},\"value\":1},\
+\"right\":{\"type\":\"Identifier\",\"span\":{\"line\":1,\"column\":14},\"name\":\"y\"}}}]}");
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert!(from_json_str("{\"type\": \"Program\", \"version\": 99, \"statements\": []}").is_err());
+        let missing = from_json_str("{\"type\": \"Program\", \"version\": 1, \"statements\": [{\"type\": \"ExpressionStatement\", \"span\": {\"line\": 1, \"column\": 1}}]}");
+        assert_eq!(missing.err(), Some(String::from("ExpressionStatement: missing field \"expression\"")));
+        let unknown = from_json_str("{\"type\": \"Program\", \"version\": 1, \"statements\": [{\"type\": \"Goto\"}]}");
+        assert_eq!(unknown.err(), Some(String::from("unknown statement type \"Goto\"")));
+    }
+
+    fn expression_error(expression: &str) -> Option<String> {
+        let input = format!("{{\"type\": \"Program\", \"version\": 1, \"statements\": [{{\"type\": \"ExpressionStatement\", \"span\": {{\"line\": 1, \"column\": 1}}, \"expression\": {}}}]}}", expression);
+        from_json_str(&input).err()
+    }
+
+    #[test]
+    fn test_rejects_unprintable_names_and_strings() {
+        let tests = vec![
+            ("{\"type\": \"Identifier\", \"span\": {\"line\": 1, \"column\": 1}, \"name\": \"a b\"}", "Identifier: field \"name\" is not a valid name: \"a b\""),
+            ("{\"type\": \"Identifier\", \"span\": {\"line\": 1, \"column\": 1}, \"name\": \"let\"}", "Identifier: field \"name\" is not a valid name: \"let\""),
+            ("{\"type\": \"Identifier\", \"span\": {\"line\": 1, \"column\": 1}, \"name\": \"\"}", "Identifier: field \"name\" is not a valid name: \"\""),
+            ("{\"type\": \"StringLiteral\", \"span\": {\"line\": 1, \"column\": 1}, \"value\": \"a\\\"b\"}", "StringLiteral: field \"value\" can't contain '\"' or NUL: \"a\\\"b\""),
+            ("{\"type\": \"PrefixExpression\", \"span\": {\"line\": 1, \"column\": 1}, \"operator\": \"- -\", \"right\": null}", "PrefixExpression: \"- -\" is not an operator"),
+        ];
+        for (expression, expected) in tests {
+            assert_eq!(expression_error(expression), Some(String::from(expected)), "{}", expression);
+        }
+        assert_eq!(expression_error("{\"type\": \"StringLiteral\", \"span\": {\"line\": 1, \"column\": 1}, \"value\": \"café a_b\"}"), None);
+    }
+}
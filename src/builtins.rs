@@ -0,0 +1,444 @@
+//! Functions implemented in Rust and available to every program by name.
+//!
+//! A builtin is found only when no variable of that name is in scope, so
+//! programs are free to define their own `type_of` or `slots`. The host
+//! application can add its own with `register_builtin`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use super::ast::ExpressionKind;
+use super::object::ObjectKind;
+use super::printer::Printer;
+use super::printer::DEFAULT_WIDTH;
+use super::lexer;
+use super::token;
+
+pub type BuiltinFunction = fn(&[ObjectKind]) -> ObjectKind;
+
+const BUILTINS: &[(&str, BuiltinFunction)] = &[
+    ("len", len),
+    ("puts", puts),
+    ("print", print),
+    ("type", type_name),
+    ("str", str),
+    ("int", int),
+    ("first", first),
+    ("last", last),
+    ("rest", rest),
+    ("push", push),
+    ("type_of", type_of),
+    ("slots", slots),
+    ("has_slot", has_slot),
+    ("arity", arity),
+    ("params", params),
+    ("source", source),
+];
+
+thread_local! {
+    static REGISTRY: RefCell<Vec<(&'static str, BuiltinFunction)>> = RefCell::new(BUILTINS.to_vec());
+}
+
+/// Makes `function` available as `name` to code resolved and evaluated
+/// afterwards on this thread. Registering a name again replaces the earlier
+/// function, including the predefined ones.
+pub fn register_builtin(name: &'static str, function: BuiltinFunction) -> Result<(), String> {
+    if !lexer::is_identifier(name) || name == "_" {
+        return Err(format!("builtin name must be an identifier: {:?}", name));
+    }
+    if token::lookup_ident(String::from(name)) != token::IDENT {
+        return Err(format!("builtin name {} is a keyword", name));
+    }
+    REGISTRY.with(|builtins| {
+        let mut builtins = builtins.borrow_mut();
+        builtins.retain(|&(n, _)| n != name);
+        builtins.push((name, function));
+    });
+    Ok(())
+}
+
+/// The builtin called `name`, as a value.
+pub fn lookup(name: &str) -> Option<ObjectKind> {
+    REGISTRY.with(|builtins| {
+        builtins.borrow().iter().find(|&&(n, _)| n == name).map(|&(n, function)| ObjectKind::BuiltIn{name: n, function})
+    })
+}
+
+fn expect_args(name: &str, args: &[ObjectKind], count: usize) -> Result<(), String> {
+    if args.len() == count {
+        Ok(())
+    } else {
+        let plural = if count == 1 { "" } else { "s" };
+        Err(format!("{} expects {} argument{}, got {}", name, count, plural, args.len()))
+    }
+}
+
+fn error(message: String) -> ObjectKind {
+    ObjectKind::Error{message}
+}
+
+fn string(value: &str) -> ObjectKind {
+    ObjectKind::StringObj{slots: HashMap::new(), value: String::from(value)}
+}
+
+fn strings(values: Vec<String>) -> ObjectKind {
+    array(values.iter().map(|v| string(v)).collect())
+}
+
+fn array(elements: Vec<ObjectKind>) -> ObjectKind {
+    ObjectKind::Array{slots: HashMap::new(), elements, frozen: false}
+}
+
+fn integer(value: u32) -> ObjectKind {
+    ObjectKind::Integer{slots: HashMap::new(), value}
+}
+
+/// The elements of an array argument, or an error naming the builtin.
+fn elements<'a>(name: &str, value: &'a ObjectKind) -> Result<&'a Vec<ObjectKind>, String> {
+    match value {
+        ObjectKind::Array{elements, ..} => Ok(elements),
+        other => Err(format!("{} expects an array, got {}", name, other.type_name()))
+    }
+}
+
+/// The number of characters in a string, elements in an array or pairs in a hash.
+fn len(args: &[ObjectKind]) -> ObjectKind {
+    if let Err(e) = expect_args("len", args, 1) {
+        return error(e);
+    }
+    match args[0] {
+        ObjectKind::StringObj{ref value, ..} => integer(value.chars().count() as u32),
+        ObjectKind::Array{ref elements, ..} => integer(elements.len() as u32),
+        ObjectKind::Hash{ref pairs, ..} => integer(pairs.len() as u32),
+        ref other => error(format!("len is not supported for {}", other.type_name()))
+    }
+}
+
+/// Prints each argument on its own line.
+fn puts(args: &[ObjectKind]) -> ObjectKind {
+    for a in args {
+        println!("{}", a);
+    }
+    ObjectKind::Null
+}
+
+/// Prints the arguments separated by spaces, without ending the line.
+fn print(args: &[ObjectKind]) -> ObjectKind {
+    let parts: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+    print!("{}", parts.join(" "));
+    ObjectKind::Null
+}
+
+/// The kind of value: `"integer"`, `"string"`, `"struct"`, ...
+fn type_name(args: &[ObjectKind]) -> ObjectKind {
+    if let Err(e) = expect_args("type", args, 1) {
+        return error(e);
+    }
+    string(args[0].type_name())
+}
+
+/// A value as it would be printed.
+fn str(args: &[ObjectKind]) -> ObjectKind {
+    if let Err(e) = expect_args("str", args, 1) {
+        return error(e);
+    }
+    string(&args[0].to_string())
+}
+
+/// Parses a string as an integer. Integers are returned unchanged.
+fn int(args: &[ObjectKind]) -> ObjectKind {
+    if let Err(e) = expect_args("int", args, 1) {
+        return error(e);
+    }
+    match args[0] {
+        ObjectKind::Integer{value, ..} => integer(value),
+        ObjectKind::Boolean{value} => integer(if value { 1 } else { 0 }),
+        ObjectKind::StringObj{ref value, ..} => {
+            match value.trim().parse::<u32>() {
+                Ok(n) => integer(n),
+                Err(_) => error(format!("cannot convert {:?} to an integer", value))
+            }
+        },
+        ref other => error(format!("cannot convert {} to an integer", other.type_name()))
+    }
+}
+
+/// The first element of an array, or null if it is empty.
+fn first(args: &[ObjectKind]) -> ObjectKind {
+    if let Err(e) = expect_args("first", args, 1) {
+        return error(e);
+    }
+    match elements("first", &args[0]) {
+        Ok(elements) => elements.first().cloned().unwrap_or(ObjectKind::Null),
+        Err(e) => error(e)
+    }
+}
+
+/// The last element of an array, or null if it is empty.
+fn last(args: &[ObjectKind]) -> ObjectKind {
+    if let Err(e) = expect_args("last", args, 1) {
+        return error(e);
+    }
+    match elements("last", &args[0]) {
+        Ok(elements) => elements.last().cloned().unwrap_or(ObjectKind::Null),
+        Err(e) => error(e)
+    }
+}
+
+/// A new array without the first element, or null if it is empty.
+fn rest(args: &[ObjectKind]) -> ObjectKind {
+    if let Err(e) = expect_args("rest", args, 1) {
+        return error(e);
+    }
+    match elements("rest", &args[0]) {
+        Ok(elements) if elements.is_empty() => ObjectKind::Null,
+        Ok(elements) => array(elements[1..].to_vec()),
+        Err(e) => error(e)
+    }
+}
+
+/// A new array with `value` added at the end; the argument is unchanged.
+fn push(args: &[ObjectKind]) -> ObjectKind {
+    if let Err(e) = expect_args("push", args, 2) {
+        return error(e);
+    }
+    match elements("push", &args[0]) {
+        Ok(elements) => {
+            let mut elements = elements.clone();
+            elements.push(args[1].clone());
+            array(elements)
+        },
+        Err(e) => error(e)
+    }
+}
+
+fn parameter_names(parameters: &[ExpressionKind]) -> Vec<String> {
+    parameters.iter().filter_map(|p| match p {
+        ExpressionKind::Identifier{value, ..} => Some(value.clone()),
+        _ => None
+    }).collect()
+}
+
+/// The kind of value, or the name of a struct or enum for its instances.
+fn type_of(args: &[ObjectKind]) -> ObjectKind {
+    if let Err(e) = expect_args("type_of", args, 1) {
+        return error(e);
+    }
+    match args[0] {
+        ObjectKind::Struct{ref name, ..} => string(name),
+        ObjectKind::Variant{ref enum_name, ..} => string(enum_name),
+        ref other => string(other.type_name())
+    }
+}
+
+/// The names of a value's own slots: sorted for slot maps, objects and
+/// hashes, in declaration order for struct fields and enum variants.
+fn slots(args: &[ObjectKind]) -> ObjectKind {
+    if let Err(e) = expect_args("slots", args, 1) {
+        return error(e);
+    }
+    let mut names: Vec<String> = match args[0] {
+        ObjectKind::Integer{ref slots, ..} |
+        ObjectKind::Function{ref slots, ..} |
+        ObjectKind::StringObj{ref slots, ..} |
+        ObjectKind::Array{ref slots, ..} |
+        ObjectKind::Module{ref slots, ..} => {
+            slots.keys().cloned().collect()
+        },
+        ObjectKind::Object{ref slots, ..} => {
+            slots.borrow().keys().cloned().collect()
+        },
+        ObjectKind::Hash{ref pairs, ..} => {
+            pairs.values().filter_map(|(k, _)| match k {
+                ObjectKind::StringObj{value, ..} => Some(value.clone()),
+                _ => None
+            }).collect()
+        },
+        ObjectKind::Struct{ref fields, ..} | ObjectKind::Variant{ref fields, ..} => {
+            return strings(fields.iter().map(|(f, _)| f.clone()).collect());
+        },
+        ObjectKind::EnumType{ref variants, ..} => {
+            return strings(variants.iter().map(|(v, _)| v.clone()).collect());
+        },
+        ref other => {
+            return error(format!("{} has no slots", other.type_name()));
+        }
+    };
+    names.sort();
+    strings(names)
+}
+
+/// Whether reading `name` from the value would succeed, parents included.
+fn has_slot(args: &[ObjectKind]) -> ObjectKind {
+    if let Err(e) = expect_args("has_slot", args, 2) {
+        return error(e);
+    }
+    match args[1] {
+        ObjectKind::StringObj{ref value, ..} => {
+            let found = !matches!(args[0].clone().get_from_slots(value.clone()), ObjectKind::Error{..});
+            ObjectKind::Boolean{value: found}
+        },
+        ref other => error(format!("has_slot expects a string slot name, got {}", other.type_name()))
+    }
+}
+
+/// How many arguments a function or constructor takes.
+fn arity(args: &[ObjectKind]) -> ObjectKind {
+    if let Err(e) = expect_args("arity", args, 1) {
+        return error(e);
+    }
+    if let ObjectKind::Native{arity, ..} = args[0] {
+        return integer(arity as u32);
+    }
+    match params(args) {
+        ObjectKind::Array{elements, ..} => ObjectKind::Integer{slots: HashMap::new(), value: elements.len() as u32},
+        other => other
+    }
+}
+
+/// The parameter names of a function, or the fields a constructor takes.
+fn params(args: &[ObjectKind]) -> ObjectKind {
+    if let Err(e) = expect_args("params", args, 1) {
+        return error(e);
+    }
+    match args[0] {
+        ObjectKind::Function{ref parameters, ..} => strings(parameter_names(parameters)),
+        ObjectKind::StructType{ref fields, ..} | ObjectKind::VariantType{ref fields, ..} => strings(fields.clone()),
+        ObjectKind::BuiltIn{name, ..} => error(format!("parameters of builtin {} are not known", name)),
+        ObjectKind::Native{ref name, ..} => error(format!("parameters of native function {} are not known", name)),
+        ref other => error(format!("expected a function, got {}", other.type_name()))
+    }
+}
+
+/// A function's source, printed from its syntax tree in `lodestone fmt`
+/// style. Type annotations are not kept at run time, so they are left out.
+fn source(args: &[ObjectKind]) -> ObjectKind {
+    if let Err(e) = expect_args("source", args, 1) {
+        return error(e);
+    }
+    match args[0] {
+        ObjectKind::Function{ref parameters, ref body, ..} => {
+            let literal = ExpressionKind::FunctionLiteral{
+                token: token::Token { t_type: token::FUNCTION, literal: String::from("fun"), span: token::Span::default() },
+                parameters: parameters.clone(),
+                annotations: vec![None; parameters.len()],
+                return_type: None,
+                body: Box::new(body.clone())
+            };
+            string(&Printer::new(DEFAULT_WIDTH).print_expression(&literal))
+        },
+        ref other => error(format!("expected a function, got {}", other.type_name()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::lexer::Lexer;
+    use super::super::parser::Parser;
+    use super::super::resolver;
+    use super::super::evaluator::eval;
+    use super::super::environment::Environment;
+    use super::super::native;
+
+    fn run(input: &str) -> String {
+        let mut p = Parser::new(Lexer::new(String::from(input)));
+        let program = p.parse_program();
+        let mut env = Environment::new();
+        let globals: Vec<String> = env.store.keys().cloned().collect();
+        let program = resolver::resolve(program, &globals).expect("resolve error");
+        eval(program, &mut env).to_string()
+    }
+
+    #[test]
+    fn test_core_builtins() {
+        let tests = vec![
+            ("[len(\"hello\"), len([1, 2]), len({\"a\": 1}), len(\"\")]", "[5, 2, 1, 0]"),
+            ("len(1)", "len is not supported for integer"),
+            ("len([1], [2])", "len expects 1 argument, got 2"),
+            ("[type(1), type(Object), type(len)]", "[integer, object, builtin]"),
+            ("[type(str([1, \"a\"])), str([1, \"a\"])]", "[string, [1, a]]"),
+            ("[int(\"42\"), int(7), int(true)]", "[42, 7, 1]"),
+            ("int(\"4x\")", "cannot convert \"4x\" to an integer"),
+            ("[first([1, 2, 3]), last([1, 2, 3]), rest([1, 2, 3]), rest([1])]", "[1, 3, [2, 3], []]"),
+            ("[first([]), last([]), rest([])]", "[Null, Null, Null]"),
+            ("first(1)", "first expects an array, got integer"),
+            ("let a := [1]; let b := push(a, 2); [a, b]", "[[1], [1, 2]]"),
+            ("const a := [1]; push(a, 2)", "[1, 2]"),
+            ("[1, 2] |> push(3) |> len", "3"),
+            ("puts(1, 2)", "Null"),
+            ("let len := fun(x) { 0 }; len([1])", "0"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(run(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_register_builtin() {
+        fn answer(_: &[ObjectKind]) -> ObjectKind {
+            ObjectKind::Integer{slots: HashMap::new(), value: 42}
+        }
+        assert_eq!(register_builtin("answer", answer), Ok(()));
+        assert_eq!(run("answer() + 1"), "43");
+        assert_eq!(register_builtin("len", answer), Ok(()));
+        assert_eq!(run("len([])"), "42");
+        assert!(register_builtin("let", answer).is_err());
+        assert!(register_builtin("no-dash", answer).is_err());
+        assert!(register_builtin("_", answer).is_err());
+        assert_eq!(register_builtin("log2", answer), Ok(()));
+        assert_eq!(register_builtin("sha256", answer), Ok(()));
+        assert_eq!(run("log2() + sha256()"), "84");
+        assert!(register_builtin("2fa", answer).is_err());
+    }
+
+    #[test]
+    fn test_reflection() {
+        let tests = vec![
+            ("[type_of(1), type_of(\"a\"), type_of(null), type_of([]), type_of({}), type_of(fun() { 1 })]",
+             "[integer, string, null, array, hash, function]"),
+            ("struct Point { x, y } enum Shape { Empty } [type_of(Point(1, 2)), type_of(Shape.Empty), type_of(Point)]",
+             "[Point, Shape, struct type]"),
+            ("[type_of(Object.clone()), type_of(type_of)]", "[object, builtin]"),
+            ("let o := Object.clone(); o.b = 1; o.a = 2; slots(o)", "[a, b]"),
+            ("let n := 1; n.unit = 2; [slots(n), slots(2)]", "[[unit], []]"),
+            ("struct Point { y, x } [slots(Point(1, 2)), slots({\"k\": 1, 2: 3})]", "[[y, x], [k]]"),
+            ("enum Shape { Circle(r), Empty } slots(Shape)", "[Circle, Empty]"),
+            ("slots(true)", "boolean has no slots"),
+            ("let p := Object.clone(); p.a = 1; let c := p.clone(); [has_slot(c, \"a\"), has_slot(c, \"b\"), has_slot(1, \"a\")]",
+             "[true, false, false]"),
+            ("has_slot(Object, 1)", "has_slot expects a string slot name, got integer"),
+            ("[arity(fun(a, b) { a }), arity(fun() { 1 })]", "[2, 0]"),
+            ("struct Point { x, y } enum Shape { Rect(w, h) } [arity(Point), params(Shape.Rect)]", "[2, [w, h]]"),
+            ("params(fun(first, second) { first })", "[first, second]"),
+            ("params(1)", "expected a function, got integer"),
+            ("arity(slots)", "parameters of builtin slots are not known"),
+            ("arity()", "arity expects 1 argument, got 0"),
+            ("has_slot(1)", "has_slot expects 2 arguments, got 1"),
+            ("source(fun(a, b: int) { let c := a+b; c })", "fun(a, b) {\n    let c := a + b;\n    c;\n}"),
+            ("let slots := 1; slots", "1"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(run(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_reflection_errors() {
+        let tests = vec![
+            ("source(len)", "expected a function, got builtin"),
+            ("params(len)", "parameters of builtin len are not known"),
+            ("source(1)", "expected a function, got integer"),
+            ("struct Point { x } source(Point)", "expected a function, got struct type"),
+            ("enum Shape { Empty } params(Shape.Empty)", "expected a function, got variant"),
+            ("source()", "source expects 1 argument, got 0"),
+            ("params(fun() { 1 }, 2)", "params expects 1 argument, got 2"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(run(input), expected, "{}", input);
+        }
+        let twice = native::native("twice", |n: u32| Ok(n * 2));
+        assert_eq!(source(std::slice::from_ref(&twice)).to_string(), "expected a function, got native function");
+        assert_eq!(params(&[twice]).to_string(), "parameters of native function twice are not known");
+    }
+}
@@ -0,0 +1,665 @@
+//! The gradual type checker behind `lodestone check`.
+//!
+//! Annotations are taken at their word and everything else is inferred
+//! locally, from literals, operators and calls to functions whose signature is
+//! known. Anything that can't be inferred is `any`, which fits every type, so
+//! unannotated code is only reported when it could not run without an error.
+
+use std::fmt;
+
+use super::ast::NodeKind;
+use super::ast::ExpressionKind;
+use super::ast::StatementKind;
+use super::ast::TypeAnnotation;
+use super::token::Span;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Any,
+    Int,
+    Str,
+    Bool,
+    Null,
+    Array,
+    Hash,
+    /// `params` is `None` when the signature is unknown, as for a bare `fun`
+    /// annotation or a function with no annotated parameters.
+    Function{params: Option<Vec<Type>>, ret: Box<Type>}
+}
+
+impl Type {
+    /// The type an annotation names, if it is one.
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "any" => Some(Type::Any),
+            "int" => Some(Type::Int),
+            "string" => Some(Type::Str),
+            "bool" => Some(Type::Bool),
+            "null" => Some(Type::Null),
+            "array" => Some(Type::Array),
+            "hash" => Some(Type::Hash),
+            "fun" => Some(Type::Function{params: None, ret: Box::new(Type::Any)}),
+            _ => None
+        }
+    }
+
+    /// Whether a value of type `actual` may be used where `self` is expected.
+    pub fn accepts(&self, actual: &Type) -> bool {
+        match (self, actual) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Function{params: expected, ret: expected_ret}, Type::Function{params: actual_params, ret: actual_ret}) => {
+                let params_fit = match (expected, actual_params) {
+                    (Some(e), Some(a)) => e.len() == a.len() && e.iter().zip(a.iter()).all(|(e, a)| a.accepts(e)),
+                    _ => true
+                };
+                params_fit && expected_ret.accepts(actual_ret)
+            },
+            (e, a) => e == a
+        }
+    }
+
+    /// The type of a value that is either `self` or `other`.
+    fn join(self, other: Type) -> Type {
+        if self == other { self } else { Type::Any }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "any"),
+            Type::Int => write!(f, "int"),
+            Type::Str => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::Null => write!(f, "null"),
+            Type::Array => write!(f, "array"),
+            Type::Hash => write!(f, "hash"),
+            Type::Function{params: None, ..} => write!(f, "fun"),
+            Type::Function{params: Some(params), ret} => {
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                write!(f, "fun({}) -> {}", params.join(", "), ret)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)
+    }
+}
+
+/// Checks `program` against its annotations, in source order.
+pub fn check(program: &NodeKind) -> Vec<TypeError> {
+    let mut checker = Checker { scopes: vec![vec![]], functions: vec![], errors: vec![] };
+    match program {
+        NodeKind::ProgramNode{statements} => {
+            for s in statements {
+                checker.statement(s);
+            }
+        },
+        NodeKind::StatementNode{statementKind} => {
+            checker.statement(statementKind);
+        },
+        NodeKind::ExpressionNode{expressionKind} => {
+            checker.expression(expressionKind);
+        }
+    }
+    checker.errors
+}
+
+struct Variable {
+    name: String,
+    ty: Type,
+    /// Set when the variable was annotated; later `let`s must fit it.
+    declared: bool,
+}
+
+/// What is known about the function being checked.
+struct Function {
+    declared: Option<Type>,
+    returned: Option<Type>,
+}
+
+struct Checker {
+    /// One entry per frame, laid out like the resolver's scopes.
+    scopes: Vec<Vec<Variable>>,
+    functions: Vec<Function>,
+    errors: Vec<TypeError>,
+}
+
+impl Checker {
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(TypeError { span, message });
+    }
+
+    fn annotation(&mut self, annotation: &TypeAnnotation) -> Type {
+        match Type::from_name(&annotation.name) {
+            Some(t) => t,
+            None => {
+                self.error(annotation.token.span, format!("unknown type '{}'", annotation.name));
+                Type::Any
+            }
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Type {
+        for scope in self.scopes.iter().rev() {
+            if let Some(v) = scope.iter().find(|v| v.name == name) {
+                return v.ty.clone();
+            }
+        }
+        Type::Any
+    }
+
+    /// Binds a `let`. An annotated variable keeps its type for good; an
+    /// unannotated one that is given a value of another type becomes `any`,
+    /// since the checker doesn't follow which branch ran.
+    fn define(&mut self, name: &str, ty: Type, declared: bool, span: Span) {
+        let existing = self.scopes.last().unwrap().iter().position(|v| v.name == name);
+        match existing {
+            Some(i) if !declared => {
+                let (old, old_declared) = {
+                    let v = &self.scopes.last().unwrap()[i];
+                    (v.ty.clone(), v.declared)
+                };
+                if old_declared {
+                    if !old.accepts(&ty) {
+                        self.error(span, format!("cannot assign {} to '{}' of type {}", ty, name, old));
+                    }
+                } else {
+                    self.scopes.last_mut().unwrap()[i].ty = old.join(ty);
+                }
+            },
+            Some(i) => {
+                let scope = self.scopes.last_mut().unwrap();
+                scope[i].ty = ty;
+                scope[i].declared = true;
+            },
+            None => {
+                self.scopes.last_mut().unwrap().push(Variable { name: String::from(name), ty, declared });
+            }
+        }
+    }
+
+    fn returned(&mut self, ty: Type, span: Span) {
+        let declared = match self.functions.last_mut() {
+            Some(function) => {
+                function.returned = Some(match function.returned.take() {
+                    Some(t) => t.join(ty.clone()),
+                    None => ty.clone()
+                });
+                function.declared.clone()
+            },
+            None => None
+        };
+        match declared {
+            Some(ref d) if !d.accepts(&ty) => {
+                self.error(span, format!("expected return type {}, found {}", d, ty));
+            },
+            _ => {}
+        }
+    }
+
+    fn statement(&mut self, statement: &StatementKind) -> Type {
+        match statement {
+            StatementKind::LetStatement{name, annotation, value, ..} => {
+                let value_type = match value {
+                    Some(v) => self.expression(v),
+                    None => Type::Null
+                };
+                let (name, span) = match name {
+                    ExpressionKind::Identifier{value, token, ..} => (value.clone(), token.span),
+                    _ => return Type::Null
+                };
+                match annotation {
+                    Some(a) => {
+                        let declared = self.annotation(a);
+                        if !declared.accepts(&value_type) {
+                            self.error(span, format!("cannot assign {} to '{}' of type {}", value_type, name, declared));
+                        }
+                        self.define(&name, declared, true, span);
+                    },
+                    None => {
+                        self.define(&name, value_type, false, span);
+                    }
+                }
+                Type::Any
+            },
+            StatementKind::ReturnStatement{token, return_value} => {
+                let ty = match return_value {
+                    Some(rv) => self.statement(rv),
+                    None => Type::Null
+                };
+                let span = match return_value {
+                    Some(rv) => statement_span(rv),
+                    None => token.span
+                };
+                self.returned(ty, span);
+                Type::Any
+            },
+            StatementKind::ExpressionStatement{expression, ..} => {
+                match expression {
+                    Some(e) => self.expression(e),
+                    None => Type::Null
+                }
+            },
+            StatementKind::BlockStatement{statements, ..} => {
+                let mut ty = Type::Null;
+                for s in statements {
+                    ty = self.statement(s);
+                }
+                ty
+            },
+            StatementKind::StructStatement{name, fields, ..} => {
+                if let ExpressionKind::Identifier{value, token, ..} = name {
+                    let constructor = Type::Function{params: Some(vec![Type::Any; fields.len()]), ret: Box::new(Type::Any)};
+                    self.define(value, constructor, false, token.span);
+                }
+                Type::Any
+            },
+            StatementKind::EnumStatement{name, ..} => {
+                if let ExpressionKind::Identifier{value, token, ..} = name {
+                    self.define(value, Type::Any, false, token.span);
+                }
+                Type::Any
+            },
+            StatementKind::AssignStatement{target, value, ..} => {
+                self.expression(target);
+                self.expression(value)
+            },
+            StatementKind::DeleteStatement{target, ..} => {
+                self.expression(target);
+                Type::Null
+            },
+            StatementKind::ImportStatement{name, ..} => {
+                if let ExpressionKind::Identifier{value, token, ..} = name {
+                    self.define(value, Type::Any, false, token.span);
+                }
+                Type::Any
+            },
+            StatementKind::ExportStatement{statement, ..} => {
+                self.statement(statement)
+            }
+        }
+    }
+
+    fn expression(&mut self, expression: &ExpressionKind) -> Type {
+        match expression {
+            ExpressionKind::Identifier{value, ..} => self.lookup(value),
+            ExpressionKind::IntegerLiteral{..} => Type::Int,
+            ExpressionKind::StringLiteral{..} => Type::Str,
+            ExpressionKind::BooleanExpression{..} => Type::Bool,
+            ExpressionKind::NullLiteral{..} => Type::Null,
+            ExpressionKind::PrefixExpression{token, operator, right} => {
+                let right = match right {
+                    Some(r) => self.expression(r),
+                    None => Type::Any
+                };
+                match operator.as_ref() {
+                    "!" => Type::Bool,
+                    "-" => {
+                        if !Type::Int.accepts(&right) {
+                            self.error(token.span, format!("operator - cannot be applied to {}", right));
+                        }
+                        Type::Int
+                    },
+                    _ => Type::Any
+                }
+            },
+            ExpressionKind::InfixExpression{token, left, operator, right} => {
+                if operator == "|>" {
+                    return self.pipeline(left, right);
+                }
+                let left = match left {
+                    Some(l) => self.expression(l),
+                    None => Type::Any
+                };
+                let right = match right {
+                    Some(r) => self.expression(r),
+                    None => Type::Any
+                };
+                self.infix(token.span, operator, left, right)
+            },
+            ExpressionKind::IfExpression{condition, consequence, alternative, ..} => {
+                if let Some(c) = condition {
+                    self.expression(c);
+                }
+                let consequence = match consequence {
+                    Some(c) => self.statement(c),
+                    None => Type::Null
+                };
+                let alternative = match alternative {
+                    Some(a) => self.statement(a),
+                    None => Type::Null
+                };
+                consequence.join(alternative)
+            },
+            ExpressionKind::FunctionLiteral{parameters, annotations, return_type, body, ..} => {
+                let mut frame = vec![];
+                let mut params = vec![];
+                for (i, p) in parameters.iter().enumerate() {
+                    let ty = match annotations.get(i) {
+                        Some(Some(a)) => self.annotation(a),
+                        _ => Type::Any
+                    };
+                    if let ExpressionKind::Identifier{value, ..} = p {
+                        frame.push(Variable { name: value.clone(), ty: ty.clone(), declared: true });
+                    }
+                    params.push(ty);
+                }
+                let declared = return_type.as_ref().map(|r| self.annotation(r));
+                self.scopes.push(frame);
+                self.functions.push(Function { declared: declared.clone(), returned: None });
+                let result = self.statement(body);
+                // A body ending in `return` has already been checked.
+                match last_statement(body) {
+                    Some(&StatementKind::ReturnStatement{..}) => {},
+                    Some(last) => {
+                        self.returned(result, statement_span(last));
+                        if may_end_in_null(body) {
+                            self.returned(Type::Null, statement_span(last));
+                        }
+                    },
+                    None => {
+                        self.returned(Type::Null, statement_span(body));
+                    }
+                }
+                let function = self.functions.pop().unwrap();
+                self.scopes.pop();
+                let annotated = annotations.iter().any(|a| a.is_some());
+                Type::Function{
+                    params: if annotated { Some(params) } else { None },
+                    ret: Box::new(declared.or(function.returned).unwrap_or(Type::Any))
+                }
+            },
+            ExpressionKind::CallExpression{token, function, arguments} => {
+                let callee = self.expression(function);
+                let arguments: Vec<(Type, Span)> = arguments.iter().map(|a| (self.expression(a), expression_span(a))).collect();
+                self.call(token.span, callee, arguments)
+            },
+            ExpressionKind::WhileLiteral{condition, consequence, ..} => {
+                self.expression(condition);
+                self.statement(consequence);
+                Type::Any
+            },
+            ExpressionKind::ArrayLiteral{elements, ..} => {
+                for e in elements {
+                    self.expression(e);
+                }
+                Type::Array
+            },
+            ExpressionKind::HashLiteral{pairs, ..} => {
+                for (k, v) in pairs {
+                    self.expression(k);
+                    self.expression(v);
+                }
+                Type::Hash
+            },
+            ExpressionKind::IndexExpression{token, left, index, optional} => {
+                let left_type = self.expression(left);
+                let index_type = match index {
+                    Some(i) => self.expression(i),
+                    None => Type::Any
+                };
+                match left_type {
+                    Type::Null if *optional => {},
+                    Type::Any | Type::Array | Type::Hash => {},
+                    ref t => {
+                        self.error(token.span, format!("cannot index {}", t));
+                    }
+                }
+                if left_type == Type::Array && !Type::Int.accepts(&index_type) {
+                    self.error(token.span, format!("array index must be int, found {}", index_type));
+                }
+                Type::Any
+            },
+            ExpressionKind::MatchExpression{subject, arms, ..} => {
+                self.expression(subject);
+                let mut result: Option<Type> = None;
+                for arm in arms {
+                    let frame = arm.pattern.binding_names().into_iter().map(|name| {
+                        Variable { name, ty: Type::Any, declared: false }
+                    }).collect();
+                    self.scopes.push(frame);
+                    if let Some(ref g) = arm.guard {
+                        self.expression(g);
+                    }
+                    let body = self.expression(&arm.body);
+                    self.scopes.pop();
+                    result = Some(match result {
+                        Some(t) => t.join(body),
+                        None => body
+                    });
+                }
+                result.unwrap_or(Type::Null)
+            },
+            ExpressionKind::SlotExpression{left, ..} => {
+                self.expression(left);
+                Type::Any
+            }
+        }
+    }
+
+    fn infix(&mut self, span: Span, operator: &str, left: Type, right: Type) -> Type {
+        let mismatch = |left: &Type, right: &Type| format!("operator {} cannot be applied to {} and {}", operator, left, right);
+        match operator {
+            "+" => {
+                match (&left, &right) {
+                    (Type::Int, Type::Int) => Type::Int,
+                    (Type::Str, Type::Str) => Type::Str,
+                    (Type::Any, t) | (t, Type::Any) if *t == Type::Any || *t == Type::Int || *t == Type::Str => Type::Any,
+                    _ => {
+                        self.error(span, mismatch(&left, &right));
+                        Type::Any
+                    }
+                }
+            },
+            "-" | "*" | "/" | "%" | "<" | ">" => {
+                if !Type::Int.accepts(&left) || !Type::Int.accepts(&right) {
+                    self.error(span, mismatch(&left, &right));
+                }
+                if operator == "<" || operator == ">" { Type::Bool } else { Type::Int }
+            },
+            "==" | "!=" => Type::Bool,
+            "??" => {
+                match left {
+                    Type::Null => right,
+                    Type::Any => Type::Any,
+                    known => known
+                }
+            },
+            _ => Type::Any
+        }
+    }
+
+    /// `x |> f(a)` calls `f(x, a)`, and `x |> f` calls `f(x)`.
+    fn pipeline(&mut self, left: &Option<Box<ExpressionKind>>, right: &Option<Box<ExpressionKind>>) -> Type {
+        let mut arguments = vec![];
+        if let Some(l) = left {
+            let ty = self.expression(l);
+            arguments.push((ty, expression_span(l)));
+        }
+        match right {
+            Some(r) => {
+                match **r {
+                    ExpressionKind::CallExpression{ref token, ref function, arguments: ref rest} => {
+                        let callee = self.expression(function);
+                        for a in rest {
+                            let ty = self.expression(a);
+                            arguments.push((ty, expression_span(a)));
+                        }
+                        self.call(token.span, callee, arguments)
+                    },
+                    ref other => {
+                        let callee = self.expression(other);
+                        self.call(expression_span(other), callee, arguments)
+                    }
+                }
+            },
+            None => Type::Any
+        }
+    }
+
+    fn call(&mut self, span: Span, callee: Type, arguments: Vec<(Type, Span)>) -> Type {
+        match callee {
+            Type::Any => Type::Any,
+            Type::Function{params, ret} => {
+                if let Some(params) = params {
+                    if params.len() != arguments.len() {
+                        self.error(span, format!("expected {} arguments, found {}", params.len(), arguments.len()));
+                    }
+                    for (i, (param, &(ref arg, arg_span))) in params.iter().zip(arguments.iter()).enumerate() {
+                        if !param.accepts(arg) {
+                            self.error(arg_span, format!("argument {} expects {}, found {}", i + 1, param, arg));
+                        }
+                    }
+                }
+                *ret
+            },
+            other => {
+                self.error(span, format!("cannot call a value of type {}", other));
+                Type::Any
+            }
+        }
+    }
+}
+
+fn last_statement(block: &StatementKind) -> Option<&StatementKind> {
+    match block {
+        StatementKind::BlockStatement{statements, ..} => statements.last().map(|s| &**s),
+        other => Some(other)
+    }
+}
+
+/// Whether `block` can finish without a value: it is empty, or it ends in an
+/// `if` that has no `else` or has a branch that can.
+fn may_end_in_null(block: &StatementKind) -> bool {
+    match last_statement(block) {
+        None => true,
+        Some(StatementKind::ExpressionStatement{expression: Some(e), ..}) => {
+            match **e {
+                ExpressionKind::IfExpression{ref consequence, ref alternative, ..} => {
+                    consequence.as_ref().is_none_or(|c| may_end_in_null(c)) || alternative.as_ref().is_none_or(|a| may_end_in_null(a))
+                },
+                _ => false
+            }
+        },
+        Some(_) => false
+    }
+}
+
+/// Where to report the value `statement` produces: its expression, if it has one.
+fn statement_span(statement: &StatementKind) -> Span {
+    match statement {
+        StatementKind::ExpressionStatement{expression: Some(e), ..} => expression_span(e),
+        StatementKind::LetStatement{token, ..} |
+        StatementKind::ReturnStatement{token, ..} |
+        StatementKind::ExpressionStatement{token, ..} |
+        StatementKind::BlockStatement{token, ..} |
+        StatementKind::StructStatement{token, ..} |
+        StatementKind::EnumStatement{token, ..} |
+        StatementKind::AssignStatement{token, ..} |
+        StatementKind::DeleteStatement{token, ..} |
+        StatementKind::ImportStatement{token, ..} |
+        StatementKind::ExportStatement{token, ..} => token.span
+    }
+}
+
+fn expression_span(expression: &ExpressionKind) -> Span {
+    match expression {
+        ExpressionKind::Identifier{token, ..} |
+        ExpressionKind::PrefixExpression{token, ..} |
+        ExpressionKind::InfixExpression{token, ..} |
+        ExpressionKind::BooleanExpression{token, ..} |
+        ExpressionKind::IfExpression{token, ..} |
+        ExpressionKind::FunctionLiteral{token, ..} |
+        ExpressionKind::CallExpression{token, ..} |
+        ExpressionKind::StringLiteral{token, ..} |
+        ExpressionKind::IntegerLiteral{token, ..} |
+        ExpressionKind::WhileLiteral{token, ..} |
+        ExpressionKind::ArrayLiteral{token, ..} |
+        ExpressionKind::IndexExpression{token, ..} |
+        ExpressionKind::HashLiteral{token, ..} |
+        ExpressionKind::MatchExpression{token, ..} |
+        ExpressionKind::NullLiteral{token} |
+        ExpressionKind::SlotExpression{token, ..} => token.span
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::lexer::Lexer;
+    use super::super::parser::Parser;
+
+    fn errors(input: &str) -> Vec<String> {
+        let mut p = Parser::new(Lexer::new(String::from(input)));
+        let program = p.parse_program();
+        assert!(p.errors.is_empty(), "parse errors in {:?}", input);
+        check(&program).iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn test_annotated_code() {
+        assert_eq!(errors("let n: int := 1; let s: string := n;"), vec!["1:22: cannot assign int to 's' of type string"]);
+        assert_eq!(errors("let n: int := 1; let n := \"one\";"), vec!["1:22: cannot assign string to 'n' of type int"]);
+        assert_eq!(errors("let f := fun(a: int, b: string) -> bool { a > 1 }; f(1, 2); f(1)"), vec![
+            "1:57: argument 2 expects string, found int",
+            "1:64: expected 2 arguments, found 1",
+        ]);
+        assert_eq!(errors("fun(a: int) -> string { if (a > 1) { return a; }; \"small\" }"), vec![
+            "1:45: expected return type string, found int",
+        ]);
+        assert_eq!(errors("let x: integer := 1;"), vec!["1:8: unknown type 'integer'"]);
+    }
+
+    #[test]
+    fn test_bodies_that_can_return_null() {
+        assert_eq!(errors("fun(a: int) -> int { }"), vec!["1:20: expected return type int, found null"]);
+        assert_eq!(errors("fun(a: int) -> int { if (a > 1) { a } }"), vec!["1:22: expected return type int, found null"]);
+        assert_eq!(errors("fun(a: int) -> int { if (a > 1) { a } else { if (a > 0) { 1 } } }"), vec![
+            "1:22: expected return type int, found null",
+        ]);
+        assert_eq!(errors("fun(a: int) -> int { if (a > 1) { a } else { } }"), vec!["1:22: expected return type int, found null"]);
+        assert_eq!(errors("fun(a: int) -> int { if (a > 1) { return a; }; 0 }"), Vec::<String>::new());
+        assert_eq!(errors("fun(a: int) -> int { if (a > 1) { a } else { 0 } }"), Vec::<String>::new());
+        assert_eq!(errors("fun(a: int) -> null { } ; fun(a: int) -> any { if (a > 1) { a } }; fun(a) { }"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_local_inference() {
+        assert_eq!(errors("let f := fun(a: int) -> int { a * 2 }; let g := f; g(1) + \"x\""), vec![
+            "1:57: operator + cannot be applied to int and string",
+        ]);
+        assert_eq!(errors("let k := fun(a: int) { a }; \"s\" |> k"), vec!["1:29: argument 1 expects int, found string"]);
+        assert_eq!(errors("let n := 5; n()"), vec!["1:15: cannot call a value of type int"]);
+        assert_eq!(errors("let h: hash := {}; let a: array := h;"), vec!["1:24: cannot assign hash to 'a' of type array"]);
+    }
+
+    #[test]
+    fn test_unannotated_code_stays_dynamic() {
+        let input = "let x := 1; let x := \"now a string\"; x + \"!\";
+let f := fun(a, b) { a + b }; f(1); f(\"a\", \"b\", \"c\");
+let pick := fun(c) { if (c) { 1 } else { \"one\" } }; pick(true) + 1;
+match [1] { [n] => n + 1, _ => 0 };";
+        assert_eq!(errors(input), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_function_types() {
+        let f = Type::Function{params: Some(vec![Type::Int]), ret: Box::new(Type::Bool)};
+        assert_eq!(f.to_string(), "fun(int) -> bool");
+        assert!(Type::from_name("fun").unwrap().accepts(&f));
+        assert!(!Type::Function{params: Some(vec![Type::Str]), ret: Box::new(Type::Any)}.accepts(&f));
+        assert_eq!(errors("let p: fun := fun(a: int) { a }; let q: int := p;"), vec![
+            "1:38: cannot assign fun to 'q' of type int",
+        ]);
+    }
+}
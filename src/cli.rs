@@ -0,0 +1,357 @@
+use std::fs;
+use std::io::Write;
+use std::io;
+use std::path::Path;
+
+use super::ast_json;
+use super::check;
+use super::lint;
+use super::optimize;
+use super::resolver;
+use super::evaluator;
+use super::environment::Environment;
+use super::object::ObjectKind;
+use super::parser::Parser;
+use super::lexer::Lexer;
+use super::printer;
+use super::package::{self, Packages, PackageResolver};
+
+const USAGE: &str = "usage:
+    lodestone                       start the REPL
+    lodestone run [--optimize] FILE  run a program and print its result
+    lodestone run [--locked]         run the package in the working directory
+    lodestone run --dump-optimized FILE
+                                     print the optimized program instead
+    lodestone lock                   write lodestone.lock for the package
+    lodestone fmt [--check] FILE...  format files in place
+    lodestone lint [--config FILE] FILE...
+                                     report likely mistakes
+    lodestone check FILE...          type-check annotated code
+    lodestone ast --json FILE        print the syntax tree as JSON
+    lodestone ast --from-json FILE   print source for a JSON syntax tree";
+
+/// Runs a `lodestone` subcommand and returns the process exit code.
+pub fn run(args: Vec<String>) -> i32 {
+    match args[0].as_ref() {
+        "run" => {
+            run_file(&args[1..])
+        },
+        "fmt" => {
+            fmt(&args[1..])
+        },
+        "lint" => {
+            lint(&args[1..])
+        },
+        "check" => {
+            check_files(&args[1..])
+        },
+        "ast" => {
+            ast(&args[1..])
+        },
+        "lock" => {
+            lock()
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    }
+}
+
+/// `lodestone run [--optimize] [--dump-optimized] [--locked] [FILE]`. The
+/// program is resolved before it runs, so undefined names are reported up
+/// front. In a package, FILE defaults to the manifest's entry point, imports
+/// can name dependencies, and `lodestone.lock` is brought up to date first;
+/// with `--locked` an out-of-date lockfile is an error instead.
+fn run_file(args: &[String]) -> i32 {
+    let dump = args.iter().any(|a| a == "--dump-optimized");
+    let optimized = dump || args.iter().any(|a| a == "--optimize" || a == "-O");
+    let locked = args.iter().any(|a| a == "--locked");
+    let files: Vec<&String> = args.iter().filter(|a| !a.starts_with("-")).collect();
+    let packages = if Path::new(package::MANIFEST_FILE).exists() {
+        match Packages::load(".") {
+            Ok(packages) => Some(packages),
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        }
+    } else {
+        None
+    };
+    let file = match (files.len(), &packages) {
+        (1, _) => files[0].clone(),
+        (0, Some(packages)) => packages.entry(),
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    if let Some(ref packages) = packages {
+        if let Err(e) = update_lockfile(packages, locked) {
+            eprintln!("{}", e);
+            return 1;
+        }
+    }
+    let source = match fs::read_to_string(&file) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}: {}", file, e);
+            return 1;
+        }
+    };
+    let mut p = Parser::new(Lexer::new(source));
+    let program = p.parse_program();
+    if !p.errors.is_empty() {
+        for error in &p.errors {
+            eprintln!("{}:{}", file, error);
+        }
+        return 1;
+    }
+    let mut env = Environment::new();
+    if let Some(packages) = packages {
+        env.modules.borrow_mut().set_resolver(Box::new(PackageResolver::new(packages)));
+    }
+    // Imports in the file are relative to it.
+    env.modules.borrow_mut().enter(&file).unwrap();
+    let globals: Vec<String> = env.store.keys().cloned().collect();
+    let mut program = match resolver::resolve(program, &globals) {
+        Ok(program) => program,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}:{}", file, error);
+            }
+            return 1;
+        }
+    };
+    if optimized {
+        program = optimize::optimize(program);
+    }
+    if dump {
+        print!("{}", printer::Printer::new(printer::DEFAULT_WIDTH).print_program(&program));
+        return 0;
+    }
+    match evaluator::eval(program, &mut env) {
+        ObjectKind::Error{message} => {
+            eprintln!("{}: error: {}", file, message);
+            1
+        },
+        ObjectKind::Null => 0,
+        value => {
+            println!("{}", value);
+            0
+        }
+    }
+}
+
+/// Writes `lodestone.lock` unless it already describes `packages`. With
+/// `locked`, a lockfile that would change is an error.
+fn update_lockfile(packages: &Packages, locked: bool) -> Result<(), String> {
+    let lock = packages.lockfile()?;
+    if fs::read_to_string(package::LOCK_FILE).ok().as_ref() == Some(&lock) {
+        return Ok(());
+    }
+    if locked {
+        return Err(format!("{} is out of date; run `lodestone lock`", package::LOCK_FILE));
+    }
+    fs::write(package::LOCK_FILE, lock).map_err(|e| format!("{}: {}", package::LOCK_FILE, e))
+}
+
+/// `lodestone lock`: records the dependencies of the package in the
+/// working directory in `lodestone.lock`.
+fn lock() -> i32 {
+    let result = Packages::load(".").and_then(|packages| update_lockfile(&packages, false));
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// `lodestone fmt [--check] FILE...`. With `--check` nothing is written and
+/// the exit code is 1 if any file is not already formatted.
+fn fmt(args: &[String]) -> i32 {
+    let check = args.iter().any(|a| a == "--check");
+    let files: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    if files.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+    let mut status = 0;
+    for file in files {
+        let source = match fs::read_to_string(file) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                status = 1;
+                continue;
+            }
+        };
+        match printer::format_source(&source, printer::DEFAULT_WIDTH) {
+            Ok(formatted) => {
+                if formatted == source {
+                    continue;
+                }
+                if check {
+                    println!("{} is not formatted", file);
+                    status = 1;
+                } else {
+                    match fs::write(file, formatted) {
+                        Ok(_) => {},
+                        Err(e) => {
+                            eprintln!("{}: {}", file, e);
+                            status = 1;
+                        }
+                    }
+                }
+            },
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("{}:{}", file, error);
+                }
+                status = 1;
+            }
+        }
+    }
+    io::stdout().flush().ok();
+    status
+}
+
+/// `lodestone lint [--config FILE] FILE...`. Without `--config`, rules are
+/// read from `lodestone-lint.toml` in the working directory when it exists.
+/// The exit code is 1 if anything was reported.
+fn lint(args: &[String]) -> i32 {
+    let mut config_path = None;
+    let mut files = vec![];
+    let mut i = 0;
+    while i < args.len() {
+        if args[i] == "--config" && i + 1 < args.len() {
+            config_path = Some(args[i + 1].clone());
+            i += 2;
+            continue;
+        }
+        files.push(&args[i]);
+        i += 1;
+    }
+    if files.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+    let config_path = match config_path {
+        Some(p) => Some(p),
+        None if Path::new(lint::CONFIG_FILE).exists() => Some(String::from(lint::CONFIG_FILE)),
+        None => None
+    };
+    let config = match config_path {
+        Some(p) => {
+            let parsed = fs::read_to_string(&p).map_err(|e| e.to_string()).and_then(|text| lint::LintConfig::parse(&text));
+            match parsed {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("{}: {}", p, e);
+                    return 2;
+                }
+            }
+        },
+        None => lint::LintConfig::new()
+    };
+    let mut status = 0;
+    for file in files {
+        let source = match fs::read_to_string(file) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                status = 1;
+                continue;
+            }
+        };
+        let mut p = Parser::new(Lexer::new(source));
+        let program = p.parse_program();
+        if !p.errors.is_empty() {
+            for error in &p.errors {
+                eprintln!("{}:{}", file, error);
+            }
+            status = 1;
+            continue;
+        }
+        for diagnostic in lint::lint(&program, p.comments(), &config) {
+            println!("{}:{}", file, diagnostic);
+            status = 1;
+        }
+    }
+    status
+}
+
+/// `lodestone check FILE...`. The exit code is 1 if any file has a type error.
+fn check_files(files: &[String]) -> i32 {
+    if files.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+    let mut status = 0;
+    for file in files {
+        let source = match fs::read_to_string(file) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                status = 1;
+                continue;
+            }
+        };
+        let mut p = Parser::new(Lexer::new(source));
+        let program = p.parse_program();
+        if !p.errors.is_empty() {
+            for error in &p.errors {
+                eprintln!("{}:{}", file, error);
+            }
+            status = 1;
+            continue;
+        }
+        for error in check::check(&program) {
+            println!("{}:{}", file, error);
+            status = 1;
+        }
+    }
+    status
+}
+
+/// `lodestone ast --json FILE` dumps the tree described in `docs/ast-json.md`;
+/// `lodestone ast --from-json FILE` turns such a dump back into source.
+fn ast(args: &[String]) -> i32 {
+    if args.len() != 2 || (args[0] != "--json" && args[0] != "--from-json") {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+    let file = &args[1];
+    let source = match fs::read_to_string(file) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}: {}", file, e);
+            return 1;
+        }
+    };
+    if args[0] == "--from-json" {
+        return match ast_json::from_json_str(&source) {
+            Ok(program) => {
+                print!("{}", printer::Printer::new(printer::DEFAULT_WIDTH).print_program(&program));
+                0
+            },
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                1
+            }
+        };
+    }
+    let mut p = Parser::new(Lexer::new(source));
+    let program = p.parse_program();
+    if !p.errors.is_empty() {
+        for error in p.errors {
+            eprintln!("{}:{}", file, error);
+        }
+        return 1;
+    }
+    println!("{}", ast_json::to_json_string(&program));
+    0
+}
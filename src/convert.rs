@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use super::object::{ObjectKind, HashKey};
+
+/// Rust values that can be handed to a Lodestone program.
+pub trait ToLodestone {
+    fn to_lodestone(self) -> ObjectKind;
+}
+
+/// Rust values that can be read back out of a Lodestone value. The error
+/// names the Lodestone type that was found instead.
+pub trait FromLodestone: Sized {
+    fn from_lodestone(value: &ObjectKind) -> Result<Self, String>;
+}
+
+fn mismatch(expected: &str, found: &ObjectKind) -> String {
+    format!("expected {}, found {}", expected, found.type_name())
+}
+
+impl ToLodestone for ObjectKind {
+    fn to_lodestone(self) -> ObjectKind {
+        self
+    }
+}
+
+impl ToLodestone for u32 {
+    fn to_lodestone(self) -> ObjectKind {
+        ObjectKind::Integer{slots: HashMap::new(), value: self}
+    }
+}
+
+impl ToLodestone for bool {
+    fn to_lodestone(self) -> ObjectKind {
+        ObjectKind::Boolean{value: self}
+    }
+}
+
+impl ToLodestone for String {
+    fn to_lodestone(self) -> ObjectKind {
+        ObjectKind::StringObj{slots: HashMap::new(), value: self}
+    }
+}
+
+impl ToLodestone for &str {
+    fn to_lodestone(self) -> ObjectKind {
+        self.to_string().to_lodestone()
+    }
+}
+
+impl ToLodestone for () {
+    fn to_lodestone(self) -> ObjectKind {
+        ObjectKind::Null
+    }
+}
+
+impl<T: ToLodestone> ToLodestone for Option<T> {
+    fn to_lodestone(self) -> ObjectKind {
+        match self {
+            Some(value) => value.to_lodestone(),
+            None => ObjectKind::Null
+        }
+    }
+}
+
+impl<T: ToLodestone> ToLodestone for Vec<T> {
+    fn to_lodestone(self) -> ObjectKind {
+        let elements = self.into_iter().map(ToLodestone::to_lodestone).collect();
+        ObjectKind::Array{slots: HashMap::new(), elements, frozen: false}
+    }
+}
+
+impl<V: ToLodestone> ToLodestone for HashMap<String, V> {
+    fn to_lodestone(self) -> ObjectKind {
+        let mut pairs = HashMap::new();
+        for (key, value) in self {
+            let hash_key = HashKey::StringObj{value: key.clone()};
+            pairs.insert(hash_key, (key.to_lodestone(), value.to_lodestone()));
+        }
+        ObjectKind::Hash{pairs, frozen: false}
+    }
+}
+
+impl FromLodestone for ObjectKind {
+    fn from_lodestone(value: &ObjectKind) -> Result<ObjectKind, String> {
+        Ok(value.clone())
+    }
+}
+
+impl FromLodestone for u32 {
+    fn from_lodestone(value: &ObjectKind) -> Result<u32, String> {
+        match value {
+            ObjectKind::Integer{value, ..} => Ok(*value),
+            other => Err(mismatch("integer", other))
+        }
+    }
+}
+
+impl FromLodestone for i64 {
+    fn from_lodestone(value: &ObjectKind) -> Result<i64, String> {
+        u32::from_lodestone(value).map(i64::from)
+    }
+}
+
+impl FromLodestone for usize {
+    fn from_lodestone(value: &ObjectKind) -> Result<usize, String> {
+        u32::from_lodestone(value).map(|v| v as usize)
+    }
+}
+
+impl FromLodestone for bool {
+    fn from_lodestone(value: &ObjectKind) -> Result<bool, String> {
+        match value {
+            ObjectKind::Boolean{value} => Ok(*value),
+            other => Err(mismatch("boolean", other))
+        }
+    }
+}
+
+impl FromLodestone for String {
+    fn from_lodestone(value: &ObjectKind) -> Result<String, String> {
+        match value {
+            ObjectKind::StringObj{value, ..} => Ok(value.clone()),
+            other => Err(mismatch("string", other))
+        }
+    }
+}
+
+impl FromLodestone for () {
+    fn from_lodestone(value: &ObjectKind) -> Result<(), String> {
+        match value {
+            ObjectKind::Null => Ok(()),
+            other => Err(mismatch("null", other))
+        }
+    }
+}
+
+impl<T: FromLodestone> FromLodestone for Option<T> {
+    fn from_lodestone(value: &ObjectKind) -> Result<Option<T>, String> {
+        match value {
+            ObjectKind::Null => Ok(None),
+            other => T::from_lodestone(other).map(Some)
+        }
+    }
+}
+
+impl<T: FromLodestone> FromLodestone for Vec<T> {
+    fn from_lodestone(value: &ObjectKind) -> Result<Vec<T>, String> {
+        match value {
+            ObjectKind::Array{elements, ..} => elements.iter().map(T::from_lodestone).collect(),
+            other => Err(mismatch("array", other))
+        }
+    }
+}
+
+impl<K: FromLodestone + Eq + Hash, V: FromLodestone> FromLodestone for HashMap<K, V> {
+    fn from_lodestone(value: &ObjectKind) -> Result<HashMap<K, V>, String> {
+        match value {
+            ObjectKind::Hash{pairs, ..} => {
+                let mut map = HashMap::new();
+                for (key, value) in pairs.values() {
+                    map.insert(K::from_lodestone(key)?, V::from_lodestone(value)?);
+                }
+                Ok(map)
+            },
+            other => Err(mismatch("hash", other))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{ToLodestone, FromLodestone};
+    use super::super::object::ObjectKind;
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(u32::from_lodestone(&7u32.to_lodestone()), Ok(7));
+        assert_eq!(i64::from_lodestone(&7u32.to_lodestone()), Ok(7));
+        assert_eq!(bool::from_lodestone(&true.to_lodestone()), Ok(true));
+        assert_eq!(String::from_lodestone(&"hi".to_lodestone()), Ok("hi".to_string()));
+        assert_eq!(<()>::from_lodestone(&().to_lodestone()), Ok(()));
+        assert_eq!(Option::<u32>::from_lodestone(&None::<u32>.to_lodestone()), Ok(None));
+        assert_eq!(Option::<u32>::from_lodestone(&Some(3u32).to_lodestone()), Ok(Some(3)));
+        assert_eq!(Vec::<u32>::from_lodestone(&vec![1u32, 2, 3].to_lodestone()), Ok(vec![1, 2, 3]));
+
+        let mut scores = HashMap::new();
+        scores.insert("ada".to_string(), 3);
+        scores.insert("bob".to_string(), 5u32);
+        assert_eq!(HashMap::<String, u32>::from_lodestone(&scores.clone().to_lodestone()), Ok(scores));
+    }
+
+    #[test]
+    fn test_mismatch() {
+        assert_eq!(u32::from_lodestone(&"7".to_lodestone()), Err("expected integer, found string".to_string()));
+        assert_eq!(Vec::<bool>::from_lodestone(&vec![true].to_lodestone()), Ok(vec![true]));
+        assert_eq!(Vec::<bool>::from_lodestone(&vec![1u32].to_lodestone()), Err("expected boolean, found integer".to_string()));
+        assert_eq!(String::from_lodestone(&ObjectKind::Null), Err("expected string, found null".to_string()));
+    }
+}
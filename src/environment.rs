@@ -1,14 +1,46 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::rc::Rc;
 
+use super::ast::Binding;
 use super::object::ObjectKind;
+use super::module::{Loader, FileResolver};
 
+/// Globals live in `store` by name. Each function call and match arm pushes a
+/// frame whose slots are laid out by the resolver, so resolved identifiers
+/// index straight into them. Frames keep their names for unresolved code.
 #[derive(Clone)]
 pub struct Environment {
-    pub store: HashMap<String, ObjectKind>
+    pub store: HashMap<String, ObjectKind>,
+    pub frames: Vec<Vec<(String, ObjectKind)>>,
+    /// Each `const` as the number of frames it was declared under (0 for
+    /// globals) and its name.
+    pub constants: HashSet<(usize, String)>,
+    /// Shared by every environment cloned or run from this one, so a module
+    /// is loaded once no matter where it is imported.
+    pub modules: Rc<RefCell<Loader>>
 }
 
 impl Environment {
+    /// A fresh environment. The only predefined global is `Object`, the
+    /// root prototype that other objects are cloned from.
+    pub fn new() -> Environment {
+        Environment::with_modules(Rc::new(RefCell::new(Loader::new(Box::new(FileResolver::new("."))))))
+    }
+
+    /// A fresh environment that loads modules through `modules`.
+    pub fn with_modules(modules: Rc<RefCell<Loader>>) -> Environment {
+        let mut store = HashMap::new();
+        store.insert(String::from("Object"), ObjectKind::Object{slots: Rc::new(RefCell::new(HashMap::new())), parent: None, frozen: false});
+        Environment { store, frames: vec![], constants: HashSet::new(), modules }
+    }
     pub fn get(&mut self, key: String) -> ObjectKind {
+        for frame in self.frames.iter().rev() {
+            if let Some((_, v)) = frame.iter().rev().find(|&(name, _)| *name == key) {
+                return v.clone();
+            }
+        }
         match self.store.get(&key) {
             Some(v) => {
                 v.clone()
@@ -24,4 +56,118 @@ impl Environment {
     pub fn insert(&mut self, key: String, value: ObjectKind) {
         self.store.insert(key, value);
     }
-}
\ No newline at end of file
+    pub fn push_frame(&mut self, frame: Vec<(String, ObjectKind)>) {
+        self.frames.push(frame);
+    }
+    /// Reads a variable the way the resolver bound it.
+    pub fn lookup(&mut self, key: String, binding: Binding) -> ObjectKind {
+        match binding {
+            Binding::Local{depth, slot} if depth < self.frames.len() => {
+                let index = self.frames.len() - 1 - depth;
+                match self.frames[index].get(slot) {
+                    Some((_, v)) => v.clone(),
+                    None => ObjectKind::Error{message: format!("unbound variable {}", key)}
+                }
+            },
+            Binding::Global => {
+                match self.store.get(&key) {
+                    Some(v) => v.clone(),
+                    None => ObjectKind::Error{message: format!("unbound variable {}", key)}
+                }
+            },
+            _ => {
+                self.get(key)
+            }
+        }
+    }
+    /// Binds a `let` in the innermost frame, or as a global at the top level.
+    /// Fails if that frame already has a constant with the same name.
+    pub fn define(&mut self, key: String, binding: Binding, value: ObjectKind) -> Result<(), String> {
+        if self.constants.contains(&(self.frames.len(), key.clone())) {
+            return Err(format!("cannot reassign constant {}", key));
+        }
+        self.bind(key, binding, value);
+        Ok(())
+    }
+
+    /// Binds a `const`. Arrays and hashes are frozen all the way down.
+    pub fn define_constant(&mut self, key: String, binding: Binding, value: ObjectKind) -> Result<(), String> {
+        self.define(key.clone(), binding, value.freeze())?;
+        self.constants.insert((self.frames.len(), key));
+        Ok(())
+    }
+
+    pub fn is_constant(&self, key: &str, binding: Binding) -> bool {
+        match self.locate(key, binding) {
+            Some((depth, _)) => self.constants.contains(&(depth, String::from(key))),
+            None => false
+        }
+    }
+
+    /// Replaces the value of a variable that is already bound, wherever it
+    /// lives. Slot writes use this to store the updated object.
+    pub fn assign(&mut self, key: String, binding: Binding, value: ObjectKind) -> Result<(), String> {
+        if self.is_constant(&key, binding) {
+            return Err(format!("cannot modify constant {}", key));
+        }
+        match self.locate(&key, binding) {
+            Some((0, None)) => {
+                self.store.insert(key, value);
+                Ok(())
+            },
+            Some((depth, Some(slot))) => {
+                self.frames[depth - 1][slot].1 = value;
+                Ok(())
+            },
+            _ => {
+                Err(format!("unbound variable {}", key))
+            }
+        }
+    }
+
+    /// Where a bound variable lives: the number of frames it was declared
+    /// under, as in `constants`, and its index in that frame.
+    fn locate(&self, key: &str, binding: Binding) -> Option<(usize, Option<usize>)> {
+        match binding {
+            Binding::Local{depth, slot} if depth < self.frames.len() => {
+                let index = self.frames.len() - 1 - depth;
+                if slot < self.frames[index].len() { Some((index + 1, Some(slot))) } else { None }
+            },
+            Binding::Global => {
+                if self.store.contains_key(key) { Some((0, None)) } else { None }
+            },
+            _ => {
+                for (index, frame) in self.frames.iter().enumerate().rev() {
+                    if let Some(slot) = frame.iter().rposition(|(name, _)| name == key) {
+                        return Some((index + 1, Some(slot)));
+                    }
+                }
+                if self.store.contains_key(key) { Some((0, None)) } else { None }
+            }
+        }
+    }
+
+    fn bind(&mut self, key: String, binding: Binding, value: ObjectKind) {
+        let frame = match self.frames.last_mut() {
+            Some(frame) => frame,
+            None => {
+                self.store.insert(key, value);
+                return;
+            }
+        };
+        match binding {
+            Binding::Local{slot, ..} => {
+                while frame.len() <= slot {
+                    frame.push((String::new(), ObjectKind::Null));
+                }
+                frame[slot] = (key, value);
+            },
+            _ => {
+                match frame.iter().position(|(name, _)| *name == key) {
+                    Some(i) => frame[i].1 = value,
+                    None => frame.push((key, value))
+                }
+            }
+        }
+    }
+}
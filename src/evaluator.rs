@@ -1,11 +1,19 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 use super::ast::NodeKind;
 use super::ast::StatementKind;
 use super::ast::ExpressionKind;
+use super::ast::PatternKind;
+use super::ast::MatchArm;
+use super::ast::Binding;
 
 use super::object::ObjectKind;
 use super::environment::Environment;
+use super::operator;
+use super::builtins;
+use super::module;
 
 pub fn eval(node: NodeKind, env: &mut Environment) -> ObjectKind {
 	match node {
@@ -14,7 +22,7 @@ pub fn eval(node: NodeKind, env: &mut Environment) -> ObjectKind {
         },
         NodeKind::StatementNode{statementKind} => {
             match statementKind {
-                StatementKind::LetStatement{token, name, value} => {
+                StatementKind::LetStatement{name, value, constant, ..} => {
                     match value {
                         Some(v) => {
                             let val = eval(NodeKind::ExpressionNode{expressionKind: *v}, env);
@@ -24,8 +32,15 @@ pub fn eval(node: NodeKind, env: &mut Environment) -> ObjectKind {
                                 },
                                 _ => {
                                     match name {
-                                        ExpressionKind::Identifier{token, value: name_value} => {
-                                            env.insert(name_value, val);
+                                        ExpressionKind::Identifier{value: name_value, binding, ..} => {
+                                            let defined = if constant {
+                                                env.define_constant(name_value, binding, val)
+                                            } else {
+                                                env.define(name_value, binding, val)
+                                            };
+                                            if let Err(message) = defined {
+                                                return ObjectKind::Error{message};
+                                            }
                                         },
                                         _ => {}
                                     }
@@ -63,6 +78,54 @@ pub fn eval(node: NodeKind, env: &mut Environment) -> ObjectKind {
                 },
                 StatementKind::BlockStatement{..} => {
                     return eval_block_statement(statementKind, env);
+                },
+                StatementKind::StructStatement{name, fields, ..} => {
+                    if let ExpressionKind::Identifier{value, binding, ..} = name {
+                        let constructor = ObjectKind::StructType{name: value.clone(), fields};
+                        if let Err(message) = env.define(value, binding, constructor) {
+                            return ObjectKind::Error{message};
+                        }
+                    }
+                },
+                StatementKind::EnumStatement{name, variants, ..} => {
+                    if let ExpressionKind::Identifier{value, binding, ..} = name {
+                        let variants = variants.into_iter().map(|v| (v.name, v.fields)).collect();
+                        let enum_type = ObjectKind::EnumType{name: value.clone(), variants};
+                        if let Err(message) = env.define(value, binding, enum_type) {
+                            return ObjectKind::Error{message};
+                        }
+                    }
+                },
+                StatementKind::AssignStatement{target, value, ..} => {
+                    let value = eval(NodeKind::ExpressionNode{expressionKind: value}, env);
+                    if is_error(value.clone()) {
+                        return value;
+                    }
+                    let assigned = value.clone();
+                    return match update_slot(target, env, move |object, name| object.add_to_slots(name, assigned)) {
+                        Ok(()) => value,
+                        Err(message) => ObjectKind::Error{message}
+                    };
+                },
+                StatementKind::DeleteStatement{target, ..} => {
+                    return match update_slot(target, env, |object, name| object.remove_from_slots(name)) {
+                        Ok(()) => ObjectKind::Null,
+                        Err(message) => ObjectKind::Error{message}
+                    };
+                },
+                StatementKind::ImportStatement{path, name, ..} => {
+                    let exports = module::import(&path, env);
+                    if is_error(exports.clone()) {
+                        return exports;
+                    }
+                    if let ExpressionKind::Identifier{value, binding, ..} = name {
+                        if let Err(message) = env.define(value, binding, exports) {
+                            return ObjectKind::Error{message};
+                        }
+                    }
+                },
+                StatementKind::ExportStatement{statement, ..} => {
+                    return eval(NodeKind::StatementNode{statementKind: *statement}, env);
                 }
                 _ => {
                     panic!("not implmented");
@@ -91,6 +154,9 @@ pub fn eval(node: NodeKind, env: &mut Environment) -> ObjectKind {
                     }
                     panic!("right part of prefix not found.");
                 },
+                ExpressionKind::InfixExpression{ref operator, ..} if operator == "|>" => {
+                    return eval_pipeline_expression(expressionKind, env);
+                },
                 ExpressionKind::InfixExpression{operator: operator, left: left, right: right, ..} => {
                     match left {
                         Some(l) => {
@@ -101,6 +167,17 @@ pub fn eval(node: NodeKind, env: &mut Environment) -> ObjectKind {
                                 },
                                 _ => {}
                             }
+                            // `??` only evaluates its right side when the left is null.
+                            if operator == "??" {
+                                match (eval_left, right) {
+                                    (ObjectKind::Null, Some(r)) => {
+                                        return eval(NodeKind::ExpressionNode{expressionKind: *r}, env);
+                                    },
+                                    (l, _) => {
+                                        return l;
+                                    }
+                                }
+                            }
                             match right {
                                 Some(r) => {
                                     let eval_right = eval(NodeKind::ExpressionNode{expressionKind: *r}, env);
@@ -124,31 +201,11 @@ pub fn eval(node: NodeKind, env: &mut Environment) -> ObjectKind {
                 ExpressionKind::IfExpression{..} => {
                     return eval_if_expression(expressionKind, env);
                 },
-                ExpressionKind::FunctionLiteral{token, parameters, body} => {
-                    return ObjectKind::Function{slots: HashMap::new(), parameters: parameters, body: *body, env: env.clone()};
+                ExpressionKind::FunctionLiteral{parameters, body, ..} => {
+                    return ObjectKind::Function{slots: HashMap::new(), parameters, body: *body, env: env.clone()};
                 },
-                ExpressionKind::CallExpression{function: function, arguments, ..} => {
-                    let func = eval(NodeKind::ExpressionNode{expressionKind: *function}, env);
-
-                    let args = eval_expressions(arguments, env);
-                    match args.len() == 1 {
-                        true => {
-                            match args.get(0) {
-                                Some(arg) => {
-                                    let first_arg = arg.clone();
-                                    match is_error(first_arg) {
-                                        true => {
-                                            return arg.clone();
-                                        },
-                                        _ => {}
-                                    }
-                                },
-                                _ => {}
-                            }
-                        },
-                        _ => {}
-                    }
-                    return apply_function(func, args);
+                ExpressionKind::CallExpression{..} | ExpressionKind::IndexExpression{..} | ExpressionKind::SlotExpression{..} => {
+                    return eval_postfix(expressionKind, env).unwrap_or(ObjectKind::Null);
                 },
                 ExpressionKind::StringLiteral{value: value, ..} => {
                     return ObjectKind::StringObj{slots: HashMap::new(), value};
@@ -156,6 +213,26 @@ pub fn eval(node: NodeKind, env: &mut Environment) -> ObjectKind {
                 ExpressionKind::IntegerLiteral{token, value} => {
                     return ObjectKind::Integer{slots: HashMap::new(), value: value};
                 },
+                ExpressionKind::ArrayLiteral{elements, ..} => {
+                    let elements = eval_expressions(elements, env);
+                    if elements.len() == 1 && is_error(elements[0].clone()) {
+                        return elements[0].clone();
+                    }
+                    return ObjectKind::Array{slots: HashMap::new(), elements, frozen: false};
+                },
+                ExpressionKind::HashLiteral{pairs, ..} => {
+                    return eval_hash_literal(pairs, env);
+                },
+                ExpressionKind::NullLiteral{..} => {
+                    return ObjectKind::Null;
+                },
+                ExpressionKind::MatchExpression{subject, arms, ..} => {
+                    let value = eval(NodeKind::ExpressionNode{expressionKind: *subject}, env);
+                    if is_error(value.clone()) {
+                        return value;
+                    }
+                    return eval_match_expression(value, arms, env);
+                },
                 _ => {
                     panic!("not implmented");
                 }
@@ -169,10 +246,10 @@ pub fn eval(node: NodeKind, env: &mut Environment) -> ObjectKind {
 }
 
 fn eval_program(statements: Vec<StatementKind>, env: &mut Environment) -> ObjectKind {
-
+    let mut result = ObjectKind::Null;
     for s in statements {
         let sNode = NodeKind::StatementNode{statementKind: s};
-        let result = eval(sNode, env);
+        result = eval(sNode, env);
         match result {
             ObjectKind::ReturnValue{value} => {
                 return *value;
@@ -183,10 +260,18 @@ fn eval_program(statements: Vec<StatementKind>, env: &mut Environment) -> Object
             _ => {}
         }
     }
-    return ObjectKind::Null;
+    result
 }
 
 fn eval_prefix_expression(operator: String, right: ObjectKind) -> ObjectKind {
+    let special = match operator.as_ref() {
+        "-" => right.call_special("__neg__", vec![]),
+        "!" => right.call_special("__not__", vec![]),
+        _ => None
+    };
+    if let Some(result) = special {
+        return result;
+    }
 	match operator.as_ref() {
         "!" => {
             eval_bang_operator_expression(right)
@@ -201,6 +286,24 @@ fn eval_prefix_expression(operator: String, right: ObjectKind) -> ObjectKind {
 }
 
 fn eval_infix_expression(operator: String, left: ObjectKind, right: ObjectKind) -> ObjectKind {
+    if let Some(custom) = operator::lookup(&operator) {
+        return (custom.handler)(left, right);
+    }
+    if let Some(result) = special_slot(&operator).and_then(|name| left.call_special(name, vec![right.clone()])) {
+        return result;
+    }
+    // Without a `__ne__`, `!=` is the opposite of `__eq__`.
+    if operator == "!=" {
+        match left.call_special("__eq__", vec![right.clone()]) {
+            Some(ObjectKind::Error{message}) => {
+                return ObjectKind::Error{message};
+            },
+            Some(result) => {
+                return native_bool_to_boolean_object(!is_truthy(result));
+            },
+            None => {}
+        }
+    }
     match left {
         ObjectKind::Integer{..} => {
             match right {
@@ -226,7 +329,7 @@ fn eval_infix_expression(operator: String, left: ObjectKind, right: ObjectKind)
                 _ => {}
             }
         }
-        ObjectKind::StringObj{..} => {
+        ObjectKind::StringObj{..} if operator != "==" && operator != "!=" => {
             let left_clone = left.clone();
             match left_clone.variant_eq(&right) {
                 true => {
@@ -237,13 +340,38 @@ fn eval_infix_expression(operator: String, left: ObjectKind, right: ObjectKind)
         },
         _ => {}
     }
+    match operator.as_ref() {
+        "==" => {
+            return native_bool_to_boolean_object(left.equals(&right));
+        },
+        "!=" => {
+            return native_bool_to_boolean_object(!left.equals(&right));
+        },
+        _ => {}
+    }
     match left.variant_eq(&right) {
         false => {
-            panic!("infix operator not valid for types");
+            return ObjectKind::Error{message: String::from("infix operator not valid for types")};
         },
         _ => {}
     }
-    panic!("not implmented");
+    ObjectKind::Error{message: String::from("operator error")}
+}
+
+/// The slot a value on the left of `operator` can define to overload it.
+fn special_slot(operator: &str) -> Option<&'static str> {
+    match operator {
+        "+" => Some("__add__"),
+        "-" => Some("__sub__"),
+        "*" => Some("__mul__"),
+        "/" => Some("__div__"),
+        "%" => Some("__mod__"),
+        "<" => Some("__lt__"),
+        ">" => Some("__gt__"),
+        "==" => Some("__eq__"),
+        "!=" => Some("__ne__"),
+        _ => None
+    }
 }
 
 fn native_bool_to_boolean_object(input: bool) -> ObjectKind {
@@ -298,11 +426,14 @@ fn eval_bang_operator_expression(right: ObjectKind) -> ObjectKind {
     }
 }
 
-fn eval_minus_prefix_operator_expression(right: ObjectKind) -> ObjectKind {	
+/// Integers are unsigned, so only zero can be negated.
+fn eval_minus_prefix_operator_expression(right: ObjectKind) -> ObjectKind {
     match right {
-        ObjectKind::Integer{slots, value} => {
-            panic!("not implmented. need to change to sized int");
-            //ObjectKind::Integer{slots: slots, value: -value}
+        ObjectKind::Integer{value: 0, ..} => {
+            ObjectKind::Integer{slots: HashMap::new(), value: 0}
+        },
+        ObjectKind::Integer{value, ..} => {
+            ObjectKind::Error{message: format!("integers can't be negative: -{}", value)}
         },
         _ => {
             ObjectKind::Error{message: String::from("operator error")}
@@ -310,30 +441,36 @@ fn eval_minus_prefix_operator_expression(right: ObjectKind) -> ObjectKind {
     }
 }
 
-
+/// Arithmetic that would leave the range of an unsigned 32-bit integer, or
+/// divide by zero, is an error rather than a wrapped value.
 fn eval_integer_infix_expression (operator: String, left: ObjectKind, right: ObjectKind) -> ObjectKind {
     match left {
         ObjectKind::Integer{slots: lslots, value: lvalue} => {
             match right {
                 ObjectKind::Integer{slots: rslots, value: rvalue} => {
-                    match operator.as_ref() {
-                        "+" => {   
-                            return ObjectKind::Integer{slots: HashMap::new(), value: lvalue + rvalue};
-                        },
-                        "-" => {
-                            return ObjectKind::Integer{slots: HashMap::new(), value: lvalue - rvalue};
+                    let arithmetic = match operator.as_ref() {
+                        "+" => Some(lvalue.checked_add(rvalue)),
+                        "-" => Some(lvalue.checked_sub(rvalue)),
+                        "*" => Some(lvalue.checked_mul(rvalue)),
+                        "/" | "%" if rvalue == 0 => {
+                            return ObjectKind::Error{message: format!("division by zero: {} {} 0", lvalue, operator)};
                         },
-                        "*" => {
-                            return ObjectKind::Integer{slots: HashMap::new(), value: lvalue * rvalue};
+                        "/" => Some(lvalue.checked_div(rvalue)),
+                        "%" => Some(lvalue.checked_rem(rvalue)),
+                        _ => None
+                    };
+                    match arithmetic {
+                        Some(Some(value)) => {
+                            return ObjectKind::Integer{slots: HashMap::new(), value};
                         },
-                        "/" => {
-                            return ObjectKind::Integer{slots: HashMap::new(), value: lvalue / rvalue};
-                        },
-                        "%" => {
-                            return ObjectKind::Integer{slots: HashMap::new(), value: lvalue % rvalue};
+                        Some(None) => {
+                            return ObjectKind::Error{message: format!("integer overflow: {} {} {}", lvalue, operator, rvalue)};
                         },
+                        None => {}
+                    }
+                    match operator.as_ref() {
                         "<" => {
-                            return ObjectKind::Boolean{value: lvalue > rvalue};
+                            return ObjectKind::Boolean{value: lvalue < rvalue};
                         },
                         ">" => {
                             return ObjectKind::Boolean{value: lvalue > rvalue};
@@ -397,14 +534,18 @@ fn eval_if_expression(ie: ExpressionKind, env: &mut Environment) -> ObjectKind {
 fn eval_identifier(node: ExpressionKind, env: &mut Environment) -> ObjectKind {
 
     match node {
-        ExpressionKind::Identifier{token, value} => {
+        ExpressionKind::Identifier{value, binding, ..} => {
             // Design decision: we're pulling a value out of the environment here.
             //                  This basically makes its value immutable since changing
             //                  the value wont change it in the environment.
-            env.clone().get(value)
+            match env.lookup(value.clone(), binding) {
+                ObjectKind::Error{message} => {
+                    builtins::lookup(&value).unwrap_or(ObjectKind::Error{message})
+                },
+                found => found
+            }
         },
         _ => {
-            // TODO: Add builtins check here.
             ObjectKind::Error{message: String::from("Ident not found.")}
         }
     }
@@ -418,7 +559,7 @@ fn eval_expressions(exps: Vec<Box<ExpressionKind>>, env: &mut Environment) -> Ve
 		let evaluated = eval(expressioNode, env);
 		match evaluated {
             ObjectKind::Error{..} => {
-                panic!("not implmented");
+                return vec![evaluated];
             },
             _ => {}
         }
@@ -427,37 +568,157 @@ fn eval_expressions(exps: Vec<Box<ExpressionKind>>, env: &mut Environment) -> Ve
 	return result;
 }
 
+fn is_slot(expression: &ExpressionKind) -> bool {
+    matches!(expression, ExpressionKind::SlotExpression{..})
+}
+
+/// `receiver.name(args)`. The method is looked up like any slot and called
+/// with `self` bound to the receiver. Objects that have no `clone` slot
+/// answer `clone()` with a new, empty object whose parent is the receiver.
+///
+/// The method works on a copy of the receiver, so when the receiver is a
+/// variable, or a slot of one, whatever the method left in `self` is stored
+/// back there. `piped` are arguments already evaluated by a pipeline, which
+/// go before `arguments`.
+fn eval_method_call(function: ExpressionKind, piped: Vec<ObjectKind>, arguments: Vec<Box<ExpressionKind>>, env: &mut Environment) -> Option<ObjectKind> {
+    let (receiver_expression, name, optional) = match function {
+        ExpressionKind::SlotExpression{left, name, optional, ..} => (*left, name, optional),
+        _ => {
+            return Some(ObjectKind::Error{message: String::from("method call needs a slot")});
+        }
+    };
+    let receiver = eval_postfix(receiver_expression.clone(), env)?;
+    if is_error(receiver.clone()) {
+        return Some(receiver);
+    }
+    let method = match receiver {
+        ObjectKind::Null if optional => {
+            return None;
+        },
+        _ => receiver.clone().get_from_slots(name.clone())
+    };
+    match (method, &receiver) {
+        (ObjectKind::Error{..}, &ObjectKind::Object{..}) if name == "clone" => {
+            Some(ObjectKind::Object{slots: Rc::new(RefCell::new(HashMap::new())), parent: Some(Box::new(receiver.clone())), frozen: false})
+        },
+        (ObjectKind::Error{message}, _) => {
+            Some(ObjectKind::Error{message})
+        },
+        (method, _) => {
+            let args = eval_expressions(arguments, env);
+            if args.len() == 1 && is_error(args[0].clone()) {
+                return Some(args[0].clone());
+            }
+            let args = piped.into_iter().chain(args).collect();
+            let frozen = receiver.is_frozen();
+            let (result, receiver) = apply_method(method, receiver.clone(), args);
+            if !frozen && !is_error(result.clone()) {
+                match place(receiver_expression) {
+                    Some((key, binding, path)) if !env.is_constant(&key, binding) => {
+                        let updated = update_place(key, binding, &path, env, |object| {
+                            *object = receiver;
+                            Ok(())
+                        });
+                        if let Err(message) = updated {
+                            return Some(ObjectKind::Error{message});
+                        }
+                    },
+                    _ => {}
+                }
+            }
+            Some(result)
+        }
+    }
+}
+
 fn apply_function(func: ObjectKind, args: Vec<ObjectKind>) -> ObjectKind {
+    apply_method(func, ObjectKind::Null, args).0
+}
+
+/// Calls `func` with `self` bound to `receiver`, for code outside the
+/// evaluator that runs special slots.
+pub fn call_method(func: ObjectKind, receiver: ObjectKind, args: Vec<ObjectKind>) -> ObjectKind {
+    apply_method(func, receiver, args).0
+}
+
+/// Calls `func` with `self` bound to `receiver`, and returns the result along
+/// with `self` as the call left it.
+fn apply_method(func: ObjectKind, receiver: ObjectKind, args: Vec<ObjectKind>) -> (ObjectKind, ObjectKind) {
     match func {
         ObjectKind::Function{parameters: parameters, body: body, env: env, ..} => {
             let fn_body = body.clone();
-            let extended_env = extend_function_env(parameters, env, args);
-            let evaluated = eval(NodeKind::StatementNode{statementKind: fn_body}, &mut extended_env.clone());
-            return unwrap_return_value(evaluated);
+            let mut extended_env = extend_function_env(parameters, env, receiver, args);
+            let evaluated = eval(NodeKind::StatementNode{statementKind: fn_body}, &mut extended_env);
+            let receiver = match extended_env.frames.last() {
+                Some(frame) => frame.iter().rev().find(|&(name, _)| name == "self").map(|(_, v)| v.clone()),
+                None => None
+            };
+            (unwrap_return_value(evaluated), receiver.unwrap_or(ObjectKind::Null))
         },
-        _ => {
-            panic!("not implmented");
+        other => (apply_callable(other, args), receiver)
+    }
+}
+
+fn apply_callable(func: ObjectKind, args: Vec<ObjectKind>) -> ObjectKind {
+    match func {
+        ObjectKind::BuiltIn{function, ..} => {
+            function(&args)
+        },
+        ObjectKind::Native{name, arity, function} => {
+            if args.len() != arity {
+                let plural = if arity == 1 { "" } else { "s" };
+                return ObjectKind::Error{message: format!("{} expects {} argument{}, got {}", name, arity, plural, args.len())};
+            }
+            match function(&args) {
+                Ok(value) => value,
+                Err(message) => ObjectKind::Error{message: format!("{}: {}", name, message)}
+            }
+        },
+        ObjectKind::StructType{name, fields} => {
+            if args.len() != fields.len() {
+                return ObjectKind::Error{message: format!("{} expects {} fields, got {}", name, fields.len(), args.len())};
+            }
+            ObjectKind::Struct{name, fields: fields.into_iter().zip(args).collect()}
+        },
+        ObjectKind::VariantType{enum_name, variant, fields} => {
+            if args.len() != fields.len() {
+                return ObjectKind::Error{message: format!("{}.{} expects {} fields, got {}", enum_name, variant, fields.len(), args.len())};
+            }
+            ObjectKind::Variant{enum_name, variant, fields: fields.into_iter().zip(args).collect()}
+        },
+        other => {
+            ObjectKind::Error{message: format!("not a function: {}", other.type_name())}
         }
     }
 }
 
-fn extend_function_env(parameters: Vec<ExpressionKind>, env: Environment, args: Vec<ObjectKind>) -> Environment {
+/// Calls run in a new frame whose first slots are the parameters, in order,
+/// followed by `self` unless a parameter has that name. Parameters without a
+/// matching argument are null.
+fn extend_function_env(parameters: Vec<ExpressionKind>, env: Environment, receiver: ObjectKind, args: Vec<ObjectKind>) -> Environment {
     let mut closure = env.clone();
+    let mut frame = Vec::new();
     let mut param_index = 0;
     for param in parameters {
         match param {
-            ExpressionKind::Identifier{token, value} => {
+            ExpressionKind::Identifier{value, ..} => {
                 match args.get(param_index) {
                     Some(arg) => {
-                        closure.insert(value, arg.clone());
+                        frame.push((value, arg.clone()));
                     },
-                    _ => {}
+                    _ => {
+                        frame.push((value, ObjectKind::Null));
+                    }
                 }
             }, 
             _ => {}
         }
         param_index = param_index + 1;
     }
+    if !frame.iter().any(|(name, _)| name == "self") {
+        frame.push((String::from("self"), receiver));
+    }
+    closure.push_frame(frame);
     return closure;
 }
 
@@ -508,4 +769,824 @@ fn is_error(obj: ObjectKind) -> bool {
             false
         }
     }
-}
\ No newline at end of file
+}
+
+fn eval_index_expression(left: ObjectKind, index: ObjectKind) -> ObjectKind {
+    if let Some(result) = left.call_special("__index__", vec![index.clone()]) {
+        return result;
+    }
+    match (left, index) {
+        (ObjectKind::Array{elements, ..}, ObjectKind::Integer{value, ..}) => {
+            match elements.get(value as usize) {
+                Some(e) => e.clone(),
+                None => ObjectKind::Null
+            }
+        },
+        (ObjectKind::Hash{pairs, ..}, index) => {
+            match index.hash_key() {
+                Some(key) => {
+                    match pairs.get(&key) {
+                        Some((_, v)) => v.clone(),
+                        None => ObjectKind::Null
+                    }
+                },
+                None => {
+                    ObjectKind::Error{message: format!("unusable as hash key: {}", index)}
+                }
+            }
+        },
+        _ => {
+            ObjectKind::Error{message: String::from("index operator not supported")}
+        }
+    }
+}
+
+/// `x |> f(a)` calls `f(x, a)`; a bare function on the right, `x |> f`, calls `f(x)`.
+fn eval_pipeline_expression(pipeline: ExpressionKind, env: &mut Environment) -> ObjectKind {
+    match pipeline {
+        ExpressionKind::InfixExpression{left: Some(left), right: Some(right), ..} => {
+            let value = eval(NodeKind::ExpressionNode{expressionKind: *left}, env);
+            if is_error(value.clone()) {
+                return value;
+            }
+            let (function, arguments) = match *right {
+                ExpressionKind::CallExpression{function, arguments, ..} => {
+                    (function, arguments)
+                },
+                other => {
+                    (Box::new(other), vec![])
+                }
+            };
+            if is_slot(&function) {
+                return eval_method_call(*function, vec![value], arguments, env).unwrap_or(ObjectKind::Null);
+            }
+            let func = eval(NodeKind::ExpressionNode{expressionKind: *function}, env);
+            if is_error(func.clone()) {
+                return func;
+            }
+            let mut args = eval_expressions(arguments, env);
+            if args.len() == 1 && is_error(args[0].clone()) {
+                return args[0].clone();
+            }
+            args.insert(0, value);
+            apply_function(func, args)
+        },
+        _ => {
+            ObjectKind::Error{message: String::from("pipeline needs a value and a function")}
+        }
+    }
+}
+
+/// Evaluates a call, index or slot read together with the postfix chain it
+/// ends. `None` means a `?.` or `?[` met null, which ends the whole chain, so
+/// `user?.address.city` is null rather than an error when `user` is.
+fn eval_postfix(expression: ExpressionKind, env: &mut Environment) -> Option<ObjectKind> {
+    match expression {
+        ExpressionKind::CallExpression{function, arguments, ..} if is_slot(&function) => {
+            eval_method_call(*function, vec![], arguments, env)
+        },
+        ExpressionKind::CallExpression{function, arguments, ..} => {
+            let func = eval_postfix(*function, env)?;
+            if is_error(func.clone()) {
+                return Some(func);
+            }
+            let args = eval_expressions(arguments, env);
+            if args.len() == 1 && is_error(args[0].clone()) {
+                return Some(args[0].clone());
+            }
+            Some(apply_function(func, args))
+        },
+        ExpressionKind::IndexExpression{left, index, optional, ..} => {
+            let left = eval_postfix(*left, env)?;
+            if is_error(left.clone()) {
+                return Some(left);
+            }
+            match left {
+                ObjectKind::Null if optional => {
+                    return None;
+                },
+                _ => {}
+            }
+            match index {
+                Some(i) => {
+                    let index = eval(NodeKind::ExpressionNode{expressionKind: *i}, env);
+                    if is_error(index.clone()) {
+                        return Some(index);
+                    }
+                    Some(eval_index_expression(left, index))
+                },
+                _ => {
+                    Some(ObjectKind::Error{message: String::from("index expression missing index")})
+                }
+            }
+        },
+        ExpressionKind::SlotExpression{left, name, optional, ..} => {
+            let left = eval_postfix(*left, env)?;
+            if is_error(left.clone()) {
+                return Some(left);
+            }
+            eval_slot_expression(left, name, optional)
+        },
+        expression => {
+            Some(eval(NodeKind::ExpressionNode{expressionKind: expression}, env))
+        }
+    }
+}
+
+/// Optional access (`?.`) gives up both for a null receiver and for a
+/// missing slot, so partially-populated data can be walked without checks.
+fn eval_slot_expression(left: ObjectKind, name: String, optional: bool) -> Option<ObjectKind> {
+    match left {
+        ObjectKind::Null if optional => {
+            return None;
+        },
+        _ => {}
+    }
+    let value = left.get_from_slots(name);
+    match value {
+        ObjectKind::Error{..} if optional => {
+            None
+        },
+        _ => {
+            Some(value)
+        }
+    }
+}
+
+/// Applies `update` to the object holding the last slot of `target`.
+fn update_slot<F>(target: ExpressionKind, env: &mut Environment, update: F) -> Result<(), String>
+    where F: FnOnce(&mut ObjectKind, String) -> Result<(), String> {
+    match place(target) {
+        Some((key, binding, mut path)) => {
+            let name = match path.pop() {
+                Some(name) => name,
+                None => {
+                    return Err(String::from("can only change slots of a variable"));
+                }
+            };
+            update_place(key, binding, &path, env, |object| update(object, name))
+        },
+        None => {
+            Err(String::from("can only change slots of a variable"))
+        }
+    }
+}
+
+/// Splits a variable, or a chain of slots read from one, into the variable
+/// and the slot names in order.
+fn place(expression: ExpressionKind) -> Option<(String, Binding, Vec<String>)> {
+    let mut path = Vec::new();
+    let mut current = expression;
+    loop {
+        match current {
+            ExpressionKind::SlotExpression{left, name, optional: false, ..} => {
+                path.push(name);
+                current = *left;
+            },
+            ExpressionKind::Identifier{value, binding, ..} => {
+                path.reverse();
+                return Some((value, binding, path));
+            },
+            _ => {
+                return None;
+            }
+        }
+    }
+}
+
+/// Applies `update` to the object at `path` in the variable `key`, then
+/// stores every object along the way back. Values are copied on read, so
+/// `p.a.b = 1` has to rebuild `p`.
+fn update_place<F>(key: String, binding: Binding, path: &[String], env: &mut Environment, update: F) -> Result<(), String>
+    where F: FnOnce(&mut ObjectKind) -> Result<(), String> {
+    let mut root = env.lookup(key.clone(), binding);
+    if let ObjectKind::Error{message} = root {
+        return Err(message);
+    }
+    if env.is_constant(&key, binding) {
+        return Err(format!("cannot modify constant {}", key));
+    }
+    update_path(&mut root, path, update)?;
+    env.assign(key, binding, root)
+}
+
+fn update_path<F>(object: &mut ObjectKind, path: &[String], update: F) -> Result<(), String>
+    where F: FnOnce(&mut ObjectKind) -> Result<(), String> {
+    match path.split_first() {
+        Some((name, rest)) => {
+            let mut inner = object.clone().get_from_slots(name.clone());
+            if let ObjectKind::Error{message} = inner {
+                return Err(message);
+            }
+            update_path(&mut inner, rest, update)?;
+            object.add_to_slots(name.clone(), inner)
+        },
+        None => {
+            update(object)
+        }
+    }
+}
+
+fn eval_hash_literal(pairs: Vec<(Box<ExpressionKind>, Box<ExpressionKind>)>, env: &mut Environment) -> ObjectKind {
+    let mut hash = HashMap::new();
+    for (k, v) in pairs {
+        let key = eval(NodeKind::ExpressionNode{expressionKind: *k}, env);
+        if is_error(key.clone()) {
+            return key;
+        }
+        let hash_key = match key.hash_key() {
+            Some(h) => h,
+            None => {
+                return ObjectKind::Error{message: format!("unusable as hash key: {}", key)};
+            }
+        };
+        let value = eval(NodeKind::ExpressionNode{expressionKind: *v}, env);
+        if is_error(value.clone()) {
+            return value;
+        }
+        hash.insert(hash_key, (key, value));
+    }
+    ObjectKind::Hash{pairs: hash, frozen: false}
+}
+
+/// Tries each arm in order. The first arm whose pattern matches and whose
+/// guard (if any) is truthy is evaluated with the pattern's bindings in scope.
+fn eval_match_expression(value: ObjectKind, arms: Vec<MatchArm>, env: &mut Environment) -> ObjectKind {
+    for arm in arms {
+        let names = arm.pattern.binding_names();
+        let mut bindings = Vec::new();
+        let matched = match_pattern(arm.pattern, &value, &mut bindings, env);
+        match matched {
+            Ok(false) => {
+                continue;
+            },
+            Err(message) => {
+                return ObjectKind::Error{message};
+            },
+            _ => {}
+        }
+        // The arm's frame follows `binding_names` order, whichever or-alternative matched.
+        let mut frame = Vec::new();
+        for name in names {
+            let bound = bindings.iter().find(|&(n, _)| *n == name).map(|(_, v)| v.clone());
+            frame.push((name, bound.unwrap_or(ObjectKind::Null)));
+        }
+        let mut arm_env = env.clone();
+        arm_env.push_frame(frame);
+        if let Some(g) = arm.guard {
+            let guard = eval(NodeKind::ExpressionNode{expressionKind: *g}, &mut arm_env);
+            if is_error(guard.clone()) {
+                return guard;
+            }
+            if !is_truthy(guard) {
+                continue;
+            }
+        }
+        return eval(NodeKind::ExpressionNode{expressionKind: *arm.body}, &mut arm_env);
+    }
+    ObjectKind::Error{message: format!("no match arm matched value: {}", value)}
+}
+
+fn match_pattern(pattern: PatternKind, value: &ObjectKind, bindings: &mut Vec<(String, ObjectKind)>, env: &mut Environment) -> Result<bool, String> {
+    match pattern {
+        PatternKind::WildcardPattern{..} => {
+            Ok(true)
+        },
+        PatternKind::BindingPattern{name, ..} => {
+            bindings.push((name, value.clone()));
+            Ok(true)
+        },
+        PatternKind::LiteralPattern{value: literal, ..} => {
+            let literal = eval(NodeKind::ExpressionNode{expressionKind: *literal}, env);
+            if let ObjectKind::Error{message} = literal {
+                return Err(message);
+            }
+            Ok(literal.equals(value))
+        },
+        PatternKind::ArrayPattern{elements: patterns, ..} => {
+            match value {
+                ObjectKind::Array{elements, ..} => {
+                    if elements.len() != patterns.len() {
+                        return Ok(false);
+                    }
+                    for (p, e) in patterns.into_iter().zip(elements.iter()) {
+                        if !match_pattern(p, e, bindings, env)? {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(true)
+                },
+                _ => {
+                    Ok(false)
+                }
+            }
+        },
+        PatternKind::HashPattern{pairs: patterns, ..} => {
+            match value {
+                ObjectKind::Hash{pairs, ..} => {
+                    for (k, p) in patterns {
+                        let key = eval(NodeKind::ExpressionNode{expressionKind: k}, env);
+                        let found = match key.hash_key() {
+                            Some(hash_key) => pairs.get(&hash_key),
+                            None => None
+                        };
+                        match found {
+                            Some((_, v)) => {
+                                if !match_pattern(p, v, bindings, env)? {
+                                    return Ok(false);
+                                }
+                            },
+                            None => {
+                                return Ok(false);
+                            }
+                        }
+                    }
+                    Ok(true)
+                },
+                _ => {
+                    Ok(false)
+                }
+            }
+        },
+        PatternKind::OrPattern{alternatives, ..} => {
+            for alternative in alternatives {
+                let mut alt_bindings = Vec::new();
+                if match_pattern(alternative, value, &mut alt_bindings, env)? {
+                    bindings.extend(alt_bindings);
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        },
+        PatternKind::VariantPattern{enum_name: pattern_enum, variant: pattern_variant, fields: patterns, ..} => {
+            match value {
+                ObjectKind::Variant{enum_name, variant, fields} if *enum_name == pattern_enum && *variant == pattern_variant => {
+                    let patterns = match patterns {
+                        Some(patterns) => patterns,
+                        None => {
+                            return Ok(true);
+                        }
+                    };
+                    if patterns.len() != fields.len() {
+                        return Ok(false);
+                    }
+                    for (p, (_, v)) in patterns.into_iter().zip(fields.iter()) {
+                        if !match_pattern(p, v, bindings, env)? {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(true)
+                },
+                _ => {
+                    Ok(false)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::lexer::Lexer;
+    use super::super::parser::Parser;
+    use super::super::resolver;
+
+    fn test_eval(input: &str) -> ObjectKind {
+        let l = Lexer::new(String::from(input));
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        let mut env = Environment::new();
+        let globals: Vec<String> = env.store.keys().cloned().collect();
+        let program = resolver::resolve(program, &globals).expect("resolve error");
+        eval(program, &mut env)
+    }
+
+    /// The message of the error `input` evaluates to.
+    fn eval_error(input: &str) -> String {
+        match test_eval(input) {
+            ObjectKind::Error{message} => message,
+            other => panic!("expected an error from {}, got {}", input, other)
+        }
+    }
+
+    #[test]
+    fn test_match_expression() {
+        let tests = vec![
+            ("match 0 { 0 => \"zero\", _ => \"other\" }", "zero"),
+            ("match 7 { 0 => \"zero\", _ => \"other\" }", "other"),
+            ("match [1, 2] { [x, y] => x + y, _ => 0 }", "3"),
+            ("match [1, 2, 3] { [x, y] => x + y, _ => 0 }", "0"),
+            ("match \"b\" { \"a\" | \"b\" => \"ab\", _ => \"other\" }", "ab"),
+            ("match 12 { n if n > 10 => n * 2, n => n }", "24"),
+            ("match 5 { n if n > 10 => n * 2, n => n }", "5"),
+            ("match {\"x\": 1, \"y\": [2, 3]} { {\"y\": [a, b]} => a * b, _ => 0 }", "6"),
+            ("let n := 4; match n { 4 => n + 1, _ => 0 }", "5"),
+        ];
+        for test in tests {
+            assert_eq!(test_eval(test.0).to_string(), test.1);
+        }
+    }
+
+    #[test]
+    fn test_match_without_matching_arm() {
+        let evaluated = test_eval("match 3 { 1 => 1, 2 => 2 }");
+        match evaluated {
+            ObjectKind::Error{message} => {
+                assert_eq!(message, "no match arm matched value: 3");
+            },
+            _ => panic!("expected an error")
+        }
+    }
+
+    #[test]
+    fn test_null_operators() {
+        let tests = vec![
+            ("null", "Null"),
+            ("null ?? 5", "5"),
+            ("3 ?? 5", "3"),
+            ("let user := {\"name\": \"ann\"}; user?.name", "ann"),
+            ("let user := {\"name\": \"ann\"}; user?.email ?? \"none\"", "none"),
+            ("let user := null; user?.name?.first ?? \"anon\"", "anon"),
+            ("let xs := null; xs?[0] ?? 1 + 1", "2"),
+            ("let xs := [7]; xs?[0]", "7"),
+            ("null == null", "true"),
+            ("1 == null", "false"),
+            ("1 != null", "true"),
+            ("if (false) { 1 } ?? 10", "10"),
+            ("null?.a.b", "Null"),
+            ("null?[0].x", "Null"),
+            ("null?.a[0].b(1)", "Null"),
+            ("let user := {\"name\": \"ann\"}; user?.address.city ?? \"nowhere\"", "nowhere"),
+            ("\"a\" == \"a\"", "true"),
+            ("\"a\" != \"b\"", "true"),
+            ("[\"a\" == \"b\", \"a\" != \"a\", \"1\" == 1]", "[false, false, false]"),
+            ("match null { null => 0, _ => 1 }", "0"),
+            ("let user := {\"name\": \"ann\"}; match user?.email { null => \"none\", email => email }", "none"),
+            ("match [1, null] { [a, null] => a, _ => 0 }", "1"),
+            ("match 5 { null => 0, n => n }", "5"),
+        ];
+        for test in tests {
+            assert_eq!(test_eval(test.0).to_string(), test.1);
+        }
+        match test_eval("null.a?.b") {
+            ObjectKind::Error{message} => assert_eq!(message, "null has no slots"),
+            other => panic!("expected an error, got {}", other)
+        }
+    }
+
+    #[test]
+    fn test_pipeline_operator() {
+        let tests = vec![
+            ("let add := fun(a, b) { a + b }; 1 |> add(2)", "3"),
+            ("let add := fun(a, b) { a + b }; let double := fun(a) { a * 2 }; 1 |> add(2) |> double()", "6"),
+            ("let double := fun(a) { a * 2 }; 1 + 2 |> double", "6"),
+            ("let pair := fun(a, b) { [a, b] }; 1 |> pair(2)", "[1, 2]"),
+            ("let o := Object.clone(); o.n = 10; o.add = fun(a, b) { a + b + self.n }; 1 |> o.add(2)", "13"),
+            ("let o := Object.clone(); o.n = 10; o.inc = fun(a) { a + self.n }; 1 |> o.inc |> o.inc()", "21"),
+            ("let o := Object.clone(); o.total = 0; o.add = fun(a) { self.total = self.total + a }; 2 |> o.add(); 3 |> o.add(); o.total", "5"),
+            ("let o := null; 1 |> o?.add(2)", "Null"),
+        ];
+        for test in tests {
+            assert_eq!(test_eval(test.0).to_string(), test.1);
+        }
+    }
+
+    fn power(left: ObjectKind, right: ObjectKind) -> ObjectKind {
+        match (left, right) {
+            (ObjectKind::Integer{value: base, ..}, ObjectKind::Integer{value: exp, ..}) => {
+                ObjectKind::Integer{slots: HashMap::new(), value: base.pow(exp)}
+            },
+            _ => ObjectKind::Error{message: String::from("** expects integers")}
+        }
+    }
+
+    #[test]
+    fn test_custom_infix_operator() {
+        operator::register_infix_operator("**", super::super::parser::PRODUCT + 1, operator::Associativity::Right, power).unwrap();
+        assert_eq!(test_eval("2 ** 3 ** 2").to_string(), "512");
+        assert_eq!(test_eval("1 + 2 ** 3 * 2").to_string(), "17");
+        assert_eq!(test_eval("2 * 3").to_string(), "6");
+        assert!(operator::register_infix_operator("==", 3, operator::Associativity::Left, power).is_err());
+        assert!(operator::register_infix_operator("**", 0, operator::Associativity::Left, power).is_err());
+    }
+
+    #[test]
+    fn test_custom_operator_prefix_of_builtin() {
+        let tests = vec![
+            ("?", "operator ? would change how ?? is read"),
+            ("-", "operator - is already defined by the language"),
+            ("|", "operator | is already defined by the language"),
+        ];
+        for (symbol, expected) in tests {
+            assert_eq!(operator::register_infix_operator(symbol, 3, operator::Associativity::Left, power), Err(expected.to_string()));
+        }
+        assert_eq!(test_eval("let user := null; user?.name ?? \"anon\"").to_string(), "anon");
+    }
+
+    #[test]
+    fn test_resolved_locals() {
+        let tests = vec![
+            ("let x := 10; let f := fun(a) { let b := a + 1; fun(c) { a + b + c + x } }; f(1)(2)", "15"),
+            ("let f := fun(a) { let a := a * 2; let a := a + 1; a }; f(4)", "9"),
+            ("let f := fun(xs) { match xs { [h, t] | [t, h, _] if h > 0 => h * 10 + t, _ => 0 } }; f([1, 2, 3])", "21"),
+            ("let f := fun(n) { match n { m => if (true) { let k := m + n; k } } }; f(3)", "6"),
+            ("let f := fun(a, b) { b }; f(1)", "Null"),
+        ];
+        for test in tests {
+            assert_eq!(test_eval(test.0).to_string(), test.1);
+        }
+    }
+
+    #[test]
+    fn test_unresolved_program_evaluates_by_name() {
+        let mut p = Parser::new(Lexer::new(String::from("let y := 2; let f := fun(a) { let b := a; [b, y] }; f(1)")));
+        let program = p.parse_program();
+        assert_eq!(eval(program, &mut Environment::new()).to_string(), "[1, 2]");
+    }
+
+    #[test]
+    fn test_array_and_hash_literals() {
+        assert_eq!(test_eval("[1, 2 + 3][1]").to_string(), "5");
+        assert_eq!(test_eval("{\"a\": 1, 2: true}").to_string(), "{2: true, a: 1}");
+        assert_eq!(test_eval("{\"a\": 1}[\"b\"]").to_string(), "Null");
+    }
+
+    #[test]
+    fn test_constants() {
+        assert_eq!(test_eval("const n := 2; let f := fun(n) { let n := n + 1; n }; f(n) + n").to_string(), "5");
+        // Unresolved code is only checked when it runs.
+        let mut p = Parser::new(Lexer::new(String::from("const x := 1; let y := 2; let x := 3; y")));
+        let program = p.parse_program();
+        assert_eq!(eval(program, &mut Environment::new()).to_string(), "cannot reassign constant x");
+        let mut p = Parser::new(Lexer::new(String::from("const xs := [1, {\"k\": [2]}]; let ys := xs;")));
+        let program = resolver::resolve(p.parse_program(), &[]).ok().unwrap();
+        let mut env = Environment::new();
+        eval(program, &mut env);
+        let xs = env.get(String::from("xs"));
+        assert!(xs.is_frozen());
+        match xs {
+            ObjectKind::Array{ref elements, ..} => match elements[1] {
+                ObjectKind::Hash{ref pairs, ..} => assert!(pairs.values().all(|(_, v)| v.is_frozen())),
+                _ => panic!("expected a hash")
+            },
+            _ => panic!("expected an array")
+        }
+        // The value stays frozen wherever it is copied to.
+        assert!(env.get(String::from("ys")).is_frozen());
+    }
+
+    #[test]
+    fn test_structs() {
+        let tests = vec![
+            ("struct Point { x, y } Point(1, 2)", "Point { x: 1, y: 2 }"),
+            ("struct Point { x, y }; let p := Point(1, [2]); p.y[0] + p.x", "3"),
+            ("struct Point { x, y } Point(1, 2) == Point(1, 2)", "true"),
+            ("struct Point { x, y } Point(1, 2) == Point(2, 1)", "false"),
+            ("struct A { v } struct B { v } A(1) == B(1)", "false"),
+            ("struct Unit {} Unit()", "Unit {}"),
+            ("struct Point { x, y } Point", "struct Point { x, y }"),
+            ("struct Point { x, y } Point(1)", "Point expects 2 fields, got 1"),
+            ("struct Point { x, y } Point(1, 2).z", "Point has no field z"),
+            ("struct Point { x, y } match Point(3, 4) { p if p.x + 1 == p.y => p.y, _ => 0 }", "4"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_struct_constructor_errors() {
+        let tests = vec![
+            ("struct Point { x, y } Point(1, 2, 3)", "Point expects 2 fields, got 3"),
+            ("struct Point { x, y } Point()", "Point expects 2 fields, got 0"),
+            ("struct Unit {} Unit(1)", "Unit expects 0 fields, got 1"),
+            ("struct Point { x, y } let p := Point(1, 2); p |> Point()", "Point expects 2 fields, got 1"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(eval_error(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_enums() {
+        let shapes = "enum Shape { Circle(r), Rect(w, h), Empty }
+let area := fun(s) { match s { Shape.Circle(r) => r * r * 3, Shape.Rect(w, h) => w * h, Shape.Empty => 0 } };";
+        let tests = vec![
+            ("enum Shape { Circle(r), Rect(w, h), Empty } Shape.Rect(1, 2)", "Shape.Rect(1, 2)"),
+            ("enum Shape { Circle(r), Rect(w, h), Empty } Shape.Empty", "Shape.Empty"),
+            ("enum Shape { Circle(r), Rect(w, h), Empty, } Shape", "enum Shape { Circle(r), Rect(w, h), Empty }"),
+            ("enum Shape { Circle(r) } Shape.Circle", "Shape.Circle(r)"),
+            ("enum Shape { Circle(r) } Shape.Circle(1, 2)", "Shape.Circle expects 1 fields, got 2"),
+            ("enum Shape { Circle(r) } Shape.Square", "Shape has no variant Square"),
+            ("enum Shape { Circle(r) } Shape.Circle(5).r", "5"),
+            ("enum Shape { Circle(r), Rect(w, h) } Shape.Rect(1, 2) == Shape.Rect(1, 2)", "true"),
+            ("enum Shape { Circle(r), Rect(w, h) } Shape.Circle(1) == Shape.Rect(1, 2)", "false"),
+            ("enum A { V } enum B { V } A.V == B.V", "false"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).to_string(), expected, "{}", input);
+        }
+        let matches = vec![
+            ("area(Shape.Circle(2))", "12"),
+            ("area(Shape.Rect(3, 4))", "12"),
+            ("area(Shape.Empty)", "0"),
+            ("match Shape.Rect(3, 4) { Shape.Rect(1, h) => h, Shape.Rect => 99 }", "99"),
+            ("match Shape.Rect(1, 4) { Shape.Circle(x) | Shape.Rect(1, x) => x, _ => 0 }", "4"),
+            ("match Shape.Circle(2) { Shape.Rect(w, h) => w }", "no match arm matched value: Shape.Circle(2)"),
+        ];
+        for (input, expected) in matches {
+            let input = format!("{}\n{}", shapes, input);
+            assert_eq!(test_eval(&input).to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_slots() {
+        let tests = vec![
+            ("let n := 5; n.unit = \"cm\"; n.unit", "cm"),
+            ("let s := \"hi\"; s.lang = \"en\"; s.lang", "en"),
+            ("let f := fun(x) { x }; f.doc = \"identity\"; f.doc", "identity"),
+            ("let xs := [1, 2]; xs.name = \"pair\"; [xs.name, xs]", "[pair, [1, 2]]"),
+            ("let h := {\"a\": 1}; h.b = 2; h", "{a: 1, b: 2}"),
+            ("let h := {\"a\": {\"b\": 1}}; h.a.b = 2; h", "{a: {b: 2}}"),
+            ("struct P { x, y } let p := P(1, 2); p.x = 10; p", "P { x: 10, y: 2 }"),
+            ("struct P { x, y } let p := P(1, 2); p.z = 10", "P has no field z"),
+            ("let n := 1; n.a = 2", "2"),
+            ("let n := 1; n.a = 2; delete n.a; n.a", "integer has no slot a"),
+            ("let h := {\"a\": 1, \"b\": 2}; delete h.a; h", "{b: 2}"),
+            ("let h := {}; delete h.a", "hash has no slot a"),
+            ("struct P { x } let p := P(1); delete p.x", "cannot remove field x from struct"),
+            ("let b := true; b.a = 1", "boolean has no slots"),
+            ("let b := null; delete b.a", "null has no slots"),
+            ("true.a", "boolean has no slots"),
+            ("const n := 1; n.a = 2", "cannot modify constant n"),
+            ("const h := {\"a\": {}}; h.a.b = 2", "cannot modify constant h"),
+            ("const h := {\"a\": {}}; let a := h.a; a.b = 2", "cannot modify a frozen hash"),
+            ("let f := fun() { let q := 1; q.t = 4; q.t }; f()", "4"),
+            ("let p := {}; let f := fun() { p.x = 1; p.x }; [f(), p]", "[1, {}]"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_prototypes() {
+        let account = "let Account := Object.clone();
+Account.balance = 0;
+Account.deposit = fun(amount) { self.balance = self.balance + amount; self.balance };
+Account.owner = fun() { self?.name ?? \"nobody\" };
+let a := Account.clone();
+a.name = \"ada\";";
+        let tests = vec![
+            ("a.balance", "0"),
+            ("a.owner()", "ada"),
+            ("Account.owner()", "nobody"),
+            ("a.deposit(5); a.deposit(7)", "12"),
+            ("a.deposit(5); [a.balance, Account.balance]", "[5, 0]"),
+            ("let b := a.clone(); b.deposit(1); [b.balance, b.name, a.balance]", "[1, ada, 0]"),
+            ("let h := {\"acct\": a}; h.acct.deposit(3); h.acct.balance", "3"),
+            ("delete a.name; a.owner()", "nobody"),
+            ("a.missing", "object has no slot missing"),
+            ("a.missing()", "object has no slot missing"),
+            ("null?.deposit(1)", "Null"),
+            ("const c := Account.clone(); c.deposit(1)", "cannot modify a frozen object"),
+            ("Object.clone()", "Object {}"),
+            ("let o := Object.clone(); o.x = 1; o == Object.clone()", "false"),
+            ("let f := fun() { self }; f()", "Null"),
+            ("let h := {\"n\": 2, \"twice\": fun() { self.n * 2 }}; h.twice()", "4"),
+            ("Account.clone = fun() { 42 }; Account.clone()", "42"),
+            ("Account.rate = 3; Account.owner = fun() { \"changed\" }; [a.rate, a.owner()]", "[3, changed]"),
+            ("let A := Object.clone(); A.v = 1; let b := A.clone(); A.v = 2; b.v", "2"),
+            ("let A := Object.clone(); A.v = 1; let b := A.clone(); b.v = 5; A.v = 2; [b.v, A.v]", "[5, 2]"),
+            ("let b := a; b.name = \"bob\"; a.name", "bob"),
+            ("[a == a, a == a.clone()]", "[true, false]"),
+            ("let o := Object.clone(); o.me = o; o", "Object { me: Object {...} }"),
+            ("let A := Object.clone(); A.v = 1; const c := A; A.v = 2; [c.v, A.v]", "[1, 2]"),
+            ("let o := Object.clone(); o.p = o; const c := o; [c.p == c, c.p == o, c]", "[true, false, Object { p: Object {...} }]"),
+            ("let o := Object.clone(); o.p = o; const c := o; let d := c.p.p; d.x = 1", "cannot modify a frozen object"),
+        ];
+        for (input, expected) in tests {
+            let input = format!("{}\n{}", account, input);
+            assert_eq!(test_eval(&input).to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_clone_errors() {
+        let tests = vec![
+            ("5.clone()", "integer has no slot clone"),
+            ("\"s\".clone()", "string has no slot clone"),
+            ("[1].clone()", "array has no slot clone"),
+            ("{}.clone()", "hash has no slot clone"),
+            ("null.clone()", "null has no slots"),
+            ("struct P { x } P(1).clone()", "P has no field clone"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(eval_error(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_special_slots() {
+        let vector = "let Vec := Object.clone();
+Vec.x = 0;
+Vec.y = 0;
+Vec.at = fun(x, y) { let v := self.clone(); v.x = x; v.y = y; v };
+Vec.__add__ = fun(other) { self.at(self.x + other.x, self.y + other.y) };
+Vec.__eq__ = fun(other) { self.x == other.x };
+Vec.__lt__ = fun(other) { other.x > self.x };
+Vec.__index__ = fun(i) { match i { 0 => self.x, 1 => self.y, _ => null } };
+Vec.__neg__ = fun() { \"negated\" };
+Vec.__str__ = fun() { \"<vec>\" };
+let a := Vec.at(1, 2);
+let b := Vec.at(3, 4);";
+        let tests = vec![
+            ("(a + b).y", "6"),
+            ("a + b", "<vec>"),
+            ("[a == b, a == Vec.at(1, 9), a != b, a != Vec.at(1, 9)]", "[false, true, true, false]"),
+            ("[a < b, b < a]", "[true, false]"),
+            ("[a[0], a[1], a[2]]", "[1, 2, Null]"),
+            ("-a", "negated"),
+            ("[a]", "[<vec>]"),
+            ("a - b", "operator error"),
+            ("Vec.__str__ = fun() { 1 }; let c := Vec.clone(); c.x = 1; c", "Object { x: 1 }"),
+            ("let h := {\"__add__\": fun(n) { n * 10 }}; h + 2", "20"),
+            ("let n := 5; n.__add__ = fun(m) { 0 }; [n + 1, 1 + n]", "[0, 6]"),
+        ];
+        for (input, expected) in tests {
+            let input = format!("{}\n{}", vector, input);
+            assert_eq!(test_eval(&input).to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_operator_fallbacks() {
+        let tests = vec![
+            ("let o := Object.clone(); o < 1", "infix operator not valid for types"),
+            ("let o := Object.clone(); o + o", "operator error"),
+            ("let o := Object.clone(); -o", "operator error"),
+            ("let o := Object.clone(); [o == o, o != o, o == 1]", "[true, false, false]"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_integer_arithmetic() {
+        let tests = vec![
+            ("[1 < 2, 2 < 1, 1 < 1]", "[true, false, false]"),
+            ("[2 > 1, 1 > 2, 1 > 1]", "[true, false, false]"),
+            ("-0", "0"),
+            ("[7 / 2, 7 % 2, 2 - 2, 65535 * 65537]", "[3, 1, 0, 4294967295]"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).to_string(), expected, "{}", input);
+        }
+        let errors = vec![
+            ("-1", "integers can't be negative: -1"),
+            ("1 - 2", "integer overflow: 1 - 2"),
+            ("4294967295 + 1", "integer overflow: 4294967295 + 1"),
+            ("65536 * 65536", "integer overflow: 65536 * 65536"),
+            ("1 / 0", "division by zero: 1 / 0"),
+            ("1 % 0", "division by zero: 1 % 0"),
+            ("let f := fun(n) { 10 / n }; f(0)", "division by zero: 10 / 0"),
+        ];
+        for (input, expected) in errors {
+            assert_eq!(eval_error(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_str_slot_errors() {
+        let object = "let o := Object.clone(); o.x = 1;\n";
+        let tests = vec![
+            ("o.__str__ = fun() { 1 }; o", "Object { __str__: fun(), x: 1 }"),
+            ("o.__str__ = fun() { null }; [o]", "[Object { __str__: fun(), x: 1 }]"),
+            ("o.__str__ = fun() { 1 + true }; str(o)", "Object { __str__: fun(), x: 1 }"),
+            ("o.__str__ = \"text\"; o", "Object { __str__: text, x: 1 }"),
+        ];
+        for (input, expected) in tests {
+            let input = format!("{}{}", object, input);
+            assert_eq!(test_eval(&input).to_string(), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_calling_non_functions() {
+        let tests = vec![
+            ("1(2)", "not a function: integer"),
+            ("let x := \"f\"; x()", "not a function: string"),
+            ("[1](0)", "not a function: array"),
+            ("let f := fun() { undefined_name }; f()", "Error finding key"),
+        ];
+        for (input, expected) in tests {
+            let l = Lexer::new(String::from(input));
+            let mut p = Parser::new(l);
+            let program = p.parse_program();
+            assert_eq!(eval(program, &mut Environment::new()).to_string(), expected, "{}", input);
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! Rewriting traversal of the AST.
+//!
+//! Implement `Folder` and override the methods for the nodes a pass rewrites;
+//! the default methods rebuild every node from its folded children, so an
+//! override can call the matching `fold_*` function first and then inspect
+//! the already-rewritten node.
+
+use super::ast::NodeKind;
+use super::ast::ExpressionKind;
+use super::ast::StatementKind;
+use super::ast::PatternKind;
+use super::ast::MatchArm;
+
+pub trait Folder {
+    fn fold_node(&mut self, node: NodeKind) -> NodeKind {
+        fold_node(self, node)
+    }
+
+    fn fold_program(&mut self, statements: Vec<StatementKind>) -> Vec<StatementKind> {
+        fold_program(self, statements)
+    }
+
+    fn fold_statement(&mut self, statement: StatementKind) -> StatementKind {
+        fold_statement(self, statement)
+    }
+
+    fn fold_expression(&mut self, expression: ExpressionKind) -> ExpressionKind {
+        fold_expression(self, expression)
+    }
+
+    fn fold_match_arm(&mut self, arm: MatchArm) -> MatchArm {
+        fold_match_arm(self, arm)
+    }
+
+    fn fold_pattern(&mut self, pattern: PatternKind) -> PatternKind {
+        fold_pattern(self, pattern)
+    }
+}
+
+fn fold_boxed_expression<F: Folder + ?Sized>(folder: &mut F, mut expression: Box<ExpressionKind>) -> Box<ExpressionKind> {
+    *expression = folder.fold_expression(*expression);
+    expression
+}
+
+fn fold_boxed_statement<F: Folder + ?Sized>(folder: &mut F, mut statement: Box<StatementKind>) -> Box<StatementKind> {
+    *statement = folder.fold_statement(*statement);
+    statement
+}
+
+pub fn fold_node<F: Folder + ?Sized>(folder: &mut F, node: NodeKind) -> NodeKind {
+    match node {
+        NodeKind::ProgramNode{statements} => {
+            NodeKind::ProgramNode{statements: folder.fold_program(statements)}
+        },
+        NodeKind::StatementNode{statementKind} => {
+            NodeKind::StatementNode{statementKind: folder.fold_statement(statementKind)}
+        },
+        NodeKind::ExpressionNode{expressionKind} => {
+            NodeKind::ExpressionNode{expressionKind: folder.fold_expression(expressionKind)}
+        }
+    }
+}
+
+pub fn fold_program<F: Folder + ?Sized>(folder: &mut F, statements: Vec<StatementKind>) -> Vec<StatementKind> {
+    statements.into_iter().map(|s| folder.fold_statement(s)).collect()
+}
+
+pub fn fold_statement<F: Folder + ?Sized>(folder: &mut F, statement: StatementKind) -> StatementKind {
+    match statement {
+        StatementKind::LetStatement{token, name, annotation, value, constant} => {
+            StatementKind::LetStatement{
+                token,
+                name: folder.fold_expression(name),
+                annotation,
+                value: value.map(|v| fold_boxed_expression(folder, v)),
+                constant
+            }
+        },
+        StatementKind::ReturnStatement{token, return_value} => {
+            StatementKind::ReturnStatement{
+                token,
+                return_value: return_value.map(|rv| fold_boxed_statement(folder, rv))
+            }
+        },
+        StatementKind::ExpressionStatement{token, expression} => {
+            StatementKind::ExpressionStatement{
+                token,
+                expression: expression.map(|e| fold_boxed_expression(folder, e))
+            }
+        },
+        StatementKind::BlockStatement{token, statements} => {
+            StatementKind::BlockStatement{
+                token,
+                statements: statements.into_iter().map(|s| fold_boxed_statement(folder, s)).collect()
+            }
+        },
+        StatementKind::StructStatement{token, name, fields} => {
+            StatementKind::StructStatement{
+                token,
+                name: folder.fold_expression(name),
+                fields
+            }
+        },
+        StatementKind::EnumStatement{token, name, variants} => {
+            StatementKind::EnumStatement{
+                token,
+                name: folder.fold_expression(name),
+                variants
+            }
+        },
+        StatementKind::AssignStatement{token, target, value} => {
+            StatementKind::AssignStatement{
+                token,
+                target: folder.fold_expression(target),
+                value: folder.fold_expression(value)
+            }
+        },
+        StatementKind::DeleteStatement{token, target} => {
+            StatementKind::DeleteStatement{
+                token,
+                target: folder.fold_expression(target)
+            }
+        },
+        StatementKind::ImportStatement{token, path, name, alias} => {
+            StatementKind::ImportStatement{
+                token,
+                path,
+                name: folder.fold_expression(name),
+                alias
+            }
+        },
+        StatementKind::ExportStatement{token, statement} => {
+            StatementKind::ExportStatement{
+                token,
+                statement: Box::new(folder.fold_statement(*statement))
+            }
+        }
+    }
+}
+
+pub fn fold_expression<F: Folder + ?Sized>(folder: &mut F, expression: ExpressionKind) -> ExpressionKind {
+    match expression {
+        ExpressionKind::Identifier{..} |
+        ExpressionKind::BooleanExpression{..} |
+        ExpressionKind::StringLiteral{..} |
+        ExpressionKind::IntegerLiteral{..} |
+        ExpressionKind::NullLiteral{..} => {
+            expression
+        },
+        ExpressionKind::PrefixExpression{token, operator, right} => {
+            ExpressionKind::PrefixExpression{
+                token,
+                operator,
+                right: right.map(|r| fold_boxed_expression(folder, r))
+            }
+        },
+        ExpressionKind::InfixExpression{token, left, operator, right} => {
+            ExpressionKind::InfixExpression{
+                token,
+                left: left.map(|l| fold_boxed_expression(folder, l)),
+                operator,
+                right: right.map(|r| fold_boxed_expression(folder, r))
+            }
+        },
+        ExpressionKind::IfExpression{token, condition, consequence, alternative} => {
+            ExpressionKind::IfExpression{
+                token,
+                condition: condition.map(|c| fold_boxed_expression(folder, c)),
+                consequence: consequence.map(|c| fold_boxed_statement(folder, c)),
+                alternative: alternative.map(|a| fold_boxed_statement(folder, a))
+            }
+        },
+        ExpressionKind::FunctionLiteral{token, parameters, annotations, return_type, body} => {
+            ExpressionKind::FunctionLiteral{
+                token,
+                parameters: parameters.into_iter().map(|p| folder.fold_expression(p)).collect(),
+                annotations,
+                return_type,
+                body: fold_boxed_statement(folder, body)
+            }
+        },
+        ExpressionKind::CallExpression{token, function, arguments} => {
+            ExpressionKind::CallExpression{
+                token,
+                function: fold_boxed_expression(folder, function),
+                arguments: arguments.into_iter().map(|a| fold_boxed_expression(folder, a)).collect()
+            }
+        },
+        ExpressionKind::WhileLiteral{token, condition, consequence} => {
+            ExpressionKind::WhileLiteral{
+                token,
+                condition: fold_boxed_expression(folder, condition),
+                consequence: fold_boxed_statement(folder, consequence)
+            }
+        },
+        ExpressionKind::ArrayLiteral{token, elements} => {
+            ExpressionKind::ArrayLiteral{
+                token,
+                elements: elements.into_iter().map(|e| fold_boxed_expression(folder, e)).collect()
+            }
+        },
+        ExpressionKind::IndexExpression{token, left, index, optional} => {
+            ExpressionKind::IndexExpression{
+                token,
+                left: fold_boxed_expression(folder, left),
+                index: index.map(|i| fold_boxed_expression(folder, i)),
+                optional
+            }
+        },
+        ExpressionKind::HashLiteral{token, pairs} => {
+            ExpressionKind::HashLiteral{
+                token,
+                pairs: pairs.into_iter().map(|(k, v)| (fold_boxed_expression(folder, k), fold_boxed_expression(folder, v))).collect()
+            }
+        },
+        ExpressionKind::MatchExpression{token, subject, arms} => {
+            ExpressionKind::MatchExpression{
+                token,
+                subject: fold_boxed_expression(folder, subject),
+                arms: arms.into_iter().map(|a| folder.fold_match_arm(a)).collect()
+            }
+        },
+        ExpressionKind::SlotExpression{token, left, name, optional} => {
+            ExpressionKind::SlotExpression{
+                token,
+                left: fold_boxed_expression(folder, left),
+                name,
+                optional
+            }
+        }
+    }
+}
+
+pub fn fold_match_arm<F: Folder + ?Sized>(folder: &mut F, arm: MatchArm) -> MatchArm {
+    MatchArm {
+        token: arm.token,
+        pattern: folder.fold_pattern(arm.pattern),
+        guard: arm.guard.map(|g| fold_boxed_expression(folder, g)),
+        body: fold_boxed_expression(folder, arm.body)
+    }
+}
+
+pub fn fold_pattern<F: Folder + ?Sized>(folder: &mut F, pattern: PatternKind) -> PatternKind {
+    match pattern {
+        PatternKind::WildcardPattern{..} |
+        PatternKind::BindingPattern{..} => {
+            pattern
+        },
+        PatternKind::LiteralPattern{token, value} => {
+            PatternKind::LiteralPattern{token, value: fold_boxed_expression(folder, value)}
+        },
+        PatternKind::ArrayPattern{token, elements} => {
+            PatternKind::ArrayPattern{
+                token,
+                elements: elements.into_iter().map(|e| folder.fold_pattern(e)).collect()
+            }
+        },
+        PatternKind::HashPattern{token, pairs} => {
+            PatternKind::HashPattern{
+                token,
+                pairs: pairs.into_iter().map(|(k, v)| (folder.fold_expression(k), folder.fold_pattern(v))).collect()
+            }
+        },
+        PatternKind::OrPattern{token, alternatives} => {
+            PatternKind::OrPattern{
+                token,
+                alternatives: alternatives.into_iter().map(|a| folder.fold_pattern(a)).collect()
+            }
+        },
+        PatternKind::VariantPattern{token, enum_name, variant, fields} => {
+            PatternKind::VariantPattern{
+                token,
+                enum_name,
+                variant,
+                fields: fields.map(|fields| fields.into_iter().map(|f| folder.fold_pattern(f)).collect())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::lexer::Lexer;
+    use super::super::parser::Parser;
+
+    struct Renamer;
+
+    impl Folder for Renamer {
+        fn fold_expression(&mut self, expression: ExpressionKind) -> ExpressionKind {
+            match fold_expression(self, expression) {
+                ExpressionKind::Identifier{token, value, binding} => {
+                    ExpressionKind::Identifier{token, value: value.to_uppercase(), binding}
+                },
+                other => other
+            }
+        }
+    }
+
+    #[test]
+    fn test_rewrites_nested_nodes() {
+        let input = "let f := fun(a) { a + b }; f(c)[d] ?? match e { [g] if h => i, _ => j?.k }";
+        let mut p = Parser::new(Lexer::new(String::from(input)));
+        let program = p.parse_program();
+        let folded = Renamer.fold_node(program);
+        assert_eq!(folded.string(), "let F = fun(A)(A + B)((F(C)[D]) ?? match E {[g] if H => I, _ => (J?.k)})");
+    }
+}
@@ -0,0 +1,295 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use super::convert::ToLodestone;
+use super::environment::Environment;
+use super::evaluator;
+use super::lexer::Lexer;
+use super::module::ModuleResolver;
+use super::native::{self, IntoNative};
+use super::object::ObjectKind;
+use super::parser::{Parser, ParseError};
+use super::resolver::{self, ResolveError};
+
+/// Why `Interpreter` could not produce a value.
+#[derive(Debug)]
+pub enum Error {
+    Io(String),
+    /// The module resolver could not find or load a module.
+    Module(String),
+    Parse(Vec<ParseError>),
+    Resolve(Vec<ResolveError>),
+    Runtime(String)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(message) => write!(f, "{}", message),
+            Error::Module(message) => write!(f, "{}", message),
+            Error::Parse(errors) => {
+                let lines: Vec<String> = errors.iter().map(|e| format!("syntax error at {}", e)).collect();
+                write!(f, "{}", lines.join("\n"))
+            },
+            Error::Resolve(errors) => {
+                let lines: Vec<String> = errors.iter().map(|e| format!("error at {}", e)).collect();
+                write!(f, "{}", lines.join("\n"))
+            },
+            Error::Runtime(message) => write!(f, "error: {}", message)
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// A Lodestone program that outlives a single source string: globals defined
+/// by one `eval_str` are visible to the next, and to the host through
+/// `get_global` and `call_function`.
+pub struct Interpreter {
+    env: Environment
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter{env: Environment::new()}
+    }
+
+    /// Parses, resolves and runs `source`, returning the value of its last
+    /// statement.
+    pub fn eval_str(&mut self, source: &str) -> Result<ObjectKind, Error> {
+        let mut p = Parser::new(Lexer::new(source.to_string()));
+        let program = p.parse_program();
+        if !p.errors.is_empty() {
+            return Err(Error::Parse(p.errors));
+        }
+        let globals: Vec<String> = self.env.store.keys().cloned().collect();
+        let program = resolver::resolve(program, &globals).map_err(Error::Resolve)?;
+        runtime(evaluator::eval(program, &mut self.env))
+    }
+
+    /// Runs the file at `path`. Modules it imports are found relative to it.
+    pub fn eval_file<P: AsRef<Path>>(&mut self, path: P) -> Result<ObjectKind, Error> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|e| Error::Io(format!("{}: {}", path.display(), e)))?;
+        self.eval_within(&path.to_string_lossy(), &source)
+    }
+
+    /// Replaces where modules are loaded from. By default they are read from
+    /// disk relative to the working directory.
+    pub fn set_module_resolver<R: ModuleResolver + 'static>(&mut self, resolver: R) {
+        self.env.modules.borrow_mut().set_resolver(Box::new(resolver));
+    }
+
+    /// Runs the module `path`, found through the module resolver, as if its
+    /// code had been passed to `eval_str`. Unlike `import`, its globals are
+    /// all defined here, exported or not.
+    pub fn eval_module(&mut self, path: &str) -> Result<ObjectKind, Error> {
+        let module = self.env.modules.borrow().resolve(path).map_err(Error::Module)?;
+        self.eval_within(&module.name, &module.source)
+    }
+
+    /// Runs `source` as the module `name`, so its imports are relative to it.
+    fn eval_within(&mut self, name: &str, source: &str) -> Result<ObjectKind, Error> {
+        self.env.modules.borrow_mut().enter(name).map_err(Error::Module)?;
+        let result = self.eval_str(source);
+        self.env.modules.borrow_mut().leave();
+        result
+    }
+
+    /// Calls the global function `name`, the way `name(args...)` would.
+    pub fn call_function(&mut self, name: &str, args: Vec<ObjectKind>) -> Result<ObjectKind, Error> {
+        match self.get_global(name) {
+            Some(function) => runtime(evaluator::call_method(function, ObjectKind::Null, args)),
+            None => Err(Error::Runtime(format!("unbound variable {}", name)))
+        }
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<ObjectKind> {
+        self.env.store.get(name).cloned()
+    }
+
+    /// Defines or replaces the global `name`, here and in modules imported
+    /// afterwards. Code already evaluated keeps the value it captured.
+    pub fn set_global<T: ToLodestone>(&mut self, name: &str, value: T) {
+        self.define(name, value.to_lodestone());
+    }
+
+    /// Defines the global `name`, here and in modules, as a call into
+    /// `function`. Arguments are converted with `FromLodestone` after their
+    /// number is checked, and an `Err` from the closure reaches the script as
+    /// an error value.
+    pub fn register_function<Args, F: IntoNative<Args>>(&mut self, name: &str, function: F) {
+        let value = native::native(name, function);
+        self.define(name, value);
+    }
+
+    fn define(&mut self, name: &str, value: ObjectKind) {
+        self.env.modules.borrow_mut().define_global(name, value.clone());
+        self.env.insert(name.to_string(), value);
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+fn runtime(value: ObjectKind) -> Result<ObjectKind, Error> {
+    match value {
+        ObjectKind::Error{message} => Err(Error::Runtime(message)),
+        value => Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::{Interpreter, Error, ObjectKind};
+    use super::super::convert::{ToLodestone, FromLodestone};
+    use super::super::module::MapResolver;
+
+    #[test]
+    fn test_globals_persist() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval_str("let double := fun(x) { x * 2 };").unwrap();
+        interpreter.set_global("base", 20u32);
+        let value = interpreter.eval_str("double(base) + 1").unwrap();
+        assert_eq!(u32::from_lodestone(&value), Ok(41));
+        assert_eq!(interpreter.get_global("base").map(|v| u32::from_lodestone(&v)), Some(Ok(20)));
+        assert!(interpreter.get_global("missing").is_none());
+    }
+
+    #[test]
+    fn test_call_function() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval_str("let add := fun(a, b) { a + b };").unwrap();
+        let sum = interpreter.call_function("add", vec![2u32.to_lodestone(), 3u32.to_lodestone()]).unwrap();
+        assert_eq!(u32::from_lodestone(&sum), Ok(5));
+        let words = interpreter.call_function("add", vec!["a".to_lodestone(), "b".to_lodestone()]).unwrap();
+        assert_eq!(String::from_lodestone(&words), Ok("ab".to_string()));
+
+        match interpreter.call_function("sub", vec![]) {
+            Err(Error::Runtime(message)) => assert_eq!(message, "unbound variable sub"),
+            _ => panic!("expected a runtime error")
+        }
+    }
+
+    #[test]
+    fn test_register_function() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let log = events.clone();
+        let mut interpreter = Interpreter::new();
+        interpreter.register_function("emit", move |event: String| {
+            log.borrow_mut().push(event);
+            Ok(())
+        });
+        interpreter.register_function("user", |id: u32| {
+            match id {
+                1 => Ok(Some("ada".to_string())),
+                2 => Err("user 2 is locked".to_string()),
+                _ => Ok(None)
+            }
+        });
+        interpreter.register_function("answer", || Ok(42u32));
+
+        interpreter.eval_str("emit(\"start\"); emit(user(1));").unwrap();
+        assert_eq!(*events.borrow(), vec!["start".to_string(), "ada".to_string()]);
+        assert_eq!(interpreter.eval_str("user(3)").map(|v| v.type_name()).ok(), Some("null"));
+        assert_eq!(interpreter.eval_str("answer() + arity(user)").map(|v| u32::from_lodestone(&v)).ok(), Some(Ok(43)));
+        assert_eq!(interpreter.eval_str("str(emit)").map(|v| v.to_string()).ok(), Some("native emit".to_string()));
+
+        let errors = vec![
+            ("user(2)", "user: user 2 is locked"),
+            ("user(1, 2)", "user expects 1 argument, got 2"),
+            ("answer(1)", "answer expects 0 arguments, got 1"),
+            ("user(\"ada\")", "user: argument 1: expected integer, found string"),
+            ("emit(user(2))", "user: user 2 is locked"),
+        ];
+        for (input, expected) in errors {
+            match interpreter.eval_str(input) {
+                Err(Error::Runtime(message)) => assert_eq!(message, expected),
+                _ => panic!("expected a runtime error from {}", input)
+            }
+        }
+        assert_eq!(events.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_native_argument_errors() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_function("now", || Ok(0u32));
+        interpreter.register_function("add", |a: u32, b: u32| Ok(a + b));
+        interpreter.register_function("sum5", |a: u32, b: u32, c: u32, d: u32, e: u32| Ok(a + b + c + d + e));
+        interpreter.register_function("greet", |name: String, times: Option<u32>| Ok(name.repeat(times.unwrap_or(1) as usize)));
+
+        let errors = vec![
+            ("now(1)", "now expects 0 arguments, got 1"),
+            ("add(1, 2, 3)", "add expects 2 arguments, got 3"),
+            ("add(1)", "add expects 2 arguments, got 1"),
+            ("sum5(1, 2, 3, 4, 5, 6)", "sum5 expects 5 arguments, got 6"),
+            ("1 |> add(2, 3)", "add expects 2 arguments, got 3"),
+            ("add(1, \"2\")", "add: argument 2: expected integer, found string"),
+            ("greet(\"hi\", true)", "greet: argument 2: expected integer, found boolean"),
+            ("let h := {\"f\": add}; h.f(1, 2, 3)", "add expects 2 arguments, got 3"),
+        ];
+        for (input, expected) in errors {
+            match interpreter.eval_str(input) {
+                Err(Error::Runtime(message)) => assert_eq!(message, expected, "{}", input),
+                _ => panic!("expected a runtime error from {}", input)
+            }
+        }
+        assert_eq!(interpreter.eval_str("greet(\"hi\", null)").map(|v| v.to_string()).ok(), Some("hi".to_string()));
+        assert_eq!(interpreter.eval_str("sum5(1, 2, 3, 4, 5)").map(|v| v.to_string()).ok(), Some("15".to_string()));
+    }
+
+    #[test]
+    fn test_module_resolver() {
+        let mut modules = MapResolver::new();
+        modules.insert("lib/math.ld", "let square := fun(x) { x * x };");
+        let mut interpreter = Interpreter::new();
+        interpreter.set_module_resolver(modules);
+        interpreter.eval_module("lib/math").unwrap();
+        let nine = interpreter.call_function("square", vec![3u32.to_lodestone()]).unwrap();
+        assert_eq!(u32::from_lodestone(&nine), Ok(9));
+
+        match interpreter.eval_module("main.ld") {
+            Err(Error::Module(message)) => assert_eq!(message, "module not found: main.ld"),
+            _ => panic!("expected a module error")
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut interpreter = Interpreter::new();
+        match interpreter.eval_str("let = 1;") {
+            Err(Error::Parse(errors)) => assert!(!errors.is_empty()),
+            _ => panic!("expected a parse error")
+        }
+        match interpreter.eval_str("missing + 1") {
+            Err(Error::Resolve(errors)) => assert_eq!(errors.len(), 1),
+            _ => panic!("expected a resolve error")
+        }
+        match interpreter.eval_str("1 + true") {
+            Err(Error::Runtime(_)) => {},
+            _ => panic!("expected a runtime error")
+        }
+        match interpreter.eval_file("no/such/file.ls") {
+            Err(Error::Io(message)) => assert!(message.starts_with("no/such/file.ls: ")),
+            _ => panic!("expected an io error")
+        }
+    }
+
+    #[test]
+    fn test_error_converts_to_boxed_error() {
+        fn run(source: &str) -> Result<ObjectKind, Box<dyn std::error::Error>> {
+            Ok(Interpreter::new().eval_str(source)?)
+        }
+        assert_eq!(run("1 + 1").ok().map(|v| v.to_string()), Some(String::from("2")));
+        assert_eq!(run("1 + true").err().map(|e| e.to_string()), Some(String::from("error: infix operator not valid for types")));
+    }
+}
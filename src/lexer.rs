@@ -1,10 +1,15 @@
 use super::token;
+use super::operator;
 
 pub struct Lexer {
     input: String,
-    position: u16,
-    read_position: u16,
+    position: usize,
+    read_position: usize,
     ch: char,
+    line: u32,
+    column: u32,
+    last_token_line: u32,
+    pub comments: Vec<token::Comment>,
 }
 
 impl Lexer {
@@ -14,32 +19,40 @@ impl Lexer {
             position: 0,
             read_position: 0,
             ch: 0 as char,
+            line: 1,
+            column: 0,
+            last_token_line: 0,
+            comments: vec![],
         };
         l.read_char();
         return l;
     }
 
     fn read_char(&mut self) {
-        if self.read_position >= self.input.len() as u16 {
-            self.ch = 0 as char;
-        } else {
-            self.ch = self.input.chars().nth(self.read_position as usize).unwrap() as char;
+        if self.ch == '\n' {
+            self.line += 1;
+            self.column = 0;
         }
+        self.column += 1;
+        self.ch = self.char_at(self.read_position);
         self.position = self.read_position;
-        self.read_position += 1;
-        println!(
-            "p: {}, rp: {}, ch: {}",
-            self.position,
-            self.read_position,
-            self.ch
-        );
+        self.read_position += self.ch.len_utf8();
     }
 
     pub fn next_token(&mut self) -> token::Token {
         let tok: token::Token;
-        println!("about to test bws: {}", self.ch);
         self.skip_whitespace();
-        println!("abount to test: {}", self.ch);
+        let span = token::Span { line: self.line, column: self.column };
+        self.last_token_line = self.line;
+        if let Some(symbol) = self.read_custom_operator() {
+            tok = token::Token {
+                t_type: token::TokenType { name: symbol },
+                literal: String::from(symbol),
+                span,
+            };
+            self.read_char();
+            return tok;
+        }
         match self.ch {
             ':' => {
                 if self.peek_char() == '=' {
@@ -50,9 +63,10 @@ impl Lexer {
                     tok = token::Token {
                         t_type: token::ASSIGN,
                         literal: l_literal,
+                        span,
                     };
                 } else {
-                    tok = new_token(token::COLON, self.ch);
+                    tok = new_token(span, token::COLON, self.ch);
                 }
             }
             '!' => {
@@ -64,9 +78,10 @@ impl Lexer {
                     tok = token::Token {
                         t_type: token::NOT_EQ,
                         literal: l_literal,
+                        span,
                     };
                 } else {
-                    tok = new_token(token::BANG, self.ch);
+                    tok = new_token(span, token::BANG, self.ch);
                 }
             }
             '=' => {
@@ -78,64 +93,128 @@ impl Lexer {
                     tok = token::Token {
                         t_type: token::EQ,
                         literal: l_literal,
+                        span,
+                    };
+                } else if self.peek_char() == '>' {
+                    let ch = self.ch;
+                    self.read_char();
+                    let mut l_literal = ch.to_string();
+                    l_literal.push(self.ch);
+                    tok = token::Token {
+                        t_type: token::FAT_ARROW,
+                        literal: l_literal,
+                        span,
                     };
                 } else {
-                    tok = new_token(token::REASSIGN, self.ch);
+                    tok = new_token(span, token::REASSIGN, self.ch);
                 }
             }
             '-' => {
-                tok = new_token(token::MINUS, self.ch);
+                if self.peek_char() == '>' {
+                    let ch = self.ch;
+                    self.read_char();
+                    let mut l_literal = ch.to_string();
+                    l_literal.push(self.ch);
+                    tok = token::Token {
+                        t_type: token::ARROW,
+                        literal: l_literal,
+                        span,
+                    };
+                } else {
+                    tok = new_token(span, token::MINUS, self.ch);
+                }
             }
-            '_' => {
-                tok = new_token(token::UNDERSCORE, self.ch);
+            // `__` starts an identifier, so special slots like `__add__` can be named.
+            '_' if self.peek_char() != '_' => {
+                tok = new_token(span, token::UNDERSCORE, self.ch);
             }
-            '/' => tok = new_token(token::SLASH, self.ch),
+            '/' => tok = new_token(span, token::SLASH, self.ch),
             '*' => {
-                tok = new_token(token::ASTERISK, self.ch);
+                tok = new_token(span, token::ASTERISK, self.ch);
             }
             '<' => {
-                tok = new_token(token::LT, self.ch);
+                tok = new_token(span, token::LT, self.ch);
             }
             '>' => {
-                tok = new_token(token::GT, self.ch);
+                tok = new_token(span, token::GT, self.ch);
             }
             ';' => {
-                tok = new_token(token::SEMICOLON, self.ch);
+                tok = new_token(span, token::SEMICOLON, self.ch);
             }
             '(' => {
-                tok = new_token(token::LPAREN, self.ch);
+                tok = new_token(span, token::LPAREN, self.ch);
             }
             ')' => {
-                tok = new_token(token::RPAREN, self.ch);
+                tok = new_token(span, token::RPAREN, self.ch);
             }
             ',' => {
-                tok = new_token(token::COMMA, self.ch);
+                tok = new_token(span, token::COMMA, self.ch);
             }
             '+' => {
-                tok = new_token(token::PLUS, self.ch);
+                tok = new_token(span, token::PLUS, self.ch);
             }
             '{' => {
-                tok = new_token(token::LBRACE, self.ch);
+                tok = new_token(span, token::LBRACE, self.ch);
             }
             '}' => {
-                tok = new_token(token::RBRACE, self.ch);
+                tok = new_token(span, token::RBRACE, self.ch);
             }
             '.' => {
-                tok = new_token(token::SLOT, self.ch);
+                tok = new_token(span, token::SLOT, self.ch);
             }
             '%' => {
-                tok = new_token(token::MODULO, self.ch);
+                tok = new_token(span, token::MODULO, self.ch);
+            }
+            '|' => {
+                if self.peek_char() == '>' {
+                    let ch = self.ch;
+                    self.read_char();
+                    let mut l_literal = ch.to_string();
+                    l_literal.push(self.ch);
+                    tok = token::Token {
+                        t_type: token::PIPELINE,
+                        literal: l_literal,
+                        span,
+                    };
+                } else {
+                    tok = new_token(span, token::PIPE, self.ch);
+                }
+            }
+            '?' => {
+                let t_type = match self.peek_char() {
+                    '.' => Some(token::OPTIONAL_SLOT),
+                    '[' => Some(token::OPTIONAL_LBRACKET),
+                    '?' => Some(token::COALESCE),
+                    _ => None
+                };
+                match t_type {
+                    Some(t) => {
+                        let ch = self.ch;
+                        self.read_char();
+                        let mut l_literal = ch.to_string();
+                        l_literal.push(self.ch);
+                        tok = token::Token {
+                            t_type: t,
+                            literal: l_literal,
+                            span,
+                        };
+                    },
+                    None => {
+                        tok = new_token(span, token::ILLEGAL, self.ch);
+                    }
+                }
             }
             '[' => {
-                tok = new_token(token::LBRACKET, self.ch);
+                tok = new_token(span, token::LBRACKET, self.ch);
             }
             ']' => {
-                tok = new_token(token::RBRACKET, self.ch);
+                tok = new_token(span, token::RBRACKET, self.ch);
             }
             '"' => {
                 tok = token::Token {
                     t_type: token::STRING,
                     literal: self.read_string(),
+                    span,
                 };
             }
             _ => {
@@ -145,43 +224,73 @@ impl Lexer {
                     tok = token::Token {
                         t_type: l_t_type,
                         literal: l_literal,
+                        span,
                     };
                     return tok;
                 } else if is_digit(self.ch) {
                     tok = token::Token {
                         t_type: token::INT,
                         literal: self.read_number(),
+                        span,
                     };
                     return tok;
                 } else if self.ch == 0 as char {
-                    tok = new_token(token::EOF, self.ch);
+                    tok = new_token(span, token::EOF, self.ch);
                 } else {
-                    tok = new_token(token::ILLEGAL, self.ch);
+                    tok = new_token(span, token::ILLEGAL, self.ch);
                 }
             }
         }
-        println!("{}", tok.literal);
         self.read_char();
         tok
     }
 
+    /// Consumes a host-registered operator starting at the current character,
+    /// leaving the lexer on its last character.
+    fn read_custom_operator(&mut self) -> Option<&'static str> {
+        let symbol = self.input.get(self.position..).and_then(operator::symbol_at)?;
+        for _ in 1..symbol.chars().count() {
+            self.read_char();
+        }
+        Some(symbol)
+    }
+
     fn skip_whitespace(&mut self) {
         let mut done = false;
         while !done {
             if self.ch == ' ' || self.ch == '\t' || self.ch == '\n' || self.ch == '\r' {
                 self.read_char();
+            } else if self.ch == '/' && self.peek_char() == '/' {
+                self.read_comment();
             } else {
                 done = true;
             }
         }
     }
 
-    fn peek_char(&mut self) -> char {
-        if self.read_position >= self.input.len() as u16 {
-            return 0 as char;
-        } else {
-            return self.input.chars().nth(self.read_position as usize).unwrap() as char;
+    fn read_comment(&mut self) {
+        let span = token::Span { line: self.line, column: self.column };
+        self.read_char();
+        self.read_char();
+        let mut text = String::new();
+        while self.ch != '\n' && self.ch != 0 as char {
+            text.push(self.ch);
+            self.read_char();
         }
+        self.comments.push(token::Comment {
+            span,
+            text,
+            trailing: self.last_token_line == span.line,
+        });
+    }
+
+    fn peek_char(&self) -> char {
+        self.char_at(self.read_position)
+    }
+
+    /// The character starting at byte offset `position`, or NUL past the end.
+    fn char_at(&self, position: usize) -> char {
+        self.input.get(position..).and_then(|rest| rest.chars().next()).unwrap_or(0 as char)
     }
 
     fn read_string(&mut self) -> String {
@@ -193,30 +302,20 @@ impl Lexer {
                 done = true;
             }
         }
-        let take_size = self.position - position;
-        return self.input
-            .chars()
-            .skip(position as usize)
-            .take(take_size as usize)
-            .collect();
+        String::from(&self.input[position..self.position])
     }
 
     fn read_identifier(&mut self) -> String {
         let position = self.position;
         let mut done = false;
         while !done {
-            if is_letter(self.ch) {
+            if is_letter(self.ch) || is_digit(self.ch) {
                 self.read_char();
             } else {
                 done = true;
             }
         }
-        let take_size = self.position - position;
-        return self.input
-            .chars()
-            .skip(position as usize)
-            .take(take_size as usize)
-            .collect();
+        String::from(&self.input[position..self.position])
     }
 
     fn read_number(&mut self) -> String {
@@ -229,20 +328,16 @@ impl Lexer {
                 done = true;
             }
         }
-        let take_size = self.position - position;
-        return self.input
-            .chars()
-            .skip(position as usize)
-            .take(take_size as usize)
-            .collect();
+        String::from(&self.input[position..self.position])
     }
 }
 
 
-fn new_token(token_type: token::TokenType, ch: char) -> token::Token {
+fn new_token(span: token::Span, token_type: token::TokenType, ch: char) -> token::Token {
     return token::Token {
         t_type: token_type,
         literal: ch.to_string(),
+        span,
     };
 }
 
@@ -255,6 +350,16 @@ fn is_digit(ch: char) -> bool {
     return '0' <= ch && ch <= '9';
 }
 
+/// Whether `name` is spelled like an identifier: a letter or `_`, then
+/// letters, digits and `_`.
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if is_letter(c) => chars.all(|c| is_letter(c) || is_digit(c)),
+        _ => false
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -278,6 +383,134 @@ mod tests {
         assert_eq!(lex.next_token().t_type, token::UNDERSCORE);
         assert_eq!(lex.next_token().literal, String::from("call"));
         assert_eq!(lex.next_token().literal, String::from(":"));
+        let mut lex = Lexer::new(String::from("_ __add__"));
+        assert_eq!(lex.next_token().t_type, token::UNDERSCORE);
+        let tok = lex.next_token();
+        assert_eq!((tok.t_type, tok.literal), (token::IDENT, String::from("__add__")));
+    }
+
+    #[test]
+    fn test_identifiers_with_digits() {
+        let mut lex = Lexer::new(String::from("log2(x1) 3d"));
+        assert_eq!(lex.next_token().literal, String::from("log2"));
+        assert_eq!(lex.next_token().t_type, token::LPAREN);
+        assert_eq!(lex.next_token().literal, String::from("x1"));
+        assert_eq!(lex.next_token().t_type, token::RPAREN);
+        assert_eq!(lex.next_token().t_type, token::INT);
+        assert_eq!(lex.next_token().literal, String::from("d"));
+        assert!(is_identifier("sha256") && is_identifier("a_b") && !is_identifier("2x") && !is_identifier("a-b"));
+    }
+
+    #[test]
+    fn test_match_tokens() {
+        let mut lex = Lexer::new(String::from("match x { 1 | 2 => _ }"));
+        assert_eq!(lex.next_token().t_type, token::MATCH);
+        assert_eq!(lex.next_token().t_type, token::IDENT);
+        assert_eq!(lex.next_token().t_type, token::LBRACE);
+        assert_eq!(lex.next_token().t_type, token::INT);
+        assert_eq!(lex.next_token().t_type, token::PIPE);
+        assert_eq!(lex.next_token().t_type, token::INT);
+        assert_eq!(lex.next_token().literal, String::from("=>"));
+        assert_eq!(lex.next_token().t_type, token::UNDERSCORE);
+        assert_eq!(lex.next_token().t_type, token::RBRACE);
+    }
+
+    #[test]
+    fn test_pipeline_token() {
+        let mut lex = Lexer::new(String::from("xs |> f()"));
+        assert_eq!(lex.next_token().t_type, token::IDENT);
+        let tok = lex.next_token();
+        assert_eq!(tok.t_type, token::PIPELINE);
+        assert_eq!(tok.literal, String::from("|>"));
+        assert_eq!(lex.next_token().t_type, token::IDENT);
+    }
+
+    #[test]
+    fn test_optional_tokens() {
+        let mut lex = Lexer::new(String::from("a?.b?[0] ?? null ?"));
+        assert_eq!(lex.next_token().t_type, token::IDENT);
+        assert_eq!(lex.next_token().t_type, token::OPTIONAL_SLOT);
+        assert_eq!(lex.next_token().t_type, token::IDENT);
+        assert_eq!(lex.next_token().t_type, token::OPTIONAL_LBRACKET);
+        assert_eq!(lex.next_token().t_type, token::INT);
+        assert_eq!(lex.next_token().t_type, token::RBRACKET);
+        assert_eq!(lex.next_token().t_type, token::COALESCE);
+        assert_eq!(lex.next_token().t_type, token::NULL);
+        assert_eq!(lex.next_token().t_type, token::ILLEGAL);
+    }
+
+    #[test]
+    fn test_token_spans() {
+        let mut lex = Lexer::new(String::from("let x := 5;\n  x + 10"));
+        assert_eq!(lex.next_token().span, token::Span { line: 1, column: 1 });
+        assert_eq!(lex.next_token().span, token::Span { line: 1, column: 5 });
+        assert_eq!(lex.next_token().span, token::Span { line: 1, column: 7 });
+        assert_eq!(lex.next_token().span, token::Span { line: 1, column: 10 });
+        assert_eq!(lex.next_token().span, token::Span { line: 1, column: 11 });
+        assert_eq!(lex.next_token().span, token::Span { line: 2, column: 3 });
+        assert_eq!(lex.next_token().span, token::Span { line: 2, column: 5 });
+        assert_eq!(lex.next_token().span, token::Span { line: 2, column: 7 });
+    }
+
+    #[test]
+    fn test_comments() {
+        let mut lex = Lexer::new(String::from("// header\nlet x := 4 / 2; // two\nx"));
+        let mut types = vec![];
+        loop {
+            let tok = lex.next_token();
+            if tok.t_type == token::EOF {
+                break;
+            }
+            types.push(tok.t_type);
+        }
+        assert_eq!(types, vec![token::LET, token::IDENT, token::ASSIGN, token::INT, token::SLASH, token::INT, token::SEMICOLON, token::IDENT]);
+        assert_eq!(Lexer::new(String::from("a->b - >")).next_token().t_type, token::IDENT);
+        assert_eq!(lex.comments, vec![
+            token::Comment { span: token::Span { line: 1, column: 1 }, text: String::from(" header"), trailing: false },
+            token::Comment { span: token::Span { line: 2, column: 17 }, text: String::from(" two"), trailing: true },
+        ]);
+    }
+
+    #[test]
+    fn test_import_export_tokens() {
+        let mut lex = Lexer::new(String::from("import \"./m\" as m; export x := 1;"));
+        assert_eq!(lex.next_token().t_type, token::IMPORT);
+        assert_eq!(lex.next_token().t_type, token::STRING);
+        let tok = lex.next_token();
+        assert_eq!((tok.t_type, tok.literal), (token::IDENT, String::from("as")));
+        assert_eq!(lex.next_token().t_type, token::IDENT);
+        assert_eq!(lex.next_token().t_type, token::SEMICOLON);
+        assert_eq!(lex.next_token().t_type, token::EXPORT);
+        assert_eq!(lex.next_token().t_type, token::IDENT);
+        assert_eq!(lex.next_token().t_type, token::ASSIGN);
+    }
+
+    #[test]
+    fn test_non_ascii_input() {
+        let mut lex = Lexer::new(String::from("// café\n\"naïve\" ü x"));
+        let tok = lex.next_token();
+        assert_eq!((tok.t_type, tok.literal), (token::STRING, String::from("naïve")));
+        let tok = lex.next_token();
+        assert_eq!((tok.t_type, tok.literal, tok.span), (token::ILLEGAL, String::from("ü"), token::Span { line: 2, column: 9 }));
+        assert_eq!(lex.next_token().span, token::Span { line: 2, column: 11 });
+        assert_eq!(lex.next_token().t_type, token::EOF);
+        assert_eq!(lex.comments[0].text, String::from(" café"));
+    }
+
+    #[test]
+    fn test_long_input() {
+        let mut input = String::from("let x := ");
+        input.push_str(&"1 + ".repeat(20000));
+        input.push_str("1;");
+        let mut lex = Lexer::new(input);
+        let mut count = 0;
+        let mut last = lex.next_token();
+        while last.t_type != token::EOF {
+            count += 1;
+            last = lex.next_token();
+        }
+        assert_eq!(count, 3 + 40001 + 1);
+        assert_eq!(last.span, token::Span { line: 1, column: 80012 });
     }
 
     #[test]
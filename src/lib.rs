@@ -0,0 +1,40 @@
+//! Lodestone as a library: embed the interpreter with `Interpreter`, and move
+//! values across the boundary with `ToLodestone` and `FromLodestone`.
+mod lexer;
+mod token;
+mod ast;
+mod evaluator;
+mod environment;
+mod parser;
+mod object;
+mod operator;
+mod printer;
+mod visit;
+mod fold;
+mod json;
+mod ast_json;
+mod resolver;
+mod lint;
+mod optimize;
+mod check;
+mod builtins;
+mod convert;
+mod native;
+mod module;
+mod package;
+mod interpreter;
+
+/// The `lodestone` binary's front ends, not part of the embedding API.
+#[doc(hidden)]
+pub mod cli;
+#[doc(hidden)]
+pub mod repl;
+
+pub use object::ObjectKind;
+pub use convert::{ToLodestone, FromLodestone};
+pub use interpreter::{Interpreter, Error};
+pub use native::{IntoNative, NativeFunction};
+pub use module::{Module, ModuleResolver, FileResolver, MapResolver};
+pub use package::{Manifest, Package, Packages, PackageResolver};
+pub use builtins::{BuiltinFunction, register_builtin};
+pub use operator::{Associativity, InfixHandler, register_infix_operator};
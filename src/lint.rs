@@ -0,0 +1,594 @@
+//! `lodestone lint`: checks for likely mistakes in a parsed program.
+//!
+//! Rules can be turned off for a project in `lodestone-lint.toml`:
+//!
+//! ```toml
+//! [rules]
+//! shadowing = false
+//! ```
+//!
+//! or for a single line with a comment, either trailing the line or on the
+//! line above it: `// lint: allow(unused-variable, shadowing)`. A comment
+//! `// lint: allow-file(rule)` anywhere turns a rule off for the whole file.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use super::ast::NodeKind;
+use super::ast::ExpressionKind;
+use super::ast::StatementKind;
+use super::ast::MatchArm;
+use super::ast::PatternKind;
+use super::token::Comment;
+use super::token::Span;
+use super::visit;
+use super::visit::Visitor;
+
+pub const CONFIG_FILE: &str = "lodestone-lint.toml";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Rule {
+    UnusedVariable,
+    UnreachableCode,
+    Shadowing,
+    MismatchedComparison,
+    ConstantCondition,
+    NonExhaustiveMatch
+}
+
+pub const ALL_RULES: [Rule; 6] = [
+    Rule::UnusedVariable,
+    Rule::UnreachableCode,
+    Rule::Shadowing,
+    Rule::MismatchedComparison,
+    Rule::ConstantCondition,
+    Rule::NonExhaustiveMatch
+];
+
+impl Rule {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::Shadowing => "shadowing",
+            Rule::MismatchedComparison => "mismatched-comparison",
+            Rule::ConstantCondition => "constant-condition",
+            Rule::NonExhaustiveMatch => "non-exhaustive-match"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        ALL_RULES.iter().find(|r| r.name() == name).cloned()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} [{}]", self.span, self.message, self.rule.name())
+    }
+}
+
+/// Which rules run. Every rule is enabled by default.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LintConfig {
+    disabled: HashSet<Rule>,
+}
+
+impl LintConfig {
+    pub fn new() -> LintConfig {
+        LintConfig { disabled: HashSet::new() }
+    }
+
+    pub fn set_enabled(&mut self, rule: Rule, enabled: bool) {
+        if enabled {
+            self.disabled.remove(&rule);
+        } else {
+            self.disabled.insert(rule);
+        }
+    }
+
+    pub fn is_enabled(&self, rule: Rule) -> bool {
+        !self.disabled.contains(&rule)
+    }
+
+    /// Reads the `[rules]` table of a `lodestone-lint.toml` file: one
+    /// `rule-name = true|false` per line, `#` comments allowed.
+    pub fn parse(text: &str) -> Result<LintConfig, String> {
+        let mut config = LintConfig::new();
+        let mut in_rules = false;
+        for (i, raw) in text.lines().enumerate() {
+            let line = match raw.find('#') {
+                Some(pos) => &raw[..pos],
+                None => raw
+            }.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                in_rules = line == "[rules]";
+                continue;
+            }
+            if !in_rules {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim().trim_matches('"');
+            let value = parts.next().map(|v| v.trim());
+            let rule = match Rule::from_name(key) {
+                Some(r) => r,
+                None => return Err(format!("line {}: unknown lint rule '{}'", i + 1, key))
+            };
+            match value {
+                Some("true") => config.set_enabled(rule, true),
+                Some("false") => config.set_enabled(rule, false),
+                _ => return Err(format!("line {}: expected `{} = true` or `{} = false`", i + 1, key, key))
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Lints `program`, dropping diagnostics for disabled rules and those
+/// suppressed by `// lint: allow(...)` in `comments`.
+pub fn lint(program: &NodeKind, comments: &[Comment], config: &LintConfig) -> Vec<Diagnostic> {
+    let mut linter = Linter { scopes: vec![vec![]], enums: vec![], diagnostics: vec![] };
+    linter.visit_node(program);
+    linter.close_scope();
+    let suppressions = Suppressions::from_comments(comments);
+    let mut diagnostics: Vec<Diagnostic> = linter.diagnostics.into_iter()
+        .filter(|d| config.is_enabled(d.rule) && !suppressions.allows(d.rule, d.span.line))
+        .collect();
+    diagnostics.sort_by_key(|d| (d.span.line, d.span.column));
+    diagnostics
+}
+
+struct Suppressions {
+    file: HashSet<Rule>,
+    lines: Vec<(u32, Rule)>,
+}
+
+impl Suppressions {
+    fn from_comments(comments: &[Comment]) -> Suppressions {
+        let mut suppressions = Suppressions { file: HashSet::new(), lines: vec![] };
+        for c in comments {
+            let text = c.text.trim();
+            if !text.starts_with("lint:") {
+                continue;
+            }
+            let directive = text["lint:".len()..].trim();
+            let (file_wide, list) = if let Some(list) = directive.strip_prefix("allow-file(") {
+                (true, list)
+            } else if let Some(list) = directive.strip_prefix("allow(") {
+                (false, list)
+            } else {
+                continue;
+            };
+            let list = match list.find(')') {
+                Some(end) => &list[..end],
+                None => continue
+            };
+            let line = if c.trailing { c.span.line } else { c.span.line + 1 };
+            for name in list.split(',') {
+                match Rule::from_name(name.trim()) {
+                    Some(rule) if file_wide => {
+                        suppressions.file.insert(rule);
+                    },
+                    Some(rule) => {
+                        suppressions.lines.push((line, rule));
+                    },
+                    None => {}
+                }
+            }
+        }
+        suppressions
+    }
+
+    fn allows(&self, rule: Rule, line: u32) -> bool {
+        self.file.contains(&rule) || self.lines.contains(&(line, rule))
+    }
+}
+
+struct Declaration {
+    name: String,
+    span: Span,
+    used: bool,
+    /// Match bindings are only checked for shadowing.
+    check_unused: bool,
+}
+
+struct Linter {
+    scopes: Vec<Vec<Declaration>>,
+    /// Enums declared so far, with their variant names.
+    enums: Vec<(String, Vec<String>)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter {
+    fn report(&mut self, rule: Rule, span: Span, message: String) {
+        self.diagnostics.push(Diagnostic { rule, span, message });
+    }
+
+    /// Declares a name in the innermost scope. A second `let` of the same
+    /// name in one scope updates the variable rather than shadowing it.
+    fn declare(&mut self, name: &str, span: Span, check_unused: bool) {
+        let outer = self.scopes[..self.scopes.len() - 1].iter().rev()
+            .filter_map(|s| s.iter().rev().find(|d| d.name == name))
+            .map(|d| d.span)
+            .next();
+        let same_scope = self.scopes.last().is_some_and(|s| s.iter().any(|d| d.name == name));
+        if same_scope {
+            let replaced = {
+                let scope = self.scopes.last_mut().unwrap();
+                let index = scope.iter().position(|d| d.name == name).unwrap();
+                scope.remove(index)
+            };
+            self.finish(replaced);
+        } else {
+            if let Some(outer_span) = outer {
+                self.report(Rule::Shadowing, span, format!("'{}' shadows the binding at {}", name, outer_span));
+            }
+        }
+        self.scopes.last_mut().unwrap().push(Declaration {
+            name: String::from(name),
+            span,
+            used: false,
+            check_unused
+        });
+    }
+
+    fn use_name(&mut self, name: &str) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(d) = scope.iter_mut().rev().find(|d| d.name == name) {
+                d.used = true;
+                return;
+            }
+        }
+    }
+
+    fn finish(&mut self, declaration: Declaration) {
+        if declaration.check_unused && !declaration.used {
+            self.report(Rule::UnusedVariable, declaration.span, format!("'{}' is never used", declaration.name));
+        }
+    }
+
+    fn close_scope(&mut self) {
+        if let Some(scope) = self.scopes.pop() {
+            for d in scope {
+                self.finish(d);
+            }
+        }
+    }
+
+    /// Reports a match whose arms pick out variants of a known enum without
+    /// covering all of them or having a catch-all arm.
+    fn check_exhaustive(&mut self, span: Span, arms: &[MatchArm]) {
+        let mut enum_name: Option<&str> = None;
+        let mut covered: Vec<&str> = vec![];
+        for arm in arms {
+            let mut alternatives = vec![];
+            flatten_alternatives(&arm.pattern, &mut alternatives);
+            for p in alternatives {
+                match p {
+                    PatternKind::VariantPattern{enum_name: name, variant, fields, ..} => {
+                        match enum_name {
+                            Some(e) if e != name => return,
+                            _ => enum_name = Some(name)
+                        }
+                        let all_fields = fields.iter().flat_map(|f| f.iter()).all(is_irrefutable);
+                        if arm.guard.is_none() && all_fields {
+                            covered.push(variant);
+                        }
+                    },
+                    p if arm.guard.is_none() && is_irrefutable(p) => return,
+                    _ => {}
+                }
+            }
+        }
+        let name = match enum_name {
+            Some(n) => n,
+            None => return
+        };
+        let missing: Vec<String> = match self.enums.iter().find(|e| e.0 == name) {
+            Some((_, variants)) => variants.iter().filter(|v| !covered.contains(&v.as_str())).cloned().collect(),
+            None => return
+        };
+        if !missing.is_empty() {
+            self.report(Rule::NonExhaustiveMatch, span, format!("match on {} does not cover {}", name, missing.join(", ")));
+        }
+    }
+
+    fn check_unreachable<'a, I: Iterator<Item = &'a StatementKind>>(&mut self, statements: I) {
+        let mut returned = false;
+        for s in statements {
+            if returned {
+                self.report(Rule::UnreachableCode, statement_span(s), String::from("unreachable statement after return"));
+                return;
+            }
+            if let StatementKind::ReturnStatement{..} = s { returned = true }
+        }
+    }
+}
+
+impl Visitor for Linter {
+    fn visit_program(&mut self, statements: &[StatementKind]) {
+        self.check_unreachable(statements.iter());
+        visit::walk_program(self, statements);
+    }
+
+    fn visit_statement(&mut self, statement: &StatementKind) {
+        match statement {
+            StatementKind::LetStatement{name, value, ..} => {
+                if let Some(v) = value { self.visit_expression(v) }
+                if let ExpressionKind::Identifier{token, value, ..} = name { self.declare(value, token.span, true) }
+            },
+            StatementKind::BlockStatement{statements, ..} => {
+                self.check_unreachable(statements.iter().map(|s| &**s));
+                visit::walk_statement(self, statement);
+            },
+            StatementKind::StructStatement{name: ExpressionKind::Identifier{token, value, ..}, ..} => {
+                self.declare(value, token.span, true);
+            },
+            StatementKind::EnumStatement{name: ExpressionKind::Identifier{token, value, ..}, variants, ..} => {
+                self.enums.push((value.clone(), variants.iter().map(|v| v.name.clone()).collect()));
+                self.declare(value, token.span, true);
+            },
+            StatementKind::ImportStatement{name: ExpressionKind::Identifier{token, value, ..}, ..} => {
+                self.declare(value, token.span, true);
+            },
+            StatementKind::ExportStatement{statement, ..} => {
+                // Importers may use an exported name even if the module doesn't.
+                self.visit_statement(statement);
+                if let Some(name) = statement.declared_name() {
+                    self.use_name(name);
+                }
+            },
+            _ => visit::walk_statement(self, statement)
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &ExpressionKind) {
+        match expression {
+            ExpressionKind::Identifier{value, ..} => {
+                self.use_name(value);
+            },
+            ExpressionKind::FunctionLiteral{parameters, body, ..} => {
+                self.scopes.push(vec![]);
+                for p in parameters {
+                    if let ExpressionKind::Identifier{token, value, ..} = p { self.declare(value, token.span, true) }
+                }
+                self.visit_statement(body);
+                self.close_scope();
+            },
+            ExpressionKind::IfExpression{token, condition: Some(condition), ..} => {
+                if is_constant(condition) {
+                    self.report(Rule::ConstantCondition, token.span, format!("if condition {} is constant", condition.clone().string()));
+                }
+                visit::walk_expression(self, expression);
+            },
+            ExpressionKind::InfixExpression{token, operator, left: Some(left), right: Some(right)} => {
+                match (literal_type(left), literal_type(right)) {
+                    (Some(l), Some(r)) if l != r && is_comparison(operator) => {
+                        let outcome = match operator.as_ref() {
+                            "==" => "is always false",
+                            "!=" => "is always true",
+                            _ => "fails at runtime"
+                        };
+                        self.report(Rule::MismatchedComparison, token.span, format!("comparing {} with {} {}", l, r, outcome));
+                    },
+                    _ => {}
+                }
+                visit::walk_expression(self, expression);
+            },
+            ExpressionKind::MatchExpression{token, arms, ..} => {
+                self.check_exhaustive(token.span, arms);
+                visit::walk_expression(self, expression);
+            },
+            _ => visit::walk_expression(self, expression)
+        }
+    }
+
+    fn visit_pattern(&mut self, pattern: &PatternKind) {
+        if let PatternKind::VariantPattern{enum_name, ..} = pattern { self.use_name(enum_name) }
+        visit::walk_pattern(self, pattern);
+    }
+
+    fn visit_match_arm(&mut self, arm: &MatchArm) {
+        // Literal patterns are evaluated outside the arm's scope.
+        self.visit_pattern(&arm.pattern);
+        self.scopes.push(vec![]);
+        let mut bindings = vec![];
+        pattern_bindings(&arm.pattern, &mut bindings);
+        for (name, span) in bindings {
+            self.declare(&name, span, false);
+        }
+        if let Some(ref g) = arm.guard { self.visit_expression(g) }
+        self.visit_expression(&arm.body);
+        self.close_scope();
+    }
+}
+
+/// Binding names with the span of their first occurrence.
+fn pattern_bindings(pattern: &PatternKind, bindings: &mut Vec<(String, Span)>) {
+    match pattern {
+        PatternKind::BindingPattern{token, name} => {
+            if !bindings.iter().any(|(n, _)| n == name) {
+                bindings.push((name.clone(), token.span));
+            }
+        },
+        PatternKind::ArrayPattern{elements, ..} => {
+            for e in elements {
+                pattern_bindings(e, bindings);
+            }
+        },
+        PatternKind::HashPattern{pairs, ..} => {
+            for (_, p) in pairs {
+                pattern_bindings(p, bindings);
+            }
+        },
+        PatternKind::OrPattern{alternatives, ..} => {
+            for a in alternatives {
+                pattern_bindings(a, bindings);
+            }
+        },
+        PatternKind::VariantPattern{fields: Some(fields), ..} => {
+            for f in fields {
+                pattern_bindings(f, bindings);
+            }
+        },
+        PatternKind::VariantPattern{fields: None, ..} |
+        PatternKind::WildcardPattern{..} |
+        PatternKind::LiteralPattern{..} => {}
+    }
+}
+
+fn flatten_alternatives<'a>(pattern: &'a PatternKind, out: &mut Vec<&'a PatternKind>) {
+    match pattern {
+        PatternKind::OrPattern{alternatives, ..} => {
+            for a in alternatives {
+                flatten_alternatives(a, out);
+            }
+        },
+        other => out.push(other)
+    }
+}
+
+fn is_irrefutable(pattern: &PatternKind) -> bool {
+    matches!(pattern, PatternKind::WildcardPattern{..} | PatternKind::BindingPattern{..})
+}
+
+fn statement_span(statement: &StatementKind) -> Span {
+    match statement {
+        StatementKind::LetStatement{token, ..} |
+        StatementKind::ReturnStatement{token, ..} |
+        StatementKind::ExpressionStatement{token, ..} |
+        StatementKind::BlockStatement{token, ..} |
+        StatementKind::StructStatement{token, ..} |
+        StatementKind::EnumStatement{token, ..} |
+        StatementKind::AssignStatement{token, ..} |
+        StatementKind::DeleteStatement{token, ..} |
+        StatementKind::ImportStatement{token, ..} |
+        StatementKind::ExportStatement{token, ..} => token.span
+    }
+}
+
+fn is_comparison(operator: &str) -> bool {
+    matches!(operator, "==" | "!=" | "<" | ">")
+}
+
+fn literal_type(expression: &ExpressionKind) -> Option<&'static str> {
+    match expression {
+        ExpressionKind::IntegerLiteral{..} => Some("integer"),
+        ExpressionKind::StringLiteral{..} => Some("string"),
+        ExpressionKind::BooleanExpression{..} => Some("boolean"),
+        ExpressionKind::NullLiteral{..} => Some("null"),
+        ExpressionKind::ArrayLiteral{..} => Some("array"),
+        ExpressionKind::HashLiteral{..} => Some("hash"),
+        ExpressionKind::FunctionLiteral{..} => Some("function"),
+        _ => None
+    }
+}
+
+/// Whether an expression is built only from literals and operators, so it
+/// has the same value every time it runs.
+fn is_constant(expression: &ExpressionKind) -> bool {
+    match expression {
+        ExpressionKind::IntegerLiteral{..} |
+        ExpressionKind::StringLiteral{..} |
+        ExpressionKind::BooleanExpression{..} |
+        ExpressionKind::NullLiteral{..} => true,
+        ExpressionKind::PrefixExpression{right: Some(r), ..} => is_constant(r),
+        ExpressionKind::InfixExpression{operator, left: Some(l), right: Some(r), ..} => {
+            operator != "|>" && is_constant(l) && is_constant(r)
+        },
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::lexer::Lexer;
+    use super::super::parser::Parser;
+
+    fn lint_source(input: &str, config: &LintConfig) -> Vec<String> {
+        let mut p = Parser::new(Lexer::new(String::from(input)));
+        let program = p.parse_program();
+        assert_eq!(p.errors.len(), 0);
+        lint(&program, p.comments(), config).iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn test_rules() {
+        let input = "let unused := 1;
+let f := fun(a, b) { return a; 0 };
+let g := fun(f) { f };
+if (1 > 2) { g(1) == \"1\" };
+match f(1, 2) { x => x };
+let x := 1;
+x";
+        assert_eq!(lint_source(input, &LintConfig::new()), vec![
+            "1:5: 'unused' is never used [unused-variable]",
+            "2:17: 'b' is never used [unused-variable]",
+            "2:32: unreachable statement after return [unreachable-code]",
+            "3:14: 'f' shadows the binding at 2:5 [shadowing]",
+            "4:1: if condition (1 > 2) is constant [constant-condition]",
+        ]);
+        assert_eq!(lint_source("1 == \"1\"; null != 2; true > 1", &LintConfig::new()), vec![
+            "1:3: comparing integer with string is always false [mismatched-comparison]",
+            "1:16: comparing null with integer is always true [mismatched-comparison]",
+            "1:27: comparing boolean with integer fails at runtime [mismatched-comparison]",
+        ]);
+    }
+
+    #[test]
+    fn test_non_exhaustive_match() {
+        let input = "enum Shape { Circle(r), Rect(w, h), Empty }
+let area := fun(s) { match s { Shape.Circle(r) => r * r * 3, Shape.Rect(w, 1) => w } };
+let name := fun(s) { match s { Shape.Circle(_) | Shape.Rect => \"round or square\", Shape.Empty if true => \"\" } };
+let total := fun(s) { match s { Shape.Circle(r) => r, _ => 0 } };
+[area, name, total]";
+        assert_eq!(lint_source(input, &LintConfig::new()), vec![
+            "2:22: match on Shape does not cover Rect, Empty [non-exhaustive-match]",
+            "3:22: match on Shape does not cover Empty [non-exhaustive-match]",
+        ]);
+        assert_eq!(lint_source("match x { Other.A => 1 }", &LintConfig::new()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_exports_count_as_used() {
+        let input = "import \"lib/util\";
+import \"lib/math\" as m;
+export let area := fun(r) { m.square(r) * 3 };
+export struct Point { x, y }
+let helper := 1;";
+        assert_eq!(lint_source(input, &LintConfig::new()), vec![
+            "1:8: 'util' is never used [unused-variable]",
+            "5:5: 'helper' is never used [unused-variable]",
+        ]);
+    }
+
+    #[test]
+    fn test_relet_updates_instead_of_shadowing() {
+        assert_eq!(lint_source("let n := 1; let n := n + 1; n", &LintConfig::new()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_config_and_suppressions() {
+        let config = LintConfig::parse("# project lints\n[rules]\nunused-variable = false\n").unwrap();
+        assert_eq!(lint_source("let x := 1;", &config), Vec::<String>::new());
+        assert!(LintConfig::parse("[rules]\nno-such-rule = true").is_err());
+        let input = "// lint: allow(unused-variable)
+let a := 1;
+let b := 2; // lint: allow(unused-variable)
+let c := 3;";
+        assert_eq!(lint_source(input, &LintConfig::new()), vec!["4:5: 'c' is never used [unused-variable]"]);
+        assert_eq!(lint_source("// lint: allow-file(unused-variable)\nlet a := 1;", &LintConfig::new()), Vec::<String>::new());
+    }
+}
@@ -1,14 +1,16 @@
-/// mods needed to import in other files.
-mod repl;
-mod lexer;
-mod token;
-mod ast;
-mod evaluator;
-mod environment;
-mod parser;
-mod object;
+extern crate lodestone;
+
+use std::env;
+use std::process;
+
+use lodestone::{cli, repl};
 
 fn main() {
-    println!("Welcome to Lodestone:");
-    repl::repl();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        println!("Welcome to Lodestone:");
+        repl::repl();
+        return;
+    }
+    process::exit(cli::run(args));
 }
@@ -0,0 +1,449 @@
+//! Modules: how `import` finds source through a `ModuleResolver`, runs each
+//! module once in its own environment, and hands out its exports.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use super::ast::NodeKind;
+use super::ast::StatementKind;
+use super::environment::Environment;
+use super::evaluator;
+use super::lexer::Lexer;
+use super::object::ObjectKind;
+use super::parser::Parser;
+use super::resolver;
+
+/// The extension tried when an import names a module without one.
+pub const EXTENSION: &str = "ld";
+
+/// Module source, under the name it is cached and reported by.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Module {
+    pub name: String,
+    pub source: String
+}
+
+/// Where imported code comes from. The interpreter never reads a module any
+/// other way, so a host that installs its own resolver decides exactly what
+/// scripts can load.
+pub trait ModuleResolver {
+    /// Finds `path` as written in the module named `importer`, or in code
+    /// handed straight to the interpreter when there is none.
+    fn resolve(&self, path: &str, importer: Option<&str>) -> Result<Module, String>;
+}
+
+/// Reads modules from disk. Paths are relative to the importing module, and
+/// to `root` for code that isn't a module itself.
+pub struct FileResolver {
+    root: String
+}
+
+impl FileResolver {
+    pub fn new(root: &str) -> FileResolver {
+        FileResolver{root: root.to_string()}
+    }
+}
+
+impl ModuleResolver for FileResolver {
+    fn resolve(&self, path: &str, importer: Option<&str>) -> Result<Module, String> {
+        let base = match importer {
+            Some(importer) => parent(importer),
+            None => self.root.clone()
+        };
+        for name in candidates(&join(&base, path)) {
+            if Path::new(&name).is_file() {
+                return match fs::read_to_string(&name) {
+                    Ok(source) => Ok(Module{name, source}),
+                    Err(e) => Err(format!("{}: {}", name, e))
+                };
+            }
+        }
+        Err(format!("module not found: {}", path))
+    }
+}
+
+/// Serves modules from memory, keyed by `/`-separated names such as
+/// `"lib/math.ld"`. Relative imports work the same way as on disk.
+pub struct MapResolver {
+    modules: HashMap<String, String>
+}
+
+impl MapResolver {
+    pub fn new() -> MapResolver {
+        MapResolver{modules: HashMap::new()}
+    }
+
+    pub fn insert(&mut self, name: &str, source: &str) {
+        self.modules.insert(join("", name), source.to_string());
+    }
+}
+
+impl Default for MapResolver {
+    fn default() -> MapResolver {
+        MapResolver::new()
+    }
+}
+
+impl ModuleResolver for MapResolver {
+    fn resolve(&self, path: &str, importer: Option<&str>) -> Result<Module, String> {
+        let base = importer.map(parent).unwrap_or_default();
+        for name in candidates(&join(&base, path)) {
+            if let Some(source) = self.modules.get(&name) {
+                return Ok(Module{name, source: source.clone()});
+            }
+        }
+        Err(format!("module not found: {}", path))
+    }
+}
+
+/// The module state an environment shares with every environment created
+/// from it, including those modules run in.
+pub struct Loader {
+    resolver: Box<dyn ModuleResolver>,
+    /// Globals the host defined, which every module can use.
+    globals: HashMap<String, ObjectKind>,
+    /// Each module's exports by name, once it has run.
+    cache: HashMap<String, ObjectKind>,
+    /// The modules being run, outermost first. The last one is importing.
+    loading: Vec<String>
+}
+
+impl Loader {
+    pub fn new(resolver: Box<dyn ModuleResolver>) -> Loader {
+        Loader{resolver, globals: HashMap::new(), cache: HashMap::new(), loading: vec![]}
+    }
+
+    /// Defines `name` in every module run from now on, as well as in the
+    /// environment the host evaluates code in.
+    pub fn define_global(&mut self, name: &str, value: ObjectKind) {
+        self.globals.insert(name.to_string(), value);
+    }
+
+    /// Replaces the resolver. Modules already loaded stay cached.
+    pub fn set_resolver(&mut self, resolver: Box<dyn ModuleResolver>) {
+        self.resolver = resolver;
+    }
+
+    /// Finds `path` as imported from the module currently running.
+    pub fn resolve(&self, path: &str) -> Result<Module, String> {
+        self.resolver.resolve(path, self.loading.last().map(|s| s.as_str()))
+    }
+
+    /// Marks `name` as running, so imports in it are relative to it. Fails
+    /// if it is already running, which means the imports form a cycle.
+    /// `name` is normalized like the names resolvers give imports, so an
+    /// entry file run as `./a.ld` is the module `a.ld` imports refer to.
+    pub fn enter(&mut self, name: &str) -> Result<(), String> {
+        let name = &join("", name);
+        match self.loading.iter().position(|n| n == name) {
+            Some(start) => {
+                let mut chain = self.loading[start..].to_vec();
+                chain.push(name.to_string());
+                Err(format!("import cycle: {}", chain.join(" -> ")))
+            },
+            None => {
+                self.loading.push(name.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    pub fn leave(&mut self) {
+        self.loading.pop();
+    }
+}
+
+/// The exports of the module `path`, running it first unless an earlier
+/// import already has. Errors name the module they happened in.
+pub fn import(path: &str, env: &Environment) -> ObjectKind {
+    let loader = env.modules.clone();
+    let module = match loader.borrow().resolve(path) {
+        Ok(module) => module,
+        Err(message) => {
+            return ObjectKind::Error{message};
+        }
+    };
+    if let Some(exports) = loader.borrow().cache.get(&module.name) {
+        return exports.clone();
+    }
+    if let Err(message) = loader.borrow_mut().enter(&module.name) {
+        return ObjectKind::Error{message};
+    }
+    let result = run(&module, &loader);
+    loader.borrow_mut().leave();
+    match result {
+        Ok(exports) => {
+            loader.borrow_mut().cache.insert(module.name, exports.clone());
+            exports
+        },
+        Err(message) => ObjectKind::Error{message}
+    }
+}
+
+fn run(module: &Module, loader: &Rc<RefCell<Loader>>) -> Result<ObjectKind, String> {
+    let mut p = Parser::new(Lexer::new(module.source.clone()));
+    let program = p.parse_program();
+    if let Some(error) = p.errors.first() {
+        return Err(format!("{}:{}", module.name, error));
+    }
+    let mut env = Environment::with_modules(loader.clone());
+    for (name, value) in &loader.borrow().globals {
+        env.insert(name.clone(), value.clone());
+    }
+    let globals: Vec<String> = env.store.keys().cloned().collect();
+    let program = match resolver::resolve(program, &globals) {
+        Ok(program) => program,
+        Err(errors) => {
+            return Err(format!("{}:{}", module.name, errors[0]));
+        }
+    };
+    let names = exports(&program);
+    if let ObjectKind::Error{message} = evaluator::eval(program, &mut env) {
+        return Err(format!("{}: {}", module.name, message));
+    }
+    let slots = names.into_iter().filter_map(|name| {
+        let value = env.store.get(&name).cloned();
+        value.map(|v| (name, v))
+    }).collect();
+    Ok(ObjectKind::Module{name: module.name.clone(), slots})
+}
+
+/// The names a program's top-level `export`s bind.
+fn exports(program: &NodeKind) -> Vec<String> {
+    match program {
+        NodeKind::ProgramNode{statements} => statements.iter().filter_map(|s| match s {
+            StatementKind::ExportStatement{statement, ..} => statement.declared_name().map(String::from),
+            _ => None
+        }).collect(),
+        _ => vec![]
+    }
+}
+
+/// The directory part of a module name.
+pub fn parent(name: &str) -> String {
+    match name.rfind('/') {
+        Some(i) => name[..i].to_string(),
+        None => String::new()
+    }
+}
+
+/// The file name in `path` without its extension: `math` for `"lib/math.ld"`.
+pub fn stem(path: &str) -> String {
+    let file = path.rsplit('/').next().unwrap_or(path);
+    match file.rfind('.') {
+        Some(i) if i > 0 => file[..i].to_string(),
+        _ => file.to_string()
+    }
+}
+
+/// `path` relative to the directory `base`, with `.` and `..` worked out.
+pub fn join(base: &str, path: &str) -> String {
+    let full = if path.starts_with('/') || base.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}/{}", base, path)
+    };
+    let mut parts: Vec<&str> = Vec::new();
+    for part in full.split('/') {
+        match part {
+            "." => {},
+            "" if !parts.is_empty() => {},
+            ".." if !parts.is_empty() && parts[parts.len() - 1] != ".." && !parts[parts.len() - 1].is_empty() => {
+                parts.pop();
+            },
+            part => parts.push(part)
+        }
+    }
+    let joined = parts.join("/");
+    if joined.is_empty() { String::from(".") } else { joined }
+}
+
+/// The names to try for `name`: itself, then with the default extension if
+/// its last part has none.
+fn candidates(name: &str) -> Vec<String> {
+    let mut names = vec![name.to_string()];
+    let last = name.rsplit('/').next().unwrap_or(name);
+    if !last.contains('.') {
+        names.push(format!("{}.{}", name, EXTENSION));
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs;
+    use std::rc::Rc;
+
+    use super::{join, ModuleResolver, FileResolver, MapResolver};
+    use super::super::interpreter::{Interpreter, Error};
+
+    fn run_in(interpreter: &mut Interpreter, modules: &[(&str, &str)], main: &str) -> Result<String, String> {
+        let mut resolver = MapResolver::new();
+        for &(name, source) in modules {
+            resolver.insert(name, source);
+        }
+        interpreter.set_module_resolver(resolver);
+        match interpreter.eval_module(main) {
+            Ok(value) => Ok(value.to_string()),
+            Err(Error::Runtime(message)) => Err(message),
+            Err(other) => Err(other.to_string())
+        }
+    }
+
+    fn run(modules: &[(&str, &str)], main: &str) -> Result<String, String> {
+        run_in(&mut Interpreter::new(), modules, main)
+    }
+
+    #[test]
+    fn test_import() {
+        let modules = [
+            ("main.ld", "import \"lib/shapes\"; import \"lib/area.ld\" as a; a.square(shapes.side) + shapes.count"),
+            ("lib/shapes.ld", "import \"./area\"; export const side := 3; export let count := area.runs; let hidden := 1;"),
+            ("lib/area.ld", "count_run(); export let square := fun(x) { x * x }; export let runs := 1;"),
+        ];
+        let runs = Rc::new(Cell::new(0));
+        let counting = |runs: &Rc<Cell<u32>>| {
+            let counter = runs.clone();
+            let mut interpreter = Interpreter::new();
+            interpreter.register_function("count_run", move || {
+                counter.set(counter.get() + 1);
+                Ok(())
+            });
+            interpreter
+        };
+        assert_eq!(run_in(&mut counting(&runs), &modules, "main"), Ok(String::from("10")));
+        // Both imports of `lib/area.ld` share one run.
+        assert_eq!(runs.get(), 1);
+
+        let mut hidden = modules.to_vec();
+        hidden[0].1 = "import \"lib/shapes\"; shapes.hidden";
+        assert_eq!(run_in(&mut counting(&runs), &hidden, "main"), Err(String::from("module lib/shapes.ld does not export hidden")));
+        hidden[0].1 = "import \"lib/shapes\"; shapes.side = 4;";
+        assert_eq!(run_in(&mut counting(&runs), &hidden, "main"), Err(String::from("cannot modify a frozen module")));
+        hidden[0].1 = "import \"lib/shapes\"; type(shapes) + \" \" + str(shapes)";
+        assert_eq!(run_in(&mut counting(&runs), &hidden, "main"), Ok(String::from("module module lib/shapes.ld")));
+    }
+
+    #[test]
+    fn test_host_globals_in_modules() {
+        let modules = [
+            ("main.ld", "import \"lib\"; lib.f(\"ada\")"),
+            ("lib.ld", "export let f := fun(name) { lookup(name) + base };"),
+        ];
+        let mut ages = HashMap::new();
+        ages.insert(String::from("ada"), 36u32);
+        let mut interpreter = Interpreter::new();
+        interpreter.register_function("lookup", move |name: String| {
+            ages.get(&name).cloned().ok_or_else(|| format!("no one called {}", name))
+        });
+        interpreter.set_global("base", 100u32);
+        assert_eq!(run_in(&mut interpreter, &modules, "main"), Ok(String::from("136")));
+        // Without the host's definitions the module doesn't resolve.
+        assert_eq!(run(&modules, "main"), Err(String::from("lib.ld:1:29: undefined variable 'lookup'")));
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let cycle = [
+            ("a.ld", "import \"b\";"),
+            ("b.ld", "import \"c\";"),
+            ("c.ld", "import \"a\";"),
+        ];
+        assert_eq!(run(&cycle, "a"), Err(String::from("b.ld: c.ld: import cycle: a.ld -> b.ld -> c.ld -> a.ld")));
+        assert_eq!(run(&[("a.ld", "import \"a\";")], "a"), Err(String::from("import cycle: a.ld -> a.ld")));
+
+        let broken = [
+            ("main.ld", "import \"lib/broken\";"),
+            ("lib/broken.ld", "let x := ;"),
+            ("lib/failing.ld", "export let x := 1 + true;"),
+            ("lib/undefined.ld", "export let x := y;"),
+        ];
+        assert_eq!(run(&broken, "main"), Err(String::from("lib/broken.ld:1:10: expected expression, found ';'")));
+        let mut failing = broken.to_vec();
+        failing[0].1 = "import \"lib/failing\";";
+        assert!(run(&failing, "main").unwrap_err().starts_with("lib/failing.ld: "));
+        failing[0].1 = "import \"lib/undefined\";";
+        assert_eq!(run(&failing, "main"), Err(String::from("lib/undefined.ld:1:17: undefined variable 'y'")));
+        failing[0].1 = "import \"lib/missing\";";
+        assert_eq!(run(&failing, "main"), Err(String::from("module not found: lib/missing")));
+    }
+
+    #[test]
+    fn test_dotted_entry_file() {
+        let root = env::temp_dir().join(format!("lodestone-entry-test-{}", ::std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.ld"), "count_run(); import \"b\";").unwrap();
+        fs::write(root.join("b.ld"), "import \"a\";").unwrap();
+        fs::write(root.join("main.ld"), "count_run(); import \"util\"; util.x").unwrap();
+        fs::write(root.join("util.ld"), "export let x := 7;").unwrap();
+        let root = root.to_str().unwrap().to_string();
+
+        let runs = Rc::new(Cell::new(0));
+        let counter = runs.clone();
+        let mut interpreter = Interpreter::new();
+        interpreter.register_function("count_run", move || {
+            counter.set(counter.get() + 1);
+            Ok(())
+        });
+        match interpreter.eval_file(format!("{}/./a.ld", root)) {
+            Err(Error::Runtime(message)) => {
+                assert_eq!(message, format!("{0}/b.ld: import cycle: {0}/a.ld -> {0}/b.ld -> {0}/a.ld", root));
+            },
+            other => panic!("expected an import cycle, got {:?}", other.map(|v| v.to_string()))
+        }
+        assert_eq!(runs.get(), 1);
+        let seven = interpreter.eval_file(format!("{}/./main.ld", root)).map(|v| v.to_string());
+        assert_eq!(seven.ok(), Some(String::from("7")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(join("lib", "math.ld"), "lib/math.ld");
+        assert_eq!(join("lib/util", "../math"), "lib/math");
+        assert_eq!(join("lib", "./a/./b.ld"), "lib/a/b.ld");
+        assert_eq!(join("", "../up.ld"), "../up.ld");
+        assert_eq!(join(".", "main.ld"), "main.ld");
+        assert_eq!(join("/srv/app", "x.ld"), "/srv/app/x.ld");
+        assert_eq!(join("lib", "/abs/x.ld"), "/abs/x.ld");
+    }
+
+    #[test]
+    fn test_map_resolver() {
+        let mut modules = MapResolver::new();
+        modules.insert("main.ld", "import \"lib/math\";");
+        modules.insert("lib/math.ld", "import \"./util.ld\";");
+        modules.insert("lib/util.ld", "1");
+
+        let math = modules.resolve("lib/math", None).unwrap();
+        assert_eq!(math.name, "lib/math.ld");
+        assert_eq!(math.source, "import \"./util.ld\";");
+        assert_eq!(modules.resolve("util", Some("lib/math.ld")).unwrap().name, "lib/util.ld");
+        assert_eq!(modules.resolve("../main", Some("lib/math.ld")).unwrap().name, "main.ld");
+        assert_eq!(modules.resolve("util", None), Err("module not found: util".to_string()));
+    }
+
+    #[test]
+    fn test_file_resolver() {
+        let root = env::temp_dir().join(format!("lodestone-module-test-{}", ::std::process::id()));
+        fs::create_dir_all(root.join("lib")).unwrap();
+        fs::write(root.join("lib").join("math.ld"), "let pi := 3;").unwrap();
+        let root = root.to_str().unwrap().to_string();
+
+        let files = FileResolver::new(&root);
+        let math = files.resolve("lib/math", None).unwrap();
+        assert_eq!(math.name, format!("{}/lib/math.ld", root));
+        assert_eq!(math.source, "let pi := 3;");
+        assert_eq!(files.resolve("math.ld", Some(&math.name)).unwrap().name, math.name);
+        assert_eq!(files.resolve("lib", None), Err("module not found: lib".to_string()));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,51 @@
+use std::rc::Rc;
+
+use super::convert::{ToLodestone, FromLodestone};
+use super::object::ObjectKind;
+
+/// A host closure once its arguments have been counted: it converts the
+/// Lodestone arguments itself and returns either a value or an error message.
+pub type NativeFunction = Rc<dyn Fn(&[ObjectKind]) -> Result<ObjectKind, String>>;
+
+/// Rust closures that can be called from Lodestone. Implemented for closures
+/// of up to five arguments whose parameters are `FromLodestone` and whose
+/// result is a `Result` of something `ToLodestone`; `Args` is the tuple of
+/// parameter types and only there to keep the impls apart.
+pub trait IntoNative<Args> {
+    /// The number of arguments and the converting wrapper.
+    fn into_native(self) -> (usize, NativeFunction);
+}
+
+fn argument<T: FromLodestone>(args: &[ObjectKind], index: usize) -> Result<T, String> {
+    T::from_lodestone(&args[index]).map_err(|e| format!("argument {}: {}", index + 1, e))
+}
+
+macro_rules! into_native {
+    ($count:expr; $($arg:ident $index:expr),*) => {
+        impl<F, R, $($arg),*> IntoNative<($($arg,)*)> for F
+            where F: Fn($($arg),*) -> Result<R, String> + 'static,
+                  R: ToLodestone,
+                  $($arg: FromLodestone),*
+        {
+            fn into_native(self) -> (usize, NativeFunction) {
+                let function = move |_args: &[ObjectKind]| {
+                    self($(argument::<$arg>(_args, $index)?),*).map(ToLodestone::to_lodestone)
+                };
+                ($count, Rc::new(function))
+            }
+        }
+    }
+}
+
+into_native!(0;);
+into_native!(1; A 0);
+into_native!(2; A 0, B 1);
+into_native!(3; A 0, B 1, C 2);
+into_native!(4; A 0, B 1, C 2, D 3);
+into_native!(5; A 0, B 1, C 2, D 3, E 4);
+
+/// Wraps `function` as a value scripts can call as `name(...)`.
+pub fn native<Args, F: IntoNative<Args>>(name: &str, function: F) -> ObjectKind {
+    let (arity, function) = function.into_native();
+    ObjectKind::Native{name: name.to_string(), arity, function}
+}
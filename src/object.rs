@@ -1,24 +1,67 @@
+use std::cell::RefCell;
 use std::fmt;
 use std::collections::HashMap;
 use std::mem::discriminant;
+use std::rc::Rc;
 
 use super::ast::NodeKind;
 use super::ast::StatementKind;
 use super::ast::ExpressionKind;
 
 use super::environment::Environment;
+use super::evaluator;
+use super::builtins::BuiltinFunction;
+use super::native::NativeFunction;
 
+/// A Lodestone value. Hosts embedding the interpreter get and pass these;
+/// the variants hidden from the documentation are the evaluator's own, hold
+/// types a host can't name, and may change without notice. Use
+/// `FromLodestone` to read hashes and `Interpreter::call_function` to call
+/// functions instead of matching on them.
 #[derive(Clone)]
 pub enum ObjectKind {
     Integer{slots: HashMap<String, ObjectKind>, value: u32},
     Boolean{value: bool},
     Null,
+    #[doc(hidden)]
     ReturnValue{value: Box<ObjectKind>},
     Error{message: String},
+    #[doc(hidden)]
     Function{slots: HashMap<String, ObjectKind>, parameters: Vec<ExpressionKind>, body: StatementKind, env: Environment},
     StringObj{slots: HashMap<String, ObjectKind>, value: String},
-    BuiltIn,
-    Array{slots: HashMap<String, ObjectKind>, elements: Vec<ObjectKind>}
+    /// A function implemented in Rust; see `builtins`.
+    BuiltIn{name: &'static str, function: BuiltinFunction},
+    /// A closure registered by the embedding host.
+    Native{name: String, arity: usize, function: NativeFunction},
+    /// `frozen` arrays and hashes belong to a constant and can't be changed.
+    Array{slots: HashMap<String, ObjectKind>, elements: Vec<ObjectKind>, frozen: bool},
+    #[doc(hidden)]
+    Hash{pairs: HashMap<HashKey, (ObjectKind, ObjectKind)>, frozen: bool},
+    /// The constructor a `struct` declaration binds; calling it makes a `Struct`.
+    StructType{name: String, fields: Vec<String>},
+    /// An instance of the struct `name`, with its fields in declaration order.
+    Struct{name: String, fields: Vec<(String, ObjectKind)>},
+    /// What an `enum` declaration binds; its variants are read as slots.
+    EnumType{name: String, variants: Vec<(String, Vec<String>)>},
+    /// The constructor for a variant that carries fields.
+    VariantType{enum_name: String, variant: String, fields: Vec<String>},
+    /// A value of the enum `enum_name`, tagged with the variant it holds.
+    Variant{enum_name: String, variant: String, fields: Vec<(String, ObjectKind)>},
+    /// A prototype-style object. Slots it doesn't have are looked up in
+    /// `parent`, the object it was cloned from. Unlike other values, objects
+    /// are shared rather than copied: every variable holding one, and every
+    /// clone delegating to it, sees changes made to its slots.
+    Object{slots: Rc<RefCell<HashMap<String, ObjectKind>>>, parent: Option<Box<ObjectKind>>, frozen: bool},
+    /// What `import` binds: the exports of the module `name`, which can't be changed.
+    Module{name: String, slots: HashMap<String, ObjectKind>}
+}
+
+/// Only integers, booleans and strings may be used as hash keys.
+#[derive(Hash, Clone, PartialEq, Eq)]
+pub enum HashKey {
+    Integer{value: u32},
+    Boolean{value: bool},
+    StringObj{value: String}
 }
 
 impl ObjectKind {
@@ -27,50 +70,324 @@ impl ObjectKind {
         discriminant(&self) == discriminant(b)
     }
 
-    fn get_from_slots(self, key: String) -> ObjectKind {
+    pub fn hash_key(&self) -> Option<HashKey> {
         match self {
-            ObjectKind::Integer{slots, ..} => {
-                let found = slots.get(&key);
-                match found {
-                    Some(v) => {
-                        v.clone()
-                    },
-                    _ => {
-                        ObjectKind::Error{message: String::from("Error finding key")}
+            ObjectKind::Integer{value, ..} => Some(HashKey::Integer{value: *value}),
+            ObjectKind::Boolean{value} => Some(HashKey::Boolean{value: *value}),
+            ObjectKind::StringObj{value, ..} => Some(HashKey::StringObj{value: value.clone()}),
+            _ => None
+        }
+    }
+
+    /// Value equality as seen by `==` and match patterns. Functions are
+    /// never equal to anything, including themselves, and an object is only
+    /// equal to itself.
+    pub fn equals(&self, other: &ObjectKind) -> bool {
+        match (self, other) {
+            (ObjectKind::Integer{value: l, ..}, ObjectKind::Integer{value: r, ..}) => l == r,
+            (ObjectKind::Boolean{value: l}, ObjectKind::Boolean{value: r}) => l == r,
+            (ObjectKind::StringObj{value: l, ..}, ObjectKind::StringObj{value: r, ..}) => l == r,
+            (ObjectKind::Null, ObjectKind::Null) => true,
+            (ObjectKind::Struct{name: l_name, fields: l}, ObjectKind::Struct{name: r_name, fields: r}) => {
+                l_name == r_name && l.len() == r.len() && l.iter().zip(r.iter()).all(|(a, b)| a.0 == b.0 && a.1.equals(&b.1))
+            },
+            (ObjectKind::Variant{enum_name: l_enum, variant: l_variant, fields: l},
+             ObjectKind::Variant{enum_name: r_enum, variant: r_variant, fields: r}) => {
+                l_enum == r_enum && l_variant == r_variant && l.len() == r.len() &&
+                    l.iter().zip(r.iter()).all(|(a, b)| a.1.equals(&b.1))
+            },
+            (ObjectKind::Object{slots: l, ..}, ObjectKind::Object{slots: r, ..}) => Rc::ptr_eq(l, r),
+            (ObjectKind::Module{name: l, ..}, ObjectKind::Module{name: r, ..}) => l == r,
+            (ObjectKind::Array{elements: l, ..}, ObjectKind::Array{elements: r, ..}) => {
+                l.len() == r.len() && l.iter().zip(r.iter()).all(|(a, b)| a.equals(b))
+            },
+            (ObjectKind::Hash{pairs: l, ..}, ObjectKind::Hash{pairs: r, ..}) => {
+                l.len() == r.len() && l.iter().all(|(k, (_, v))| {
+                    match r.get(k) {
+                        Some((_, other_v)) => v.equals(other_v),
+                        None => false
                     }
+                })
+            },
+            _ => false
+        }
+    }
+
+    /// Marks an array or hash, and every array or hash inside it, as frozen.
+    /// A frozen object is a frozen copy of its own slots; the objects it
+    /// delegates to are still shared.
+    pub fn freeze(self) -> ObjectKind {
+        self.freeze_copies(&mut HashMap::new())
+    }
+
+    /// `copies` maps each object already being frozen to its frozen copy, so
+    /// an object that holds itself is copied once and the copy holds itself.
+    fn freeze_copies(self, copies: &mut HashMap<usize, Rc<RefCell<HashMap<String, ObjectKind>>>>) -> ObjectKind {
+        match self {
+            ObjectKind::Array{slots, elements, ..} => {
+                ObjectKind::Array{slots, elements: elements.into_iter().map(|e| e.freeze_copies(copies)).collect(), frozen: true}
+            },
+            ObjectKind::Hash{pairs, ..} => {
+                let pairs = pairs.into_iter().map(|(k, (key, value))| (k, (key, value.freeze_copies(copies)))).collect();
+                ObjectKind::Hash{pairs, frozen: true}
+            },
+            ObjectKind::Struct{name, fields} => {
+                ObjectKind::Struct{name, fields: fields.into_iter().map(|(f, v)| (f, v.freeze_copies(copies))).collect()}
+            },
+            ObjectKind::Variant{enum_name, variant, fields} => {
+                let fields = fields.into_iter().map(|(f, v)| (f, v.freeze_copies(copies))).collect();
+                ObjectKind::Variant{enum_name, variant, fields}
+            },
+            ObjectKind::Object{slots, parent, ..} => {
+                let address = Rc::as_ptr(&slots) as usize;
+                if let Some(copy) = copies.get(&address) {
+                    return ObjectKind::Object{slots: copy.clone(), parent, frozen: true};
+                }
+                let copy = Rc::new(RefCell::new(HashMap::new()));
+                copies.insert(address, copy.clone());
+                let own: Vec<(String, ObjectKind)> = slots.borrow().iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+                for (k, v) in own {
+                    let v = v.freeze_copies(copies);
+                    copy.borrow_mut().insert(k, v);
+                }
+                ObjectKind::Object{slots: copy, parent, frozen: true}
+            },
+            other => other
+        }
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        match self {
+            ObjectKind::Array{frozen, ..} | ObjectKind::Hash{frozen, ..} | ObjectKind::Object{frozen, ..} => *frozen,
+            ObjectKind::Module{..} => true,
+            _ => false
+        }
+    }
+
+    /// Hashes expose their string keys as slots. Objects delegate slots they
+    /// don't have to their parent.
+    pub fn get_from_slots(self, key: String) -> ObjectKind {
+        let type_name = self.type_name();
+        let found = match self {
+            ObjectKind::Integer{slots, ..} |
+            ObjectKind::Function{slots, ..} |
+            ObjectKind::StringObj{slots, ..} |
+            ObjectKind::Array{slots, ..} => {
+                slots.get(&key).cloned()
+            },
+            ObjectKind::Hash{pairs, ..} => {
+                pairs.get(&HashKey::StringObj{value: key.clone()}).map(|(_, v)| v.clone())
+            },
+            ObjectKind::Object{slots, parent, ..} => {
+                let found = slots.borrow().get(&key).cloned();
+                match (found, parent) {
+                    (Some(v), _) => Some(v),
+                    (None, Some(p)) => {
+                        return p.get_from_slots(key);
+                    },
+                    (None, None) => None
                 }
             },
+            ObjectKind::Module{name, mut slots} => {
+                return match slots.remove(&key) {
+                    Some(v) => v,
+                    None => ObjectKind::Error{message: format!("module {} does not export {}", name, key)}
+                };
+            },
+            ObjectKind::Struct{name, fields} => {
+                return match fields.iter().find(|&(f, _)| *f == key) {
+                    Some((_, v)) => v.clone(),
+                    None => ObjectKind::Error{message: format!("{} has no field {}", name, key)}
+                };
+            },
+            ObjectKind::Variant{enum_name, variant, fields} => {
+                return match fields.iter().find(|&(f, _)| *f == key) {
+                    Some((_, v)) => v.clone(),
+                    None => ObjectKind::Error{message: format!("{}.{} has no field {}", enum_name, variant, key)}
+                };
+            },
+            ObjectKind::EnumType{name, variants} => {
+                return match variants.into_iter().find(|v| v.0 == key) {
+                    Some((variant, ref fields)) if fields.is_empty() => {
+                        ObjectKind::Variant{enum_name: name, variant, fields: vec![]}
+                    },
+                    Some((variant, fields)) => {
+                        ObjectKind::VariantType{enum_name: name, variant, fields}
+                    },
+                    None => ObjectKind::Error{message: format!("{} has no variant {}", name, key)}
+                };
+            },
+            ref other => {
+                return ObjectKind::Error{message: format!("{} has no slots", other.type_name())};
+            }
+        };
+        match found {
+            Some(v) => {
+                v
+            },
             _ => {
-                panic!("not implmented");
+                ObjectKind::Error{message: format!("{} has no slot {}", type_name, key)}
             }
         }
     }
 
-    fn remove_from_slots(&mut self, key: String) {
+    /// A slot, following an object's parents, or `None` if the value
+    /// doesn't have it.
+    pub fn find_slot(&self, key: &str) -> Option<ObjectKind> {
         match self {
-            ObjectKind::Integer{slots, ..} => {
-                slots.remove(&key);
+            ObjectKind::Integer{slots, ..} |
+            ObjectKind::Function{slots, ..} |
+            ObjectKind::StringObj{slots, ..} |
+            ObjectKind::Array{slots, ..} => {
+                slots.get(key).cloned()
+            },
+            ObjectKind::Hash{pairs, ..} => {
+                pairs.get(&HashKey::StringObj{value: String::from(key)}).map(|(_, v)| v.clone())
+            },
+            ObjectKind::Struct{fields, ..} | ObjectKind::Variant{fields, ..} => {
+                fields.iter().find(|&(f, _)| f == key).map(|(_, v)| v.clone())
+            },
+            ObjectKind::Object{slots, parent, ..} => {
+                let found = slots.borrow().get(key).cloned();
+                match (found, parent) {
+                    (Some(v), _) => Some(v),
+                    (None, Some(p)) => p.find_slot(key),
+                    (None, None) => None
+                }
+            },
+            ObjectKind::Module{slots, ..} => {
+                slots.get(key).cloned()
+            },
+            _ => None
+        }
+    }
+
+    /// Calls the function in the special slot `name`, such as `__add__`, with
+    /// `self` bound to this value. `None` if there is no such function.
+    pub fn call_special(&self, name: &str, args: Vec<ObjectKind>) -> Option<ObjectKind> {
+        match self.find_slot(name) {
+            Some(method @ ObjectKind::Function{..}) => Some(evaluator::call_method(method, self.clone(), args)),
+            _ => None
+        }
+    }
+
+    /// Removes a slot. Struct and variant fields are fixed and can't be removed.
+    pub fn remove_from_slots(&mut self, key: String) -> Result<(), String> {
+        let removed = match self {
+            ObjectKind::Array{frozen: true, ..} | ObjectKind::Hash{frozen: true, ..} | ObjectKind::Object{frozen: true, ..} |
+            ObjectKind::Module{..} => {
+                return Err(format!("cannot modify a frozen {}", self.type_name()));
+            },
+            ObjectKind::Integer{slots, ..} |
+            ObjectKind::Function{slots, ..} |
+            ObjectKind::StringObj{slots, ..} |
+            ObjectKind::Array{slots, ..} => {
+                slots.remove(&key).is_some()
+            },
+            ObjectKind::Object{slots, ..} => {
+                slots.borrow_mut().remove(&key).is_some()
+            },
+            ObjectKind::Hash{pairs, ..} => {
+                pairs.remove(&HashKey::StringObj{value: key.clone()}).is_some()
+            },
+            ObjectKind::Struct{..} | ObjectKind::Variant{..} => {
+                return Err(format!("cannot remove field {} from {}", key, self.type_name()));
             },
             _ => {
-                panic!("not implmented");
+                return Err(format!("{} has no slots", self.type_name()));
             }
+        };
+        if removed {
+            Ok(())
+        } else {
+            Err(format!("{} has no slot {}", self.type_name(), key))
         }
     }
 
-    fn add_to_slots(&mut self, key: String, value: ObjectKind) {
+    /// Sets a slot, adding it if it isn't there. Struct and variant fields
+    /// can be changed but not added.
+    pub fn add_to_slots(&mut self, key: String, value: ObjectKind) -> Result<(), String> {
         match self {
-            ObjectKind::Integer{slots, ..} => {
+            ObjectKind::Array{frozen: true, ..} | ObjectKind::Hash{frozen: true, ..} | ObjectKind::Object{frozen: true, ..} |
+            ObjectKind::Module{..} => {
+                Err(format!("cannot modify a frozen {}", self.type_name()))
+            },
+            ObjectKind::Integer{slots, ..} |
+            ObjectKind::Function{slots, ..} |
+            ObjectKind::StringObj{slots, ..} |
+            ObjectKind::Array{slots, ..} => {
                 slots.insert(key, value);
+                Ok(())
+            },
+            ObjectKind::Object{slots, ..} => {
+                slots.borrow_mut().insert(key, value);
+                Ok(())
+            },
+            ObjectKind::Hash{pairs, ..} => {
+                let hash_key = HashKey::StringObj{value: key.clone()};
+                pairs.insert(hash_key, (ObjectKind::StringObj{slots: HashMap::new(), value: key}, value));
+                Ok(())
+            },
+            ObjectKind::Struct{name, fields} => {
+                match fields.iter_mut().find(|&&mut (ref f, _)| *f == key) {
+                    Some(field) => {
+                        field.1 = value;
+                        Ok(())
+                    },
+                    None => Err(format!("{} has no field {}", name, key))
+                }
+            },
+            ObjectKind::Variant{enum_name, variant, fields} => {
+                match fields.iter_mut().find(|&&mut (ref f, _)| *f == key) {
+                    Some(field) => {
+                        field.1 = value;
+                        Ok(())
+                    },
+                    None => Err(format!("{}.{} has no field {}", enum_name, variant, key))
+                }
             },
             _ => {
-                panic!("not implmented");
+                Err(format!("{} has no slots", self.type_name()))
             }
         }
     }
+
+    /// A short lowercase name for the kind of value, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ObjectKind::Integer{..} => "integer",
+            ObjectKind::Boolean{..} => "boolean",
+            ObjectKind::Null => "null",
+            ObjectKind::ReturnValue{..} => "return value",
+            ObjectKind::Error{..} => "error",
+            ObjectKind::Function{..} => "function",
+            ObjectKind::StringObj{..} => "string",
+            ObjectKind::BuiltIn{..} => "builtin",
+            ObjectKind::Native{..} => "native function",
+            ObjectKind::Module{..} => "module",
+            ObjectKind::Array{..} => "array",
+            ObjectKind::Hash{..} => "hash",
+            ObjectKind::StructType{..} => "struct type",
+            ObjectKind::Struct{..} => "struct",
+            ObjectKind::EnumType{..} => "enum",
+            ObjectKind::VariantType{..} => "variant constructor",
+            ObjectKind::Variant{..} => "variant",
+            ObjectKind::Object{..} => "object"
+        }
+    }
+}
+
+thread_local! {
+    /// The objects being displayed, innermost last.
+    static PRINTING: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
 }
 
 impl fmt::Display for ObjectKind {
+    /// A `__str__` slot that returns a string replaces the built-in form.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ObjectKind::StringObj{value, ..}) = self.call_special("__str__", vec![]) {
+            return write!(f, "{}", value);
+        }
         match self {
             ObjectKind::Integer{value: value, ..} => {
                 write!(f, "{}", value)
@@ -84,6 +401,72 @@ impl fmt::Display for ObjectKind {
             ObjectKind::StringObj{value: value, ..} => {
                 write!(f, "{}", value)
             },
+            ObjectKind::Boolean{value} => {
+                write!(f, "{}", value)
+            },
+            ObjectKind::Array{elements, ..} => {
+                let elements: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+                write!(f, "[{}]", elements.join(", "))
+            },
+            ObjectKind::Hash{pairs, ..} => {
+                let mut pairs: Vec<String> = pairs.values().map(|(k, v)| format!("{}: {}", k, v)).collect();
+                pairs.sort();
+                write!(f, "{{{}}}", pairs.join(", "))
+            },
+            ObjectKind::Function{parameters, ..} => {
+                let parameters: Vec<String> = parameters.iter().map(|p| p.clone().string()).collect();
+                write!(f, "fun({})", parameters.join(", "))
+            },
+            ObjectKind::BuiltIn{name, ..} => {
+                write!(f, "builtin {}", name)
+            },
+            ObjectKind::Native{name, ..} => {
+                write!(f, "native {}", name)
+            },
+            ObjectKind::StructType{name, fields} => {
+                write!(f, "struct {} {{ {} }}", name, fields.join(", "))
+            },
+            ObjectKind::Struct{name, fields} if fields.is_empty() => {
+                write!(f, "{} {{}}", name)
+            },
+            ObjectKind::Struct{name, fields} => {
+                let fields: Vec<String> = fields.iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+                write!(f, "{} {{ {} }}", name, fields.join(", "))
+            },
+            ObjectKind::EnumType{name, variants} => {
+                let variants: Vec<String> = variants.iter().map(|(v, fields)| {
+                    if fields.is_empty() { v.clone() } else { format!("{}({})", v, fields.join(", ")) }
+                }).collect();
+                write!(f, "enum {} {{ {} }}", name, variants.join(", "))
+            },
+            ObjectKind::VariantType{enum_name, variant, fields} => {
+                write!(f, "{}.{}({})", enum_name, variant, fields.join(", "))
+            },
+            ObjectKind::Variant{enum_name, variant, fields} if fields.is_empty() => {
+                write!(f, "{}.{}", enum_name, variant)
+            },
+            ObjectKind::Variant{enum_name, variant, fields} => {
+                let fields: Vec<String> = fields.iter().map(|(_, v)| v.to_string()).collect();
+                write!(f, "{}.{}({})", enum_name, variant, fields.join(", "))
+            },
+            ObjectKind::Object{slots, ..} if slots.borrow().is_empty() => {
+                write!(f, "Object {{}}")
+            },
+            ObjectKind::Object{slots, ..} => {
+                // An object can hold itself; print it only once per line.
+                let address = Rc::as_ptr(slots) as usize;
+                if PRINTING.with(|p| p.borrow().contains(&address)) {
+                    return write!(f, "Object {{...}}");
+                }
+                PRINTING.with(|p| p.borrow_mut().push(address));
+                let mut lines: Vec<String> = slots.borrow().iter().map(|(k, v)| format!("{}: {}", k, v)).collect();
+                PRINTING.with(|p| p.borrow_mut().pop());
+                lines.sort();
+                write!(f, "Object {{ {} }}", lines.join(", "))
+            },
+            ObjectKind::Module{name, ..} => {
+                write!(f, "module {}", name)
+            },
             _ => {
                 write!(f, "{}", "display not implmented")
             }
@@ -0,0 +1,78 @@
+use std::cell::RefCell;
+use std::cmp::Reverse;
+
+use super::object::ObjectKind;
+
+/// Which side of a chain like `a op b op c` binds first.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Associativity {
+    Left,
+    Right
+}
+
+pub type InfixHandler = fn(ObjectKind, ObjectKind) -> ObjectKind;
+
+/// An infix operator registered by the host application. The lexer turns
+/// `symbol` into a token, the parser gives it `precedence`, and the evaluator
+/// calls `handler` with both evaluated operands.
+#[derive(Clone)]
+pub struct CustomOperator {
+    pub symbol: &'static str,
+    pub precedence: u8,
+    pub associativity: Associativity,
+    pub handler: InfixHandler
+}
+
+thread_local! {
+    static CUSTOM_OPERATORS: RefCell<Vec<CustomOperator>> = const { RefCell::new(Vec::new()) };
+}
+
+const OPERATOR_CHARS: &str = "+-*/%<>=!&|^~?@$:.";
+
+/// Registers `symbol` as an infix operator for code lexed, parsed and
+/// evaluated afterwards on this thread. Registering the same symbol again
+/// replaces the earlier definition.
+pub fn register_infix_operator(symbol: &'static str, precedence: u8, associativity: Associativity, handler: InfixHandler) -> Result<(), String> {
+    if symbol.is_empty() || !symbol.chars().all(|c| OPERATOR_CHARS.contains(c)) {
+        return Err(format!("operator symbol must be made of {}: {:?}", OPERATOR_CHARS, symbol));
+    }
+    if symbol.contains("//") {
+        return Err(format!("operator {} would start a comment", symbol));
+    }
+    if super::parser::is_builtin_operator(symbol) {
+        return Err(format!("operator {} is already defined by the language", symbol));
+    }
+    if let Some(builtin) = super::parser::builtin_operator_extending(symbol) {
+        return Err(format!("operator {} would change how {} is read", symbol, builtin));
+    }
+    if precedence == super::parser::LOWEST || precedence >= super::parser::PREFIX {
+        return Err(format!("operator precedence must be between {} and {}", super::parser::LOWEST + 1, super::parser::PREFIX - 1));
+    }
+    let operator = CustomOperator {
+        symbol,
+        precedence,
+        associativity,
+        handler
+    };
+    CUSTOM_OPERATORS.with(|ops| {
+        let mut ops = ops.borrow_mut();
+        ops.retain(|o| o.symbol != symbol);
+        ops.push(operator);
+        // Longest first so the lexer prefers `**=` over `**`.
+        ops.sort_by_key(|o| Reverse(o.symbol.len()));
+    });
+    Ok(())
+}
+
+pub fn lookup(symbol: &str) -> Option<CustomOperator> {
+    CUSTOM_OPERATORS.with(|ops| {
+        ops.borrow().iter().find(|o| o.symbol == symbol).cloned()
+    })
+}
+
+/// The longest registered symbol that `input` starts with.
+pub fn symbol_at(input: &str) -> Option<&'static str> {
+    CUSTOM_OPERATORS.with(|ops| {
+        ops.borrow().iter().map(|o| o.symbol).find(|symbol| input.starts_with(symbol))
+    })
+}
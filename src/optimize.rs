@@ -0,0 +1,351 @@
+//! Optional rewrites of a resolved program before evaluation: constant
+//! folding, pruning `if` branches whose condition is constant, and inlining
+//! calls to trivial functions.
+//!
+//! Constants are folded by running the evaluator on them, so an optimized
+//! program computes exactly what the original would. Anything that would
+//! fail at runtime (overflow, division by zero, type errors) is left alone
+//! so the error still happens when, and only if, that code runs.
+
+use std::collections::HashMap;
+
+use super::ast::NodeKind;
+use super::ast::ExpressionKind;
+use super::ast::StatementKind;
+use super::ast::Binding;
+use super::fold;
+use super::fold::Folder;
+use super::visit;
+use super::visit::Visitor;
+use super::evaluator;
+use super::environment::Environment;
+use super::object::ObjectKind;
+use super::operator;
+use super::token;
+
+/// Optimizes a program. Inlining relies on the bindings filled in by
+/// `resolver::resolve`; on an unresolved tree only folding and pruning run.
+pub fn optimize(program: NodeKind) -> NodeKind {
+    let mut collector = InlineCandidates { lets: HashMap::new(), functions: HashMap::new() };
+    collector.visit_node(&program);
+    let lets = collector.lets;
+    let mut functions = collector.functions;
+    functions.retain(|name, _| lets.get(name) == Some(&1));
+    Optimizer { inlinable: functions }.fold_node(program)
+}
+
+/// A function whose body is a single expression over its parameters.
+struct TrivialFunction {
+    parameters: Vec<String>,
+    body: ExpressionKind,
+}
+
+/// Finds globals bound exactly once to a trivial function.
+struct InlineCandidates {
+    /// How many times each global is declared, by any kind of statement.
+    lets: HashMap<String, usize>,
+    functions: HashMap<String, TrivialFunction>,
+}
+
+impl Visitor for InlineCandidates {
+    fn visit_statement(&mut self, statement: &StatementKind) {
+        match statement {
+            StatementKind::LetStatement{name: ExpressionKind::Identifier{binding: Binding::Global, ..}, ..} |
+            StatementKind::StructStatement{name: ExpressionKind::Identifier{binding: Binding::Global, ..}, ..} |
+            StatementKind::EnumStatement{name: ExpressionKind::Identifier{binding: Binding::Global, ..}, ..} |
+            StatementKind::ImportStatement{name: ExpressionKind::Identifier{binding: Binding::Global, ..}, ..} => {
+                if let Some(name) = statement.declared_name() {
+                    *self.lets.entry(String::from(name)).or_insert(0) += 1;
+                }
+            },
+            _ => {}
+        }
+        if let StatementKind::LetStatement{name: ExpressionKind::Identifier{value: name, binding: Binding::Global, ..}, value: Some(value), ..} = statement {
+            if let Some(f) = trivial_function(value) {
+                self.functions.insert(name.clone(), f);
+            }
+        }
+        visit::walk_statement(self, statement);
+    }
+}
+
+fn trivial_function(expression: &ExpressionKind) -> Option<TrivialFunction> {
+    let (parameters, body) = match expression {
+        ExpressionKind::FunctionLiteral{parameters, body, ..} => (parameters, body),
+        _ => return None
+    };
+    let parameters: Vec<String> = parameters.iter().filter_map(|p| match p {
+        ExpressionKind::Identifier{value, ..} => Some(value.clone()),
+        _ => None
+    }).collect();
+    let statements = match **body {
+        StatementKind::BlockStatement{ref statements, ..} if statements.len() == 1 => statements,
+        _ => return None
+    };
+    let body = match *statements[0] {
+        StatementKind::ExpressionStatement{expression: Some(ref e), ..} => e,
+        StatementKind::ReturnStatement{return_value: Some(ref rv), ..} => match **rv {
+            StatementKind::ExpressionStatement{expression: Some(ref e), ..} => e,
+            _ => return None
+        },
+        _ => return None
+    };
+    if uses_only(body, &parameters) {
+        Some(TrivialFunction { parameters, body: (**body).clone() })
+    } else {
+        None
+    }
+}
+
+/// Whether `expression` refers to nothing but `parameters` and has no
+/// binders or blocks, so substituting arguments into it is always safe.
+fn uses_only(expression: &ExpressionKind, parameters: &[String]) -> bool {
+    let all = |items: &Vec<Box<ExpressionKind>>| items.iter().all(|e| uses_only(e, parameters));
+    match expression {
+        ExpressionKind::Identifier{value, ..} => parameters.contains(value),
+        ExpressionKind::IntegerLiteral{..} |
+        ExpressionKind::StringLiteral{..} |
+        ExpressionKind::BooleanExpression{..} |
+        ExpressionKind::NullLiteral{..} => true,
+        ExpressionKind::PrefixExpression{right: Some(r), ..} => uses_only(r, parameters),
+        ExpressionKind::InfixExpression{operator, left: Some(l), right: Some(r), ..} => {
+            operator != "|>" && uses_only(l, parameters) && uses_only(r, parameters)
+        },
+        ExpressionKind::ArrayLiteral{elements, ..} => all(elements),
+        ExpressionKind::IndexExpression{left, index: Some(i), ..} => uses_only(left, parameters) && uses_only(i, parameters),
+        ExpressionKind::SlotExpression{left, ..} => uses_only(left, parameters),
+        _ => false
+    }
+}
+
+fn is_literal(expression: &ExpressionKind) -> bool {
+    matches!(expression,
+        ExpressionKind::IntegerLiteral{..} |
+        ExpressionKind::StringLiteral{..} |
+        ExpressionKind::BooleanExpression{..} |
+        ExpressionKind::NullLiteral{..})
+}
+
+/// Arguments that can be copied into an inlined body any number of times.
+fn is_simple(expression: &ExpressionKind) -> bool {
+    match expression {
+        ExpressionKind::Identifier{..} => true,
+        e => is_literal(e)
+    }
+}
+
+fn literal(value: ObjectKind, span: token::Span) -> Option<ExpressionKind> {
+    let token = |t_type: token::TokenType, literal: String| token::Token { t_type, literal, span };
+    match value {
+        ObjectKind::Integer{value, ..} => Some(ExpressionKind::IntegerLiteral{token: token(token::INT, value.to_string()), value}),
+        ObjectKind::StringObj{value, ..} => Some(ExpressionKind::StringLiteral{token: token(token::STRING, value.clone()), value}),
+        ObjectKind::Boolean{value} => {
+            let t_type = if value { token::TRUE } else { token::FALSE };
+            Some(ExpressionKind::BooleanExpression{token: token(t_type, value.to_string()), value})
+        },
+        ObjectKind::Null => Some(ExpressionKind::NullLiteral{token: token(token::NULL, String::from("null"))}),
+        _ => None
+    }
+}
+
+struct Optimizer {
+    inlinable: HashMap<String, TrivialFunction>,
+}
+
+impl Optimizer {
+    fn evaluate(&self, expression: ExpressionKind, span: token::Span) -> ExpressionKind {
+        let value = evaluator::eval(NodeKind::ExpressionNode{expressionKind: expression.clone()}, &mut Environment::new());
+        literal(value, span).unwrap_or(expression)
+    }
+
+    fn fold_infix(&mut self, expression: ExpressionKind) -> ExpressionKind {
+        let (span, operator, left, right) = match expression {
+            ExpressionKind::InfixExpression{ref token, ref operator, left: Some(ref l), right: Some(ref r)} => {
+                (token.span, operator.clone(), l, r)
+            },
+            _ => return expression
+        };
+        if !is_literal(left) {
+            return expression;
+        }
+        if operator == "??" {
+            return match **left {
+                ExpressionKind::NullLiteral{..} => (**right).clone(),
+                _ => (**left).clone()
+            };
+        }
+        if !is_literal(right) || operator == "|>" || operator::lookup(&operator).is_some() {
+            return expression;
+        }
+        self.evaluate(expression.clone(), span)
+    }
+
+    fn prune_if(&mut self, expression: ExpressionKind) -> ExpressionKind {
+        match expression {
+            ExpressionKind::IfExpression{token, condition: Some(condition), consequence, alternative} => {
+                if !is_literal(&condition) {
+                    return ExpressionKind::IfExpression{token, condition: Some(condition), consequence, alternative};
+                }
+                // Only `true` is truthy to the evaluator.
+                let taken = match *condition {
+                    ExpressionKind::BooleanExpression{value: true, ..} => consequence,
+                    _ => alternative
+                };
+                let taken = match taken {
+                    Some(block) => block,
+                    None => {
+                        return ExpressionKind::NullLiteral{token: token::Token { t_type: token::NULL, literal: String::from("null"), span: token.span }};
+                    }
+                };
+                match *taken {
+                    StatementKind::BlockStatement{ref statements, ..} if statements.len() == 1 => {
+                        if let StatementKind::ExpressionStatement{expression: Some(ref e), ..} = *statements[0] {
+                            return (**e).clone();
+                        }
+                    },
+                    _ => {}
+                }
+                // A longer branch still needs its block, so keep it behind a literal `true`.
+                let always = ExpressionKind::BooleanExpression{token: token::Token { t_type: token::TRUE, literal: String::from("true"), span: token.span }, value: true};
+                ExpressionKind::IfExpression{token, condition: Some(Box::new(always)), consequence: Some(taken), alternative: None}
+            },
+            other => other
+        }
+    }
+
+    fn inline_call(&mut self, expression: ExpressionKind) -> ExpressionKind {
+        let inlined = match expression {
+            ExpressionKind::CallExpression{ref function, ref arguments, ..} => {
+                match **function {
+                    ExpressionKind::Identifier{ref value, binding: Binding::Global, ..} => {
+                        match self.inlinable.get(value) {
+                            Some(f) if f.parameters.len() == arguments.len() && arguments.iter().all(|a| is_simple(a)) => {
+                                let mut substitute = Substitute {
+                                    parameters: f.parameters.clone(),
+                                    arguments: arguments.iter().map(|a| (**a).clone()).collect()
+                                };
+                                Some(substitute.fold_expression(f.body.clone()))
+                            },
+                            _ => None
+                        }
+                    },
+                    _ => None
+                }
+            },
+            _ => None
+        };
+        match inlined {
+            Some(body) => self.fold_expression(body),
+            None => expression
+        }
+    }
+}
+
+impl Folder for Optimizer {
+    fn fold_expression(&mut self, expression: ExpressionKind) -> ExpressionKind {
+        let expression = fold::fold_expression(self, expression);
+        match expression {
+            ExpressionKind::InfixExpression{..} => self.fold_infix(expression),
+            ExpressionKind::PrefixExpression{ref token, ref operator, right: Some(ref r)} if operator == "!" && is_literal(r) => {
+                self.evaluate(expression.clone(), token.span)
+            },
+            ExpressionKind::IfExpression{..} => self.prune_if(expression),
+            ExpressionKind::CallExpression{..} => self.inline_call(expression),
+            other => other
+        }
+    }
+}
+
+/// Replaces parameter names in an inlined body with the call's arguments.
+struct Substitute {
+    parameters: Vec<String>,
+    arguments: Vec<ExpressionKind>,
+}
+
+impl Folder for Substitute {
+    fn fold_expression(&mut self, expression: ExpressionKind) -> ExpressionKind {
+        match expression {
+            ExpressionKind::Identifier{ref value, ..} => {
+                match self.parameters.iter().position(|p| p == value) {
+                    Some(i) => self.arguments[i].clone(),
+                    None => expression.clone()
+                }
+            },
+            other => fold::fold_expression(self, other)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::lexer::Lexer;
+    use super::super::parser::Parser;
+    use super::super::printer::Printer;
+    use super::super::printer::DEFAULT_WIDTH;
+    use super::super::resolver;
+
+    fn parse(input: &str) -> NodeKind {
+        let mut p = Parser::new(Lexer::new(String::from(input)));
+        let program = p.parse_program();
+        resolver::resolve(program, &[]).expect("resolve error")
+    }
+
+    fn optimized(input: &str) -> String {
+        Printer::new(DEFAULT_WIDTH).print_program(&optimize(parse(input)))
+    }
+
+    #[test]
+    fn test_folds_constants() {
+        assert_eq!(optimized("60 * 60 * 24"), "86400;\n");
+        assert_eq!(optimized("\"prefix\" + \"suffix\""), "\"prefixsuffix\";\n");
+        assert_eq!(optimized("!(1 == 2) != false"), "true;\n");
+        assert_eq!(optimized("null ?? 1 + 1"), "2;\n");
+        assert_eq!(optimized("let x := 1; x * (2 + 3)"), "let x := 1;\nx * 5;\n");
+    }
+
+    #[test]
+    fn test_keeps_runtime_errors() {
+        assert_eq!(optimized("1 - 2"), "1 - 2;\n");
+        assert_eq!(optimized("1 / 0"), "1 / 0;\n");
+        assert_eq!(optimized("4294967295 + 1"), "4294967295 + 1;\n");
+        assert_eq!(optimized("1 % 0"), "1 % 0;\n");
+        assert_eq!(optimized("\"a\" - \"b\""), "\"a\" - \"b\";\n");
+    }
+
+    #[test]
+    fn test_prunes_constant_ifs() {
+        assert_eq!(optimized("if (2 > 1) { \"a\" } else { \"b\" }"), "\"a\";\n");
+        assert_eq!(optimized("if (false) { \"a\" }"), "null;\n");
+        assert_eq!(optimized("if (1) { \"a\" } else { let y := 1; y }"), "if (true) {\n    let y := 1;\n    y;\n};\n");
+    }
+
+    #[test]
+    fn test_inlines_trivial_functions() {
+        assert_eq!(optimized("let add := fun(a, b) { a + b }; add(1, 2)"), "let add := fun(a, b) {\n    a + b;\n};\n3;\n");
+        assert_eq!(optimized("let sq := fun(a) { return a * a; }; let n := 4; sq(n)"), "let sq := fun(a) {\n    return a * a;\n};\nlet n := 4;\nn * n;\n");
+        // Not inlined: rebound, non-trivial body, or arguments that are not simple.
+        assert_eq!(optimized("let f := fun(a) { a }; let f := fun(a) { 1 }; f(2)"), "let f := fun(a) {\n    a;\n};\nlet f := fun(a) {\n    1;\n};\nf(2);\n");
+        assert_eq!(optimized("let g := 1; let f := fun(a) { a + g }; f(2)"), "let g := 1;\nlet f := fun(a) {\n    a + g;\n};\nf(2);\n");
+        assert_eq!(optimized("let f := fun(a) { a }; f([1])"), "let f := fun(a) {\n    a;\n};\nf([1]);\n");
+        assert_eq!(optimized("let f := fun(a) { a }; struct f { x }\nf(2)"), "let f := fun(a) {\n    a;\n};\nstruct f { x }\nf(2);\n");
+        assert_eq!(optimized("let f := fun(a) { a }; enum f { A }\nf(2)"), "let f := fun(a) {\n    a;\n};\nenum f { A }\nf(2);\n");
+        assert_eq!(optimized("let f := fun(a) { a }; import \"lib\" as f;\nf(2)"), "let f := fun(a) {\n    a;\n};\nimport \"lib\" as f;\nf(2);\n");
+        // Inner calls are inlined first, which can make the outer one inlinable.
+        assert_eq!(optimized("let f := fun(a) { a }; let h := fun() { 1 }; f(h())"), "let f := fun(a) {\n    a;\n};\nlet h := fun() {\n    1;\n};\n1;\n");
+    }
+
+    #[test]
+    fn test_optimized_programs_evaluate_the_same() {
+        let programs = vec![
+            "let add := fun(a, b) { a + b }; let k := 60 * 60; add(k, 24) * 2",
+            "let pick := fun(x) { if (x == 1) { \"one\" } else { \"other\" } }; [pick(1), pick(2), if (true) { 3 }]",
+            "let f := fun(a) { a }; match f(1 + 1) { 2 => \"two\", _ => \"no\" }",
+        ];
+        for program in programs {
+            let plain = evaluator::eval(parse(program), &mut Environment::new());
+            let fast = evaluator::eval(optimize(parse(program)), &mut Environment::new());
+            assert_eq!(fast.to_string(), plain.to_string(), "{}", program);
+        }
+    }
+}
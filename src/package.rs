@@ -0,0 +1,403 @@
+//! Packages: a directory with a `lodestone.toml` manifest naming the package,
+//! the file it starts from, and the local directories it depends on:
+//!
+//! ```toml
+//! [package]
+//! name = "app"
+//! entry = "main.ld"
+//!
+//! [dependencies]
+//! geometry = { path = "../geometry" }
+//! strings = { path = "vendor/strings" }
+//! ```
+//!
+//! Everything is found on disk; there is no registry. `lodestone.lock`
+//! records where each dependency was found and a checksum of its sources, so
+//! a changed dependency is noticed.
+
+use std::fs;
+use std::path::Path;
+
+use super::module::{self, Module, ModuleResolver, FileResolver, EXTENSION};
+
+pub const MANIFEST_FILE: &str = "lodestone.toml";
+pub const LOCK_FILE: &str = "lodestone.lock";
+
+/// The entry point of a package that doesn't name one.
+const DEFAULT_ENTRY: &str = "main.ld";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Manifest {
+    pub name: String,
+    pub entry: String,
+    /// Each dependency's name and directory, relative to the package.
+    pub dependencies: Vec<(String, String)>
+}
+
+impl Manifest {
+    /// Reads a `lodestone.toml`: `name` and optional `entry` under
+    /// `[package]`, and `name = { path = "dir" }` lines under
+    /// `[dependencies]`. `#` comments are allowed.
+    pub fn parse(text: &str) -> Result<Manifest, String> {
+        let mut name = None;
+        let mut entry = String::from(DEFAULT_ENTRY);
+        let mut dependencies: Vec<(String, String)> = vec![];
+        let mut section = String::new();
+        for (i, raw) in text.lines().enumerate() {
+            let line = match raw.find('#') {
+                Some(pos) => &raw[..pos],
+                None => raw
+            }.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                section = line.to_string();
+                if section != "[package]" && section != "[dependencies]" {
+                    return Err(format!("line {}: unknown section {}", i + 1, section));
+                }
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim().trim_matches('"');
+            let value = match parts.next() {
+                Some(v) => v.trim(),
+                None => return Err(format!("line {}: expected `key = value`", i + 1))
+            };
+            match (section.as_ref(), key) {
+                ("[package]", "name") => {
+                    let value = quoted(value).ok_or_else(|| format!("line {}: expected a string for name", i + 1))?;
+                    if !is_package_name(&value) {
+                        return Err(format!("line {}: package name '{}' must start with a letter and hold only letters, digits, '_' and '-'", i + 1, value));
+                    }
+                    name = Some(value);
+                },
+                ("[package]", "entry") => {
+                    entry = quoted(value).ok_or_else(|| format!("line {}: expected a string for entry", i + 1))?;
+                },
+                ("[dependencies]", _) => {
+                    if !is_package_name(key) {
+                        return Err(format!("line {}: '{}' is not a package name", i + 1, key));
+                    }
+                    if dependencies.iter().any(|(d, _)| d == key) {
+                        return Err(format!("line {}: duplicate dependency '{}'", i + 1, key));
+                    }
+                    let path = dependency_path(value).ok_or_else(|| format!("line {}: expected `{} = {{ path = \"DIR\" }}`", i + 1, key))?;
+                    dependencies.push((key.to_string(), path));
+                },
+                ("", _) => {
+                    return Err(format!("line {}: '{}' is outside a section", i + 1, key));
+                },
+                (_, _) => {
+                    return Err(format!("line {}: unknown key '{}' in {}", i + 1, key, section));
+                }
+            }
+        }
+        match name {
+            Some(name) => Ok(Manifest{name, entry, dependencies}),
+            None => Err(String::from("missing `name` in [package]"))
+        }
+    }
+}
+
+/// The contents of a `"string"`, without escapes.
+fn quoted(value: &str) -> Option<String> {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        let inner = &value[1..value.len() - 1];
+        if !inner.contains('"') {
+            return Some(inner.to_string());
+        }
+    }
+    None
+}
+
+/// The directory in `{ path = "dir" }`.
+fn dependency_path(value: &str) -> Option<String> {
+    if !value.starts_with('{') || !value.ends_with('}') {
+        return None;
+    }
+    let mut parts = value[1..value.len() - 1].splitn(2, '=');
+    match (parts.next().map(|k| k.trim()), parts.next()) {
+        (Some("path"), Some(path)) => quoted(path.trim()).filter(|p| !p.is_empty()),
+        _ => None
+    }
+}
+
+fn is_package_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() => chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+        _ => false
+    }
+}
+
+/// A package found on disk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Package {
+    /// The package's directory, as a `/`-separated path.
+    pub dir: String,
+    pub manifest: Manifest
+}
+
+/// A package and, after it, every package it depends on directly or not.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Packages {
+    pub packages: Vec<Package>
+}
+
+impl Packages {
+    /// Reads the manifest in `dir` and those of all its dependencies. Two
+    /// different directories may not use the same package name.
+    pub fn load(dir: &str) -> Result<Packages, String> {
+        let mut packages: Vec<Package> = vec![read_package(dir)?];
+        let mut next = 0;
+        while next < packages.len() {
+            let package = packages[next].clone();
+            next += 1;
+            for (name, path) in &package.manifest.dependencies {
+                let dir = module::join(&package.dir, path);
+                if let Some(existing) = packages.iter().find(|p| p.manifest.name == *name) {
+                    if existing.dir != dir {
+                        return Err(format!("two packages named {}: {} and {}", name, existing.dir, dir));
+                    }
+                    continue;
+                }
+                let dependency = read_package(&dir)?;
+                if dependency.manifest.name != *name {
+                    return Err(format!("{}/{} names package '{}', but {} depends on it as '{}'",
+                        dir, MANIFEST_FILE, dependency.manifest.name, package.manifest.name, name));
+                }
+                packages.push(dependency);
+            }
+        }
+        Ok(Packages{packages})
+    }
+
+    /// The package the manifest was loaded from.
+    pub fn root(&self) -> &Package {
+        &self.packages[0]
+    }
+
+    /// The file the root package runs.
+    pub fn entry(&self) -> String {
+        module::join(&self.root().dir, &self.root().manifest.entry)
+    }
+
+    fn named(&self, name: &str) -> Option<&Package> {
+        self.packages.iter().find(|p| p.manifest.name == name)
+    }
+
+    /// The package a module belongs to: the one with the longest directory
+    /// containing it, or the root.
+    fn containing(&self, module: &str) -> &Package {
+        self.packages[1..].iter()
+            .filter(|p| module.starts_with(&format!("{}/", p.dir)))
+            .max_by_key(|p| p.dir.len())
+            .unwrap_or(self.root())
+    }
+
+    /// The text of `lodestone.lock` for these packages: every dependency
+    /// with its directory, a checksum of its sources and its own
+    /// dependencies, sorted by name.
+    pub fn lockfile(&self) -> Result<String, String> {
+        let mut out = format!("# Written by lodestone for {}. Do not edit.\nversion = 1\n", self.root().manifest.name);
+        let mut dependencies: Vec<&Package> = self.packages[1..].iter().collect();
+        dependencies.sort_by(|a, b| a.manifest.name.cmp(&b.manifest.name));
+        for package in dependencies {
+            let names: Vec<String> = package.manifest.dependencies.iter().map(|(n, _)| format!("\"{}\"", n)).collect();
+            out.push_str(&format!("\n[[package]]\nname = \"{}\"\npath = \"{}\"\nchecksum = \"{:016x}\"\ndependencies = [{}]\n",
+                package.manifest.name, package.dir, checksum(&package.dir)?, names.join(", ")));
+        }
+        Ok(out)
+    }
+}
+
+fn read_package(dir: &str) -> Result<Package, String> {
+    let path = format!("{}/{}", dir, MANIFEST_FILE);
+    let text = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+    let manifest = Manifest::parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+    Ok(Package{dir: dir.to_string(), manifest})
+}
+
+/// A 64-bit FNV-1a hash of every module in `dir` and its subdirectories,
+/// with their paths. Subdirectories that are packages of their own are left
+/// to their own checksum.
+fn checksum(dir: &str) -> Result<u64, String> {
+    let mut files = vec![];
+    source_files(Path::new(dir), "", &mut files)?;
+    files.sort();
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for name in files {
+        let contents = fs::read(Path::new(dir).join(&name)).map_err(|e| format!("{}/{}: {}", dir, name, e))?;
+        for byte in name.bytes().chain(Some(0)).chain(contents).chain(Some(0)) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    Ok(hash)
+}
+
+fn source_files(dir: &Path, prefix: &str, files: &mut Vec<String>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let path = entry.path();
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if path.is_dir() {
+            if !path.join(MANIFEST_FILE).exists() {
+                source_files(&path, &format!("{}/", name), files)?;
+            }
+        } else if path.extension().is_some_and(|e| e == EXTENSION) {
+            files.push(name);
+        }
+    }
+    Ok(())
+}
+
+/// Finds modules for a package: `import "geometry/shapes"` is
+/// `shapes.ld` in the directory of the `geometry` dependency, and
+/// `import "geometry"` is its entry point. A package can only import the
+/// packages its own manifest lists. Other paths are files, as with
+/// `FileResolver`.
+pub struct PackageResolver {
+    packages: Packages
+}
+
+impl PackageResolver {
+    pub fn new(packages: Packages) -> PackageResolver {
+        PackageResolver{packages}
+    }
+}
+
+impl ModuleResolver for PackageResolver {
+    fn resolve(&self, path: &str, importer: Option<&str>) -> Result<Module, String> {
+        let from = match importer {
+            Some(importer) => self.packages.containing(importer),
+            None => self.packages.root()
+        };
+        let mut parts = path.splitn(2, '/');
+        let first = parts.next().unwrap_or("");
+        let rest = parts.next();
+        let depends = from.manifest.dependencies.iter().any(|(name, _)| name == first);
+        match self.packages.named(first) {
+            Some(package) if depends => {
+                let target = rest.unwrap_or(&package.manifest.entry[..]);
+                FileResolver::new(&package.dir).resolve(target, None)
+            },
+            found => {
+                let files = FileResolver::new(&self.packages.root().dir);
+                match files.resolve(path, importer) {
+                    Err(_) if found.is_some() && first != from.manifest.name => {
+                        Err(format!("package {} does not depend on {}", from.manifest.name, first))
+                    },
+                    result => result
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::{Manifest, Packages, PackageResolver};
+    use super::super::interpreter::Interpreter;
+    use super::super::module::ModuleResolver;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = Manifest::parse("[package]
+name = \"app\" # the application
+entry = \"src/app.ld\"
+
+[dependencies]
+geometry = { path = \"../geometry\" }
+strings = {path=\"vendor/strings\"}
+").unwrap();
+        assert_eq!(manifest, Manifest{
+            name: String::from("app"),
+            entry: String::from("src/app.ld"),
+            dependencies: vec![
+                (String::from("geometry"), String::from("../geometry")),
+                (String::from("strings"), String::from("vendor/strings")),
+            ]
+        });
+        assert_eq!(Manifest::parse("[package]\nname = \"app\"").unwrap().entry, "main.ld");
+
+        let errors = vec![
+            ("[dependencies]\na = { path = \"a\" }", "missing `name` in [package]"),
+            ("name = \"app\"", "line 1: 'name' is outside a section"),
+            ("[package]\nname = app", "line 2: expected a string for name"),
+            ("[package]\nname = \"1app\"", "line 2: package name '1app' must start with a letter and hold only letters, digits, '_' and '-'"),
+            ("[package]\nversion = \"1\"", "line 2: unknown key 'version' in [package]"),
+            ("[registry]", "line 1: unknown section [registry]"),
+            ("[dependencies]\ngeometry = \"1.0\"", "line 2: expected `geometry = { path = \"DIR\" }`"),
+            ("[dependencies]\na = { path = \"a\" }\na = { path = \"b\" }", "line 3: duplicate dependency 'a'"),
+        ];
+        for (input, expected) in errors {
+            assert_eq!(Manifest::parse(input), Err(String::from(expected)));
+        }
+    }
+
+    fn write(root: &str, files: &[(&str, &str)]) {
+        for &(name, contents) in files {
+            let path = format!("{}/{}", root, name);
+            fs::create_dir_all(&path[..path.rfind('/').unwrap()]).unwrap();
+            fs::write(&path, contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_packages() {
+        let root = env::temp_dir().join(format!("lodestone-package-test-{}", ::std::process::id()));
+        let root = root.to_str().unwrap().to_string();
+        write(&root, &[
+            ("app/lodestone.toml", "[package]\nname = \"app\"\n[dependencies]\ngeometry = { path = \"../geometry\" }\nstrings = { path = \"vendor/strings\" }"),
+            ("app/main.ld", "import \"geometry/shapes\"; import \"strings\"; import \"./lib/local\"; shapes.area(2) + strings.width + local.one"),
+            ("app/lib/local.ld", "export let one := 1;"),
+            ("app/vendor/strings/lodestone.toml", "[package]\nname = \"strings\"\nentry = \"strings.ld\""),
+            ("app/vendor/strings/strings.ld", "export let width := 10;"),
+            ("geometry/lodestone.toml", "[package]\nname = \"geometry\"\n[dependencies]\nstrings = { path = \"../app/vendor/strings\" }"),
+            ("geometry/shapes.ld", "import \"strings\"; export let area := fun(x) { x * x * strings.width };"),
+        ]);
+        let app = format!("{}/app", root);
+        let packages = Packages::load(&app).unwrap();
+        let names: Vec<&str> = packages.packages.iter().map(|p| p.manifest.name.as_ref()).collect();
+        assert_eq!(names, vec!["app", "geometry", "strings"]);
+        assert_eq!(packages.packages[2].dir, format!("{}/app/vendor/strings", root));
+        assert_eq!(packages.entry(), format!("{}/app/main.ld", root));
+
+        let resolver = PackageResolver::new(packages.clone());
+        assert_eq!(resolver.resolve("geometry/shapes", None).unwrap().name, format!("{}/geometry/shapes.ld", root));
+        assert_eq!(resolver.resolve("strings", Some(&packages.entry())).unwrap().name, format!("{}/app/vendor/strings/strings.ld", root));
+        let shapes = format!("{}/geometry/shapes.ld", root);
+        assert_eq!(resolver.resolve("app/main", Some(&shapes)), Err(String::from("package geometry does not depend on app")));
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_module_resolver(resolver);
+        let result = interpreter.eval_file(packages.entry()).unwrap();
+        assert_eq!(result.to_string(), "51");
+
+        let lock = packages.lockfile().unwrap();
+        assert!(lock.contains(&format!("[[package]]\nname = \"geometry\"\npath = \"{}/geometry\"\nchecksum = \"", root)));
+        assert!(lock.contains("dependencies = [\"strings\"]\n\n[[package]]\nname = \"strings\""));
+        assert_eq!(Packages::load(&app).unwrap().lockfile().unwrap(), lock);
+        write(&root, &[("geometry/shapes.ld", "export let area := fun(x) { x };")]);
+        assert!(Packages::load(&app).unwrap().lockfile().unwrap() != lock);
+
+        write(&root, &[("geometry/lodestone.toml", "[package]\nname = \"geometry\"\n[dependencies]\nmissing = { path = \"missing\" }")]);
+        assert_eq!(Packages::load(&app), Err(format!("{}/geometry/missing/lodestone.toml: No such file or directory (os error 2)", root)));
+        write(&root, &[
+            ("geometry/lodestone.toml", "[package]\nname = \"geometry\"\n[dependencies]\nstrings = { path = \"strings\" }"),
+            ("geometry/strings/lodestone.toml", "[package]\nname = \"strings\""),
+        ]);
+        assert_eq!(Packages::load(&app), Err(format!("two packages named strings: {0}/app/vendor/strings and {0}/geometry/strings", root)));
+        write(&root, &[("geometry/strings/lodestone.toml", "[package]\nname = \"text\"")]);
+        write(&root, &[("app/lodestone.toml", "[package]\nname = \"app\"\n[dependencies]\nstrings = { path = \"../geometry/strings\" }")]);
+        assert_eq!(Packages::load(&app), Err(format!("{}/geometry/strings/lodestone.toml names package 'text', but app depends on it as 'strings'", root)));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,24 +1,142 @@
-use std::collections::HashMap;
+use std::fmt;
 
 use super::ast::NodeKind;
 use super::ast::ExpressionKind;
 use super::ast::StatementKind;
+use super::ast::PatternKind;
+use super::ast::MatchArm;
+use super::ast::Binding;
+use super::ast::TypeAnnotation;
+use super::ast::EnumVariant;
 use super::token;
 use super::lexer;
+use super::module;
+use super::operator;
+use super::operator::Associativity;
 
-const LOWEST: u8 = 0;
-const EQUALS: u8 = 1;
-const LESSGREATER: u8 = 2;
-const SUM: u8 = 3;
-const PRODUCT: u8 = 4;
-const MODULUS: u8 = 5;
-const PREFIX: u8 = 6;
-const CALL: u8 = 7;
-const INDEX: u8 = 8;
+pub const LOWEST: u8 = 0;
+pub const PIPELINE: u8 = 1;
+pub const COALESCE: u8 = 2;
+pub const EQUALS: u8 = 3;
+pub const LESSGREATER: u8 = 4;
+pub const SUM: u8 = 5;
+pub const PRODUCT: u8 = 6;
+pub const MODULUS: u8 = 7;
+pub const PREFIX: u8 = 8;
+pub const CALL: u8 = 9;
+pub const INDEX: u8 = 10;
+
+type PrefixParseFn = fn(&mut Parser) -> Option<Box<ExpressionKind>>;
+type InfixParseFn = fn(&mut Parser, Option<Box<ExpressionKind>>) -> Option<Box<ExpressionKind>>;
+
+/// One row of the Pratt parser's operator table. `precedence` and
+/// `associativity` only matter for tokens with an `infix` parser.
+struct Operator {
+    token: token::TokenType,
+    precedence: u8,
+    associativity: Associativity,
+    prefix: Option<PrefixParseFn>,
+    infix: Option<InfixParseFn>,
+}
+
+static OPERATORS: &[Operator] = &[
+    Operator { token: token::INT, precedence: LOWEST, associativity: Associativity::Left, prefix: Some(Parser::parser_integer_literal), infix: None },
+    Operator { token: token::IDENT, precedence: LOWEST, associativity: Associativity::Left, prefix: Some(Parser::parse_identifier), infix: None },
+    Operator { token: token::STRING, precedence: LOWEST, associativity: Associativity::Left, prefix: Some(Parser::parse_string_literal), infix: None },
+    Operator { token: token::TRUE, precedence: LOWEST, associativity: Associativity::Left, prefix: Some(Parser::parse_boolean), infix: None },
+    Operator { token: token::FALSE, precedence: LOWEST, associativity: Associativity::Left, prefix: Some(Parser::parse_boolean), infix: None },
+    Operator { token: token::NULL, precedence: LOWEST, associativity: Associativity::Left, prefix: Some(Parser::parse_null_literal), infix: None },
+    Operator { token: token::IF, precedence: LOWEST, associativity: Associativity::Left, prefix: Some(Parser::parse_if_expression), infix: None },
+    Operator { token: token::FUNCTION, precedence: LOWEST, associativity: Associativity::Left, prefix: Some(Parser::parse_function_literal), infix: None },
+    Operator { token: token::MATCH, precedence: LOWEST, associativity: Associativity::Left, prefix: Some(Parser::parse_match_expression), infix: None },
+    Operator { token: token::LBRACE, precedence: LOWEST, associativity: Associativity::Left, prefix: Some(Parser::parse_hash_literal), infix: None },
+    Operator { token: token::BANG, precedence: LOWEST, associativity: Associativity::Left, prefix: Some(Parser::parse_prefix_expression), infix: None },
+    Operator { token: token::PIPELINE, precedence: PIPELINE, associativity: Associativity::Left, prefix: None, infix: Some(Parser::parse_infix_expression) },
+    Operator { token: token::COALESCE, precedence: COALESCE, associativity: Associativity::Right, prefix: None, infix: Some(Parser::parse_infix_expression) },
+    Operator { token: token::EQ, precedence: EQUALS, associativity: Associativity::Left, prefix: None, infix: Some(Parser::parse_infix_expression) },
+    Operator { token: token::NOT_EQ, precedence: EQUALS, associativity: Associativity::Left, prefix: None, infix: Some(Parser::parse_infix_expression) },
+    Operator { token: token::LT, precedence: LESSGREATER, associativity: Associativity::Left, prefix: None, infix: Some(Parser::parse_infix_expression) },
+    Operator { token: token::GT, precedence: LESSGREATER, associativity: Associativity::Left, prefix: None, infix: Some(Parser::parse_infix_expression) },
+    Operator { token: token::PLUS, precedence: SUM, associativity: Associativity::Left, prefix: None, infix: Some(Parser::parse_infix_expression) },
+    Operator { token: token::MINUS, precedence: SUM, associativity: Associativity::Left, prefix: Some(Parser::parse_prefix_expression), infix: Some(Parser::parse_infix_expression) },
+    Operator { token: token::SLASH, precedence: PRODUCT, associativity: Associativity::Left, prefix: None, infix: Some(Parser::parse_infix_expression) },
+    Operator { token: token::ASTERISK, precedence: PRODUCT, associativity: Associativity::Left, prefix: None, infix: Some(Parser::parse_infix_expression) },
+    Operator { token: token::MODULO, precedence: MODULUS, associativity: Associativity::Left, prefix: None, infix: Some(Parser::parse_infix_expression) },
+    Operator { token: token::LPAREN, precedence: CALL, associativity: Associativity::Left, prefix: Some(Parser::parse_grouped_expression), infix: Some(Parser::parse_call_expression) },
+    Operator { token: token::LBRACKET, precedence: INDEX, associativity: Associativity::Left, prefix: Some(Parser::parse_array_literal), infix: Some(Parser::parse_index_expression) },
+    Operator { token: token::OPTIONAL_LBRACKET, precedence: INDEX, associativity: Associativity::Left, prefix: None, infix: Some(Parser::parse_index_expression) },
+    Operator { token: token::SLOT, precedence: INDEX, associativity: Associativity::Left, prefix: None, infix: Some(Parser::parse_slot_expression) },
+    Operator { token: token::OPTIONAL_SLOT, precedence: INDEX, associativity: Associativity::Left, prefix: None, infix: Some(Parser::parse_slot_expression) },
+];
+
+fn lookup_operator(t: &token::TokenType) -> Option<&'static Operator> {
+    OPERATORS.iter().find(|op| op.token == *t)
+}
+
+/// Precedence and associativity of the infix operator spelled `symbol`,
+/// including host-registered ones.
+pub fn infix_binding(symbol: &str) -> Option<(u8, Associativity)> {
+    match OPERATORS.iter().find(|op| op.token.name == symbol && op.infix.is_some()) {
+        Some(op) => {
+            Some((op.precedence, op.associativity))
+        },
+        None => {
+            operator::lookup(symbol).map(|op| (op.precedence, op.associativity))
+        }
+    }
+}
+
+/// Operator tokens of the language that have no entry in `OPERATORS`.
+const OTHER_OPERATOR_TOKENS: [token::TokenType; 7] = [
+    token::ASSIGN, token::REASSIGN, token::FAT_ARROW, token::ARROW, token::PIPE, token::COLON, token::SLOT
+];
+
+fn builtin_operators() -> Vec<&'static str> {
+    OPERATORS.iter().map(|op| op.token.name).chain(OTHER_OPERATOR_TOKENS.iter().map(|t| t.name)).collect()
+}
+
+/// Whether `symbol` is already an operator token of the language.
+pub fn is_builtin_operator(symbol: &str) -> bool {
+    builtin_operators().contains(&symbol)
+}
+
+/// A longer operator token of the language that starts with `symbol`, and
+/// would stop being lexed as one if `symbol` were an operator too.
+pub fn builtin_operator_extending(symbol: &str) -> Option<&'static str> {
+    builtin_operators().into_iter().find(|name| name.len() > symbol.len() && name.starts_with(symbol))
+}
+
+/// A syntax error at `span`: what the parser was looking for and the token it got.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub span: token::Span,
+    pub expected: String,
+    pub found: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: expected {}, found {}", self.span, self.expected, self.found)
+    }
+}
+
+fn describe_token(token: &token::Token) -> String {
+    match token.t_type {
+        token::EOF => {
+            String::from("end of input")
+        },
+        token::STRING => {
+            format!("\"{}\"", token.literal)
+        },
+        _ => {
+            format!("'{}'", token.literal)
+        }
+    }
+}
 
 pub struct Parser {
     lexer: lexer::Lexer,
-    pub errors: Vec<String>,
+    pub errors: Vec<ParseError>,
     cur_token: token::Token,
     peek_token: token::Token,
 }
@@ -37,132 +155,43 @@ impl Parser {
         return parser;
     }
 
+    /// Comments the lexer skipped so far, in source order.
+    pub fn comments(&self) -> &[token::Comment] {
+        &self.lexer.comments
+    }
+
     fn prefix_parse_call(&mut self, token: token::Token) -> Option<Box<ExpressionKind>> {
-        match token.t_type {
-            token::BANG => {
-                return self.parse_prefix_expression();
+        match lookup_operator(&token.t_type).and_then(|op| op.prefix) {
+            Some(prefix) => {
+                prefix(self)
             },
-            token::MINUS => {
-                return self.parse_prefix_expression();
-            },
-            token::INT => {
-                return self.parser_integer_literal();
-            },
-            token::IDENT => {
-                return self.parse_identifier();
-            },
-            token::TRUE => {
-                return self.parse_boolean();
-            },
-            token::FALSE => {
-                return self.parse_boolean();
-            },
-            token::LPAREN => {
-                return self.parse_grouped_expression();
-            },
-            token::IF => {
-                return self.parse_if_expression();
-            },
-            token::FUNCTION => {
-                return self.parse_function_literal();
-            },
-            token::STRING => {
-                return self.parse_string_literal();
-            },
-            /*token::WHILE => {
-                return self.parse_while_literal();
-            },
-            token::IMPORT => {
-                return self.parse_import_literal();
-            },
-            */
-            token::LBRACKET => {
-                return self.parse_array_literal();
-            }
-            _ => {
-                return None;
+            None => {
+                None
             }
         }
     }
 
     fn infix_parse_call(&mut self, token: token::Token, expression: Option<Box<ExpressionKind>>) -> Option<Box<ExpressionKind>> {
-        match token.t_type {
-            token::PLUS => {
-                return self.parse_infix_expression(expression);
-            },
-            token::MINUS => {
-                return self.parse_infix_expression(expression);
-            },
-            token::MODULO => {
-                return self.parse_infix_expression(expression);;
-            },
-            token::SLASH => {
-                return self.parse_infix_expression(expression);;
-            },
-            token::ASTERISK => {
-                return self.parse_infix_expression(expression);
-            },
-            token::EQ => {
-                return self.parse_infix_expression(expression);
-            },
-            token::NOT_EQ => {
-                return self.parse_infix_expression(expression);
-            },
-            token::LT => {
-                return self.parse_infix_expression(expression);
-            },
-            token::GT => {
-                return self.parse_infix_expression(expression);
-            },
-            token::LPAREN => {
-                return self.parse_call_expression(expression);
+        match lookup_operator(&token.t_type).and_then(|op| op.infix) {
+            Some(infix) => {
+                infix(self, expression)
             },
-            token::LBRACKET => {
-                return self.parse_index_expression(expression);
+            None if operator::lookup(token.t_type.name).is_some() => {
+                self.parse_infix_expression(expression)
             },
-            _ => {
-                return None;
+            None => {
+                None
             }
         }
     }
 
     fn has_infix(&mut self,  token: token::Token) -> bool {
-        match token.t_type {
-            token::PLUS => {
-                return true;
-            },
-            token::MINUS => {
-                return true;
-            },
-            token::MODULO => {
-                return true;
-            },
-            token::SLASH => {
-                return true;
-            },
-            token::ASTERISK => {
-                return true;
-            },
-            token::EQ => {
-                return true;
-            },
-            token::NOT_EQ => {
-                return true;
-            },
-            token::LT => {
-                return true;
-            },
-            token::GT => {
-                return true;
-            },
-            token::LPAREN => {
-                return true;
-            },
-            token::LBRACKET => {
-                return true;
+        match lookup_operator(&token.t_type) {
+            Some(op) => {
+                op.infix.is_some()
             },
-            _ => {
-                return false;
+            None => {
+                operator::lookup(token.t_type.name).is_some()
             }
         }
     }
@@ -172,31 +201,81 @@ impl Parser {
         self.peek_token = self.lexer.next_token();
     }
 
+    /// Parses every statement in the input. Statements containing syntax
+    /// errors are dropped and parsing resumes at the next statement, so
+    /// `errors` ends up holding every error in the input rather than the first.
     pub fn parse_program(&mut self) -> NodeKind {
         let mut stmt_vec: Vec<StatementKind> = Vec::new();
         while self.cur_token.t_type != token::EOF {
+            let errors_before = self.errors.len();
             let stmt = self.parse_statement();
             match stmt {
-                Some(x) => {
+                Some(x) if self.errors.len() == errors_before => {
                     stmt_vec.push(*x);
                 },
-                None => {}
+                _ => {
+                    self.synchronize();
+                }
             }
             self.next_token();
         }
         return NodeKind::ProgramNode { statements: stmt_vec };
     }
 
+    /// Skips the rest of a broken statement, stopping on its `;`, just
+    /// before the start of the next statement, or on the `}` closing the
+    /// enclosing block. Braced bodies inside the statement are skipped whole.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        loop {
+            if self.cur_token_is(token::EOF) {
+                return;
+            }
+            if self.cur_token_is(token::LBRACE) {
+                depth += 1;
+            } else if self.cur_token_is(token::RBRACE) {
+                if depth == 0 {
+                    return;
+                }
+                depth -= 1;
+            }
+            if depth == 0 {
+                if self.cur_token_is(token::SEMICOLON) {
+                    return;
+                }
+                if self.peek_token_is(token::RBRACE) || self.peek_token_is(token::LET) || self.peek_token_is(token::RETURN) || self.peek_token_is(token::EOF) {
+                    return;
+                }
+            }
+            self.next_token();
+        }
+    }
+
     fn parse_statement(&mut self) -> Option<Box<StatementKind>> {
         match self.cur_token.t_type {
-            token::LET => {
-                return self.parse_let_statement();
+            token::LET | token::CONST => {
+                self.parse_let_statement()
             },
             token::RETURN => {
-                return self.parse_return_statement();
+                self.parse_return_statement()
+            },
+            token::STRUCT => {
+                self.parse_struct_statement()
+            },
+            token::ENUM => {
+                self.parse_enum_statement()
+            },
+            token::DELETE => {
+                self.parse_delete_statement()
+            },
+            token::IMPORT => {
+                self.parse_import_statement()
+            },
+            token::EXPORT => {
+                self.parse_export_statement()
             },
             _ => {
-                return self.parse_expression_statement();
+                self.parse_expression_statement()
             }
         }
     }
@@ -211,9 +290,15 @@ impl Parser {
 
     fn peek_error(&mut self, t: token::TokenType) {
         let token = self.peek_token.clone();
-        let msg = String::from(format!("expected next token to be {}, got {} instead",
-        t.name, token.t_type.name));
-        self.errors.push(msg);
+        self.error_at(&token, format!("'{}'", t.name));
+    }
+
+    fn error_at(&mut self, token: &token::Token, expected: String) {
+        self.errors.push(ParseError {
+            span: token.span,
+            expected,
+            found: describe_token(token),
+        });
     }
 
     fn expect_peek(&mut self, t: token::TokenType) -> bool {
@@ -231,22 +316,130 @@ impl Parser {
         if !self.expect_peek(token::IDENT) {
             return None;
         }
-        let name = ExpressionKind::Identifier {token: self.cur_token.clone(), value: self.cur_token.clone().literal };
+        let name = ExpressionKind::Identifier {token: self.cur_token.clone(), value: self.cur_token.clone().literal, binding: Binding::Unresolved };
+        let annotation = match self.parse_optional_annotation() {
+            Ok(a) => a,
+            Err(()) => {
+                return None;
+            }
+        };
         if !self.expect_peek(token::ASSIGN) {
             return None;
         }
         self.next_token();
-        let value = self.parse_expression(LOWEST);
+        let value = Some(self.parse_expression(LOWEST)?);
+        if self.peek_token_is(token::SEMICOLON) {
+            self.next_token();
+        }
+        let constant = token.t_type == token::CONST;
+        Some(Box::new(StatementKind::LetStatement { token, name, annotation, value, constant }))
+    }
+
+    /// `struct Name { field, ... }`, with an optional trailing comma.
+    fn parse_struct_statement(&mut self) -> Option<Box<StatementKind>> {
+        let token = self.cur_token.clone();
+        if !self.expect_peek(token::IDENT) {
+            return None;
+        }
+        let name = ExpressionKind::Identifier {token: self.cur_token.clone(), value: self.cur_token.literal.clone(), binding: Binding::Unresolved };
+        if !self.expect_peek(token::LBRACE) {
+            return None;
+        }
+        let mut fields = vec![];
+        while !self.peek_token_is(token::RBRACE) {
+            if !self.expect_peek(token::IDENT) {
+                return None;
+            }
+            fields.push(self.cur_token.literal.clone());
+            if !self.peek_token_is(token::COMMA) {
+                break;
+            }
+            self.next_token();
+        }
+        if !self.expect_peek(token::RBRACE) {
+            return None;
+        }
+        if self.peek_token_is(token::SEMICOLON) {
+            self.next_token();
+        }
+        Some(Box::new(StatementKind::StructStatement { token, name, fields }))
+    }
+
+    /// `enum Name { Variant(field, ...), Variant, ... }`, with an optional
+    /// trailing comma.
+    fn parse_enum_statement(&mut self) -> Option<Box<StatementKind>> {
+        let token = self.cur_token.clone();
+        if !self.expect_peek(token::IDENT) {
+            return None;
+        }
+        let name = ExpressionKind::Identifier {token: self.cur_token.clone(), value: self.cur_token.literal.clone(), binding: Binding::Unresolved };
+        if !self.expect_peek(token::LBRACE) {
+            return None;
+        }
+        let mut variants = vec![];
+        while !self.peek_token_is(token::RBRACE) {
+            if !self.expect_peek(token::IDENT) {
+                return None;
+            }
+            let variant_token = self.cur_token.clone();
+            let mut fields = vec![];
+            if self.peek_token_is(token::LPAREN) {
+                self.next_token();
+                while !self.peek_token_is(token::RPAREN) {
+                    if !self.expect_peek(token::IDENT) {
+                        return None;
+                    }
+                    fields.push(self.cur_token.literal.clone());
+                    if !self.peek_token_is(token::COMMA) {
+                        break;
+                    }
+                    self.next_token();
+                }
+                if !self.expect_peek(token::RPAREN) {
+                    return None;
+                }
+            }
+            variants.push(EnumVariant { name: variant_token.literal.clone(), token: variant_token, fields });
+            if !self.peek_token_is(token::COMMA) {
+                break;
+            }
+            self.next_token();
+        }
+        if !self.expect_peek(token::RBRACE) {
+            return None;
+        }
         if self.peek_token_is(token::SEMICOLON) {
             self.next_token();
         }
-        Some(Box::new(StatementKind::LetStatement { token: token, name: name, value: value }))
+        Some(Box::new(StatementKind::EnumStatement { token, name, variants }))
+    }
+
+    /// Parses the type name after a `:` or `->`. Type names are identifiers,
+    /// plus the keywords `null` and `fun`.
+    fn parse_type_annotation(&mut self) -> Option<TypeAnnotation> {
+        if self.peek_token_is(token::IDENT) || self.peek_token_is(token::NULL) || self.peek_token_is(token::FUNCTION) {
+            self.next_token();
+            Some(TypeAnnotation { token: self.cur_token.clone(), name: self.cur_token.literal.clone() })
+        } else {
+            let token = self.peek_token.clone();
+            self.error_at(&token, String::from("a type name"));
+            None
+        }
+    }
+
+    /// Parses an optional `: type` after a parameter or `let` name.
+    fn parse_optional_annotation(&mut self) -> Result<Option<TypeAnnotation>, ()> {
+        if !self.peek_token_is(token::COLON) {
+            return Ok(None);
+        }
+        self.next_token();
+        self.parse_type_annotation().map(Some).ok_or(())
     }
 
     fn parse_return_statement(&mut self) -> Option<Box<StatementKind>> {
         let token = self.cur_token.clone();
         self.next_token();
-        let return_value = self.parse_expression(LOWEST);
+        let return_value = Some(self.parse_expression(LOWEST)?);
         if self.peek_token_is(token::SEMICOLON) {
             self.next_token();
         }
@@ -256,30 +449,132 @@ impl Parser {
 
     fn parse_expression_statement(&mut self) -> Option<Box<StatementKind>> {
         let token = self.cur_token.clone();
-        let expression = self.parse_expression(LOWEST);
+        let expression = Some(self.parse_expression(LOWEST)?);
+        if self.peek_token_is(token::REASSIGN) {
+            return self.parse_assign_statement(expression);
+        }
         if self.peek_token_is(token::SEMICOLON) {
             self.next_token();
         }
         Some(Box::new(StatementKind::ExpressionStatement{ token: token, expression: expression } ))
     }
 
+    /// `target = value`, where the target has already been parsed.
+    fn parse_assign_statement(&mut self, target: Option<Box<ExpressionKind>>) -> Option<Box<StatementKind>> {
+        self.next_token();
+        let token = self.cur_token.clone();
+        let target = match target {
+            Some(t) => match *t {
+                ExpressionKind::SlotExpression{optional: false, ..} => *t,
+                _ => {
+                    self.error_at(&token, String::from("a slot before '='"));
+                    return None;
+                }
+            },
+            None => {
+                return None;
+            }
+        };
+        self.next_token();
+        let value = match self.parse_expression(LOWEST) {
+            Some(v) => *v,
+            None => {
+                return None;
+            }
+        };
+        if self.peek_token_is(token::SEMICOLON) {
+            self.next_token();
+        }
+        Some(Box::new(StatementKind::AssignStatement{ token, target, value }))
+    }
+
+    /// `delete target`, where the target is a slot.
+    fn parse_delete_statement(&mut self) -> Option<Box<StatementKind>> {
+        let token = self.cur_token.clone();
+        self.next_token();
+        let target_token = self.cur_token.clone();
+        let target = match self.parse_expression(LOWEST) {
+            Some(t) => match *t {
+                ExpressionKind::SlotExpression{optional: false, ..} => *t,
+                _ => {
+                    self.error_at(&target_token, String::from("a slot to delete"));
+                    return None;
+                }
+            },
+            None => {
+                return None;
+            }
+        };
+        if self.peek_token_is(token::SEMICOLON) {
+            self.next_token();
+        }
+        Some(Box::new(StatementKind::DeleteStatement{ token, target }))
+    }
+
+    /// `import "path"` or `import "path" as name`. Without `as` the module is
+    /// bound to its file name less the extension, which must be an identifier.
+    fn parse_import_statement(&mut self) -> Option<Box<StatementKind>> {
+        let token = self.cur_token.clone();
+        if !self.expect_peek(token::STRING) {
+            return None;
+        }
+        let path_token = self.cur_token.clone();
+        let path = path_token.literal.clone();
+        let alias = self.peek_token_is(token::IDENT) && self.peek_token.literal == "as";
+        let name_token = if alias {
+            self.next_token();
+            if !self.expect_peek(token::IDENT) {
+                return None;
+            }
+            self.cur_token.clone()
+        } else {
+            let stem = module::stem(&path);
+            if !lexer::is_identifier(&stem) {
+                self.error_at(&path_token, String::from("'as' and a name for this module"));
+                return None;
+            }
+            token::Token { t_type: token::IDENT, literal: stem, span: path_token.span }
+        };
+        if self.peek_token_is(token::SEMICOLON) {
+            self.next_token();
+        }
+        let name = ExpressionKind::Identifier{ value: name_token.literal.clone(), token: name_token, binding: Binding::Unresolved };
+        Some(Box::new(StatementKind::ImportStatement{ token, path, name, alias }))
+    }
+
+    /// `export` before a `let`, `const`, `struct` or `enum`.
+    fn parse_export_statement(&mut self) -> Option<Box<StatementKind>> {
+        let token = self.cur_token.clone();
+        self.next_token();
+        let statement = match self.cur_token.t_type {
+            token::LET | token::CONST => self.parse_let_statement(),
+            token::STRUCT => self.parse_struct_statement(),
+            token::ENUM => self.parse_enum_statement(),
+            _ => {
+                let found = self.cur_token.clone();
+                self.error_at(&found, String::from("'let', 'const', 'struct' or 'enum' after 'export'"));
+                return None;
+            }
+        };
+        statement.map(|s| Box::new(StatementKind::ExportStatement{ token, statement: s }))
+    }
+
     fn parse_prefix_expression(&mut self) -> Option<Box<ExpressionKind>> {
         let token = self.cur_token.clone();
         let operator = self.cur_token.literal.clone();
         self.next_token();
-        let right = self.parse_expression(PREFIX);
+        let right = Some(self.parse_expression(PREFIX)?);
         Some(Box::new(ExpressionKind::PrefixExpression { token: token, operator: operator, right: right}))
     }
 
-    fn no_prefix_parse_fn_error(&mut self, t: token::TokenType) {
-        let msg = format!("no prefix parse function for {} found", t.name);
-        self.errors.push(msg);
+    fn no_prefix_parse_fn_error(&mut self, t: token::Token) {
+        self.error_at(&t, String::from("expression"));
     }
 
     fn parse_expression(&mut self, precedence: u8) -> Option<Box<ExpressionKind>> {
         let cur_token = self.cur_token.clone();
-        let peek_token = self.peek_token.clone();
-        let mut prefix = self.prefix_parse_call(cur_token.clone());
+        let errors_before = self.errors.len();
+        let prefix = self.prefix_parse_call(cur_token.clone());
         match prefix {
             Some(p) => {
                 let mut left_exp = Some(p);
@@ -290,30 +585,29 @@ impl Parser {
                         return left_exp;
                     }
                     self.next_token();
-                    left_exp = self.infix_parse_call(peek, left_exp);
+                    left_exp = Some(self.infix_parse_call(peek, left_exp)?);
                 }
                 return left_exp;
             },
             None => {
-                //let error_token = cur_token.clone();
-                self.no_prefix_parse_fn_error(cur_token.t_type);
-                println!("No prefix!");
+                // A prefix parser that failed has already said why.
+                if self.errors.len() == errors_before {
+                    self.no_prefix_parse_fn_error(cur_token);
+                }
                 return None;
             }
         }
-        //self.prefix_parse_call(cur_token.clone())
     }
 
     fn parse_identifier(&mut self) -> Option<Box<ExpressionKind>> {
-        Some(Box::new(ExpressionKind::Identifier { token: self.cur_token.clone(), value: self.cur_token.literal.clone()}))
+        Some(Box::new(ExpressionKind::Identifier { token: self.cur_token.clone(), value: self.cur_token.literal.clone(), binding: Binding::Unresolved}))
     }
 
     fn parser_integer_literal(&mut self) -> Option<Box<ExpressionKind>> {
         let cur_token = self.cur_token.clone();
         let wrapped_value = self.cur_token.literal.parse::<u32>();
         if wrapped_value.is_err() {
-            let msg = format!("could not parse {} as integer", self.cur_token.literal);
-            self.errors.push(msg);
+            self.error_at(&cur_token, String::from("integer that fits in 32 bits"));
             return None;
         }
         let value = wrapped_value.unwrap();
@@ -321,19 +615,28 @@ impl Parser {
     }
 
     pub fn precedences(&mut self, key: token::TokenType) -> Option<u8> {
-        let mut kw_map = HashMap::new();
-        kw_map.insert(token::EQ, EQUALS);
-        kw_map.insert(token::NOT_EQ, EQUALS);
-        kw_map.insert(token::LT, LESSGREATER);
-        kw_map.insert(token::GT, LESSGREATER);
-        kw_map.insert(token::PLUS, SUM);
-        kw_map.insert(token::MINUS, SUM);
-        kw_map.insert(token::MODULO, MODULUS);
-        kw_map.insert(token::SLASH, PRODUCT);
-        kw_map.insert(token::ASTERISK, PRODUCT);
-        kw_map.insert(token::LPAREN, CALL);
-        kw_map.insert(token::LBRACKET, INDEX);
-        kw_map.get(&key).cloned()
+        match lookup_operator(&key) {
+            Some(op) if op.infix.is_some() => {
+                Some(op.precedence)
+            },
+            Some(_) => {
+                None
+            },
+            None => {
+                operator::lookup(key.name).map(|op| op.precedence)
+            }
+        }
+    }
+
+    fn associativity(&mut self, key: token::TokenType) -> Associativity {
+        match lookup_operator(&key) {
+            Some(op) => {
+                op.associativity
+            },
+            None => {
+                operator::lookup(key.name).map(|op| op.associativity).unwrap_or(Associativity::Left)
+            }
+        }
     }
 
     fn peek_precedence(&mut self) -> u8 {
@@ -357,12 +660,19 @@ impl Parser {
     fn parse_infix_expression(&mut self, left: Option<Box<ExpressionKind>>) -> Option<Box<ExpressionKind>> {
         let cur_token = self.cur_token.clone();
         let operator = cur_token.literal.clone();
-        let precedence = self.cur_precedence();
+        let mut precedence = self.cur_precedence();
+        if self.associativity(cur_token.t_type.clone()) == Associativity::Right {
+            precedence -= 1;
+        }
         self.next_token();
-        let right = self.parse_expression(precedence);
+        let right = Some(self.parse_expression(precedence)?);
         Some(Box::new(ExpressionKind::InfixExpression { token: cur_token, operator: operator, left: left, right: right }))
     }
 
+    fn parse_null_literal(&mut self) -> Option<Box<ExpressionKind>> {
+        Some(Box::new(ExpressionKind::NullLiteral { token: self.cur_token.clone() }))
+    }
+
     fn parse_boolean(&mut self) -> Option<Box<ExpressionKind>> {
         let cur_token = self.cur_token.clone();
         Some(Box::new(ExpressionKind::BooleanExpression {token: cur_token, value: self.cur_token_is(token::TRUE)}))
@@ -371,7 +681,7 @@ impl Parser {
     fn parse_grouped_expression(&mut self) -> Option<Box<ExpressionKind>> {
         self.next_token();
         let exp = self.parse_expression(LOWEST);
-        if !self.expect_peek(token::RPAREN) {
+        if exp.is_none() || !self.expect_peek(token::RPAREN) {
             return None;
         }
         return exp;
@@ -384,38 +694,52 @@ impl Parser {
         }
         self.next_token();
         let condition = self.parse_expression(LOWEST);
-        if !self.expect_peek(token::RPAREN) {
+        if condition.is_none() || !self.expect_peek(token::RPAREN) {
             return None;
         }
         if !self.expect_peek(token::LBRACE) {
             return None;
         }
-        let consequence = self.parse_block_statement();
+        let consequence = Some(self.parse_block_statement()?);
         let mut alternative = None;
         if self.peek_token_is(token::ELSE) {
             self.next_token();
             if !self.expect_peek(token::LBRACE) {
                 return None;
             }
-            alternative = self.parse_block_statement();
+            alternative = Some(self.parse_block_statement()?);
         }
         Some(Box::new(ExpressionKind::IfExpression { token: cur_token, condition: condition, consequence: consequence, alternative: alternative } ))
     }
 
+    /// Parses statements up to the matching `}`. Broken statements are
+    /// reported and skipped like in `parse_program`; the block itself only
+    /// fails when its closing brace is missing.
     fn parse_block_statement(&mut self) -> Option<Box<StatementKind>> {
         let cur_token = self.cur_token.clone();
         let mut statements = Vec::new();
         self.next_token();
         while !self.cur_token_is(token::RBRACE) && !self.cur_token_is(token::EOF) {
+            let errors_before = self.errors.len();
             let stmt = self.parse_statement();
             match stmt {
-                Some(x) => {
-                    statements.push(x);
+                Some(ref x) if self.errors.len() == errors_before => {
+                    statements.push(x.clone());
                 },
-                None => {}
+                _ => {
+                    self.synchronize();
+                    if self.cur_token_is(token::RBRACE) {
+                        break;
+                    }
+                }
             }
             self.next_token();
         }
+        if self.cur_token_is(token::EOF) {
+            let eof = self.cur_token.clone();
+            self.error_at(&eof, String::from("'}'"));
+            return None;
+        }
         Some(Box::new(StatementKind::BlockStatement { token: cur_token, statements: statements}))
     }
 
@@ -424,60 +748,86 @@ impl Parser {
         if !self.expect_peek(token::LPAREN) {
             return None;
         }
-        let parameters = self.parse_function_parameters();
+        let (parameters, annotations) = match self.parse_function_parameters() {
+            Some(p) => p,
+            None => {
+                return None;
+            }
+        };
+        let return_type = if self.peek_token_is(token::ARROW) {
+            self.next_token();
+            match self.parse_type_annotation() {
+                Some(t) => Some(t),
+                None => {
+                    return None;
+                }
+            }
+        } else {
+            None
+        };
         if !self.expect_peek(token::LBRACE) {
             return None;
         }
         let body = self.parse_block_statement();
         match body {
             Some(b) => {
-                Some(Box::new(ExpressionKind::FunctionLiteral { token: cur_token, parameters: parameters, body: b }))
+                Some(Box::new(ExpressionKind::FunctionLiteral { token: cur_token, parameters, annotations, return_type, body: b }))
             },
             _ => {
-                panic!("Required function body");
+                None
             }
         }
     }
 
-    fn parse_function_parameters(&mut self) -> Vec<ExpressionKind> {
+    fn parse_function_parameters(&mut self) -> Option<(Vec<ExpressionKind>, Vec<Option<TypeAnnotation>>)> {
         let mut identifiers = Vec::new();
+        let mut annotations = Vec::new();
         if self.peek_token_is(token::RPAREN) {
             self.next_token();
-            return identifiers;
+            return Some((identifiers, annotations));
+        }
+        if !self.expect_peek(token::IDENT) {
+            return None;
         }
-        self.next_token();
         let cur_token = self.cur_token.clone();
-        let mut ident = ExpressionKind::Identifier{ token: cur_token, value: self.cur_token.literal.clone() };
+        let mut ident = ExpressionKind::Identifier{ token: cur_token, value: self.cur_token.literal.clone(), binding: Binding::Unresolved };
         identifiers.push(ident);
+        match self.parse_optional_annotation() {
+            Ok(a) => annotations.push(a),
+            Err(()) => {
+                return None;
+            }
+        }
         while self.peek_token_is(token::COMMA) {
             self.next_token();
-            self.next_token();
+            if !self.expect_peek(token::IDENT) {
+                return None;
+            }
             let cur_token = self.cur_token.clone();
-            ident = ExpressionKind::Identifier{token: cur_token, value: self.cur_token.literal.clone() };
+            ident = ExpressionKind::Identifier{token: cur_token, value: self.cur_token.literal.clone(), binding: Binding::Unresolved };
             identifiers.push(ident);
+            match self.parse_optional_annotation() {
+                Ok(a) => annotations.push(a),
+                Err(()) => {
+                    return None;
+                }
+            }
         }
         if !self.expect_peek(token::RPAREN) {
-            return vec![];
+            return None;
         }
-        return identifiers;
+        Some((identifiers, annotations))
     }
 
     fn parse_call_expression(&mut self, func: Option<Box<ExpressionKind>>) -> Option<Box<ExpressionKind>> {
         let args = self.parse_expression_list(token::RPAREN);
         let cur_token = self.cur_token.clone();
-        match func {
-            Some(f) => {
-                match args {
-                    Some(argsUnwrapped) => {
-                        Some(Box::new(ExpressionKind::CallExpression { token: cur_token, function: f, arguments: argsUnwrapped }))
-                    }
-                    None => {
-                        Some(Box::new(ExpressionKind::CallExpression { token: cur_token, function: f, arguments: vec![]}))
-                    }
-                }
+        match (func, args) {
+            (Some(f), Some(args)) => {
+                Some(Box::new(ExpressionKind::CallExpression { token: cur_token, function: f, arguments: args }))
             },
-            None => {
-                panic!("not a call expression.")
+            _ => {
+                None
             }
         }
     }
@@ -516,19 +866,11 @@ impl Parser {
         }
         Some(Box::new(ast::WhileLiteral { token: self.cur_token, consequence: self.parse_block_statement().unwrap() }))
     }
-
-    fn parse_import_literal(&mut self) -> Option<Box<astenum::ExpressionKind>> {
-        let cur_token = self.cur_token;
-        if !self.expect_peek(token::STRING) {
-            return None;
-        }
-        Some(Box::new(ast::ImportLiteral { token: cur_token, path: self.parse_string_literal() }))
-    }
         */
 
     fn parse_array_literal(&mut self) -> Option<Box<ExpressionKind>> {
         let cur_token = self.cur_token.clone();
-        Some(Box::new(ExpressionKind::ArrayLiteral { token: cur_token, elements: self.parse_expression_list(token::RBRACKET).unwrap() }))
+        self.parse_expression_list(token::RBRACKET).map(|elements| Box::new(ExpressionKind::ArrayLiteral { token: cur_token, elements }))
     }
 
     fn prase_index_expression(&mut self, left: Box<ExpressionKind>) -> Option<Box<ExpressionKind>> {
@@ -539,7 +881,7 @@ impl Parser {
         if !self.expect_peek(token::RBRACKET) {
             return None;
         }
-        Some(Box::new(ExpressionKind::IndexExpression { token: cur_token, left: left, index: index } ))
+        Some(Box::new(ExpressionKind::IndexExpression { token: cur_token, left, index, optional: false } ))
     }
 
     fn parse_expression_list(&mut self, end: token::TokenType) -> Option<Vec<Box<ExpressionKind>>> {
@@ -550,12 +892,22 @@ impl Parser {
         }
 
         self.next_token();
-        list.push(self.parse_expression(LOWEST).unwrap());
+        match self.parse_expression(LOWEST) {
+            Some(e) => list.push(e),
+            None => {
+                return None;
+            }
+        }
 
         while self.peek_token_is(token::COMMA) {
             self.next_token();
             self.next_token();
-            list.push(self.parse_expression(LOWEST).unwrap());
+            match self.parse_expression(LOWEST) {
+                Some(e) => list.push(e),
+                None => {
+                    return None;
+                }
+            }
         }
 
         if !self.expect_peek(end) {
@@ -565,22 +917,273 @@ impl Parser {
         Some(list)
     }
 
-    fn parse_index_expression(&mut self, left: Option<Box<ExpressionKind>>) -> Option<Box<ExpressionKind>> {
+    fn parse_hash_literal(&mut self) -> Option<Box<ExpressionKind>> {
+        let cur_token = self.cur_token.clone();
+        let mut pairs = Vec::new();
+        while !self.peek_token_is(token::RBRACE) {
+            self.next_token();
+            let key = self.parse_expression(LOWEST);
+            if !self.expect_peek(token::COLON) {
+                return None;
+            }
+            self.next_token();
+            let value = self.parse_expression(LOWEST);
+            match (key, value) {
+                (Some(k), Some(v)) => {
+                    pairs.push((k, v));
+                },
+                _ => {
+                    return None;
+                }
+            }
+            if !self.peek_token_is(token::RBRACE) && !self.expect_peek(token::COMMA) {
+                return None;
+            }
+        }
+        if !self.expect_peek(token::RBRACE) {
+            return None;
+        }
+        Some(Box::new(ExpressionKind::HashLiteral { token: cur_token, pairs }))
+    }
+
+    fn parse_match_expression(&mut self) -> Option<Box<ExpressionKind>> {
         let cur_token = self.cur_token.clone();
         self.next_token();
-        let index = self.parse_expression(LOWEST);
+        let subject = match self.parse_expression(LOWEST) {
+            Some(s) => s,
+            None => {
+                return None;
+            }
+        };
+        if !self.expect_peek(token::LBRACE) {
+            return None;
+        }
+        let mut arms = Vec::new();
+        while !self.peek_token_is(token::RBRACE) && !self.peek_token_is(token::EOF) {
+            self.next_token();
+            let parsed = match self.parse_match_arm() {
+                Some(arm) => {
+                    arms.push(arm);
+                    self.peek_token_is(token::RBRACE) || self.expect_peek(token::COMMA)
+                },
+                None => false
+            };
+            if !parsed {
+                // The error is reported; carry on with the next arm.
+                self.skip_match_arm();
+                if self.cur_token_is(token::EOF) {
+                    return None;
+                }
+                if self.cur_token_is(token::RBRACE) {
+                    return Some(Box::new(ExpressionKind::MatchExpression { token: cur_token, subject, arms }));
+                }
+            }
+        }
+        if !self.expect_peek(token::RBRACE) {
+            return None;
+        }
+        Some(Box::new(ExpressionKind::MatchExpression { token: cur_token, subject, arms }))
+    }
 
-        if !self.expect_peek(token::RBRACKET) {
+    /// Skips the rest of a broken match arm, stopping on the `,` after it or
+    /// on the `}` closing the match. Brackets inside the arm are skipped whole.
+    fn skip_match_arm(&mut self) {
+        let mut depth = 0;
+        loop {
+            if self.cur_token_is(token::EOF) {
+                return;
+            }
+            if depth == 0 && (self.cur_token_is(token::COMMA) || self.cur_token_is(token::RBRACE)) {
+                return;
+            }
+            if self.cur_token_is(token::LBRACE) || self.cur_token_is(token::LPAREN) || self.cur_token_is(token::LBRACKET) || self.cur_token_is(token::OPTIONAL_LBRACKET) {
+                depth += 1;
+            } else if depth > 0 && (self.cur_token_is(token::RBRACE) || self.cur_token_is(token::RPAREN) || self.cur_token_is(token::RBRACKET)) {
+                depth -= 1;
+            }
+            self.next_token();
+        }
+    }
+
+    fn parse_match_arm(&mut self) -> Option<MatchArm> {
+        let cur_token = self.cur_token.clone();
+        let pattern = match self.parse_pattern() {
+            Some(p) => p,
+            None => {
+                return None;
+            }
+        };
+        let mut guard = None;
+        if self.peek_token_is(token::IF) {
+            self.next_token();
+            self.next_token();
+            guard = Some(self.parse_expression(LOWEST)?);
+        }
+        if !self.expect_peek(token::FAT_ARROW) {
             return None;
         }
-        match left {
-            Some(l) => {
-                return Some(Box::new(ExpressionKind::IndexExpression { token: cur_token, left: l, index: index }));
-            },
+        self.next_token();
+        self.parse_expression(LOWEST).map(|body| MatchArm { token: cur_token, pattern, guard, body })
+    }
+
+    /// Parses `a | b | ...`, leaving the parser on the last token of the pattern.
+    fn parse_pattern(&mut self) -> Option<PatternKind> {
+        let cur_token = self.cur_token.clone();
+        let first = match self.parse_single_pattern() {
+            Some(p) => p,
             None => {
                 return None;
             }
+        };
+        if !self.peek_token_is(token::PIPE) {
+            return Some(first);
+        }
+        let mut alternatives = vec![first];
+        while self.peek_token_is(token::PIPE) {
+            self.next_token();
+            self.next_token();
+            match self.parse_single_pattern() {
+                Some(p) => {
+                    alternatives.push(p);
+                },
+                None => {
+                    return None;
+                }
+            }
+        }
+        Some(PatternKind::OrPattern { token: cur_token, alternatives })
+    }
+
+    fn parse_single_pattern(&mut self) -> Option<PatternKind> {
+        let cur_token = self.cur_token.clone();
+        match cur_token.t_type {
+            token::UNDERSCORE => {
+                Some(PatternKind::WildcardPattern { token: cur_token })
+            },
+            token::IDENT if self.peek_token_is(token::SLOT) => {
+                self.next_token();
+                if !self.expect_peek(token::IDENT) {
+                    return None;
+                }
+                let variant = self.cur_token.literal.clone();
+                let fields = if self.peek_token_is(token::LPAREN) {
+                    self.next_token();
+                    let mut fields = Vec::new();
+                    while !self.peek_token_is(token::RPAREN) {
+                        self.next_token();
+                        match self.parse_pattern() {
+                            Some(p) => {
+                                fields.push(p);
+                            },
+                            None => {
+                                return None;
+                            }
+                        }
+                        if !self.peek_token_is(token::RPAREN) && !self.expect_peek(token::COMMA) {
+                            return None;
+                        }
+                    }
+                    if !self.expect_peek(token::RPAREN) {
+                        return None;
+                    }
+                    Some(fields)
+                } else {
+                    None
+                };
+                let enum_name = cur_token.literal.clone();
+                Some(PatternKind::VariantPattern { token: cur_token, enum_name, variant, fields })
+            },
+            token::IDENT => {
+                let name = cur_token.literal.clone();
+                Some(PatternKind::BindingPattern { token: cur_token, name })
+            },
+            token::INT | token::STRING | token::TRUE | token::FALSE | token::NULL => {
+                self.prefix_parse_call(cur_token.clone()).map(|value| PatternKind::LiteralPattern { token: cur_token, value })
+            },
+            token::LBRACKET => {
+                let mut elements = Vec::new();
+                while !self.peek_token_is(token::RBRACKET) {
+                    self.next_token();
+                    match self.parse_pattern() {
+                        Some(p) => {
+                            elements.push(p);
+                        },
+                        None => {
+                            return None;
+                        }
+                    }
+                    if !self.peek_token_is(token::RBRACKET) && !self.expect_peek(token::COMMA) {
+                        return None;
+                    }
+                }
+                if !self.expect_peek(token::RBRACKET) {
+                    return None;
+                }
+                Some(PatternKind::ArrayPattern { token: cur_token, elements })
+            },
+            token::LBRACE => {
+                let mut pairs = Vec::new();
+                while !self.peek_token_is(token::RBRACE) {
+                    self.next_token();
+                    let key_token = self.cur_token.clone();
+                    let key = match self.parse_single_pattern() {
+                        Some(PatternKind::LiteralPattern { value, .. }) => *value,
+                        Some(_) => {
+                            self.error_at(&key_token, String::from("literal hash pattern key"));
+                            return None;
+                        },
+                        None => {
+                            return None;
+                        }
+                    };
+                    if !self.expect_peek(token::COLON) {
+                        return None;
+                    }
+                    self.next_token();
+                    match self.parse_pattern() {
+                        Some(p) => {
+                            pairs.push((key, p));
+                        },
+                        None => {
+                            return None;
+                        }
+                    }
+                    if !self.peek_token_is(token::RBRACE) && !self.expect_peek(token::COMMA) {
+                        return None;
+                    }
+                }
+                if !self.expect_peek(token::RBRACE) {
+                    return None;
+                }
+                Some(PatternKind::HashPattern { token: cur_token, pairs })
+            },
+            _ => {
+                self.error_at(&cur_token, String::from("pattern"));
+                None
+            }
+        }
+    }
+
+    fn parse_slot_expression(&mut self, left: Option<Box<ExpressionKind>>) -> Option<Box<ExpressionKind>> {
+        let cur_token = self.cur_token.clone();
+        let optional = cur_token.t_type == token::OPTIONAL_SLOT;
+        if !self.expect_peek(token::IDENT) {
+            return None;
         }
+        let name = self.cur_token.literal.clone();
+        left.map(|l| Box::new(ExpressionKind::SlotExpression { token: cur_token, left: l, name, optional }))
+    }
+
+    fn parse_index_expression(&mut self, left: Option<Box<ExpressionKind>>) -> Option<Box<ExpressionKind>> {
+        let cur_token = self.cur_token.clone();
+        self.next_token();
+        let index = self.parse_expression(LOWEST);
+
+        if index.is_none() || !self.expect_peek(token::RBRACKET) {
+            return None;
+        }
+        let optional = cur_token.t_type == token::OPTIONAL_LBRACKET;
+        left.map(|l| Box::new(ExpressionKind::IndexExpression { token: cur_token, left: l, index, optional }))
     }
 
 }
@@ -853,4 +1456,177 @@ mod tests {
     }
 
 }
-*/
\ No newline at end of file
+*/
+
+#[cfg(test)]
+mod parser_tests {
+    use super::*;
+
+    fn parse_string(input: &str) -> String {
+        let lexer = lexer::Lexer::new(String::from(input));
+        let mut p = Parser::new(lexer);
+        let program = p.parse_program();
+        assert_eq!(p.errors.len(), 0);
+        program.string()
+    }
+
+    fn parse_errors(input: &str) -> Vec<String> {
+        let lexer = lexer::Lexer::new(String::from(input));
+        let mut p = Parser::new(lexer);
+        p.parse_program();
+        p.errors.iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn test_malformed_array_does_not_panic() {
+        assert_eq!(parse_errors("let a := [1, , 2];"), vec!["1:14: expected expression, found ','"]);
+    }
+
+    #[test]
+    fn test_reports_every_error() {
+        let input = "let x := ;
+let y := 5;
+let add := fun(a, 1) { a };
+let z := (1 + 2;
+if (y) { let q := ]; y } else { y };
+let w := 4";
+        assert_eq!(parse_errors(input), vec![
+            "1:10: expected expression, found ';'",
+            "3:19: expected 'IDENT', found '1'",
+            "4:16: expected ')', found ';'",
+            "5:19: expected expression, found ']'",
+        ]);
+    }
+
+    #[test]
+    fn test_recovers_statements_after_errors() {
+        let lexer = lexer::Lexer::new(String::from("let x := ; let y := 5; y"));
+        let mut p = Parser::new(lexer);
+        let program = p.parse_program();
+        assert_eq!(p.errors.len(), 1);
+        assert_eq!(program.string(), "let y = 5y");
+    }
+
+    #[test]
+    fn test_one_error_per_broken_match_arm() {
+        let input = "let r := match x { 1 => , 2 => 3 };
+let y := 5;
+let s := match x { [a => 1, _ => f(1, 2) };
+let t := match x { 1 => 2 3, _ => [4, 5] };
+let z := 6;";
+        assert_eq!(parse_errors(input), vec![
+            "1:25: expected expression, found ','",
+            "3:23: expected ',', found '=>'",
+            "4:27: expected ',', found '3'",
+        ]);
+    }
+
+    #[test]
+    fn test_unclosed_block() {
+        let errors = parse_errors("let f := fun(a) { a + 1");
+        assert_eq!(errors, vec!["1:24: expected '}', found end of input"]);
+    }
+
+    #[test]
+    fn test_error_expected_and_found() {
+        let lexer = lexer::Lexer::new(String::from("let 5 := 1;"));
+        let mut p = Parser::new(lexer);
+        p.parse_program();
+        assert_eq!(p.errors[0], ParseError {
+            span: token::Span { line: 1, column: 5 },
+            expected: String::from("'IDENT'"),
+            found: String::from("'5'"),
+        });
+    }
+
+    #[test]
+    fn test_operator_table_precedence() {
+        let tests = vec![
+            ("-a * b", "((-a) * b)"),
+            ("!-a", "(!(-a))"),
+            ("a + b * c + d / e - f", "(((a + (b * c)) + (d / e)) - f)"),
+            ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4))"),
+            ("a * [1, 2, 3, 4][b * c] * d", "((a * ([1, 2, 3, 4][(b * c)])) * d)"),
+            ("add(a + b + c * d / f + g % 5)", "add((((a + b) + ((c * d) / f)) + (g % 5)))"),
+            ("a ?? b ?? c", "(a ?? (b ?? c))"),
+            ("p.x * f(q).y", "((p.x) * (f(q).y))"),
+        ];
+        for test in tests {
+            assert_eq!(parse_string(test.0), test.1);
+        }
+    }
+
+    #[test]
+    fn test_pipeline_precedence() {
+        let tests = vec![
+            ("xs |> map(f) |> filter(g)", "((xs |> map(f)) |> filter(g))"),
+            ("a + b |> f()", "((a + b) |> f())"),
+            ("a ?? b |> f()", "((a ?? b) |> f())"),
+            ("a?.b?[0] ?? c", "(((a?.b)?[0]) ?? c)"),
+        ];
+        for test in tests {
+            assert_eq!(parse_string(test.0), test.1);
+        }
+    }
+
+    #[test]
+    fn test_type_annotations() {
+        let lexer = lexer::Lexer::new(String::from("let n: int := 1; fun(a: string, b, c: fun) -> null { a }"));
+        let mut p = Parser::new(lexer);
+        let program = p.parse_program();
+        assert!(p.errors.is_empty());
+        let statements = match program {
+            NodeKind::ProgramNode{statements} => statements,
+            _ => panic!("expected a program")
+        };
+        match statements[0] {
+            StatementKind::LetStatement{annotation: Some(ref a), ..} => assert_eq!(a.name, "int"),
+            _ => panic!("expected an annotated let")
+        }
+        match statements[1] {
+            StatementKind::ExpressionStatement{expression: Some(ref e), ..} => match **e {
+                ExpressionKind::FunctionLiteral{ref annotations, return_type: Some(ref r), ..} => {
+                    let names: Vec<Option<String>> = annotations.iter().map(|a| a.as_ref().map(|a| a.name.clone())).collect();
+                    assert_eq!(names, vec![Some(String::from("string")), None, Some(String::from("fun"))]);
+                    assert_eq!(r.name, "null");
+                },
+                _ => panic!("expected an annotated function")
+            },
+            _ => panic!("expected an expression statement")
+        }
+        assert_eq!(parse_errors("let x: := 1;"), vec!["1:8: expected a type name, found ':='"]);
+        assert_eq!(parse_errors("fun(a) -> { a }"), vec!["1:11: expected a type name, found '{'"]);
+    }
+
+    #[test]
+    fn test_slot_assignment() {
+        let lexer = lexer::Lexer::new(String::from("p.a.b = 1 + 2; delete p.a"));
+        let mut p = Parser::new(lexer);
+        let program = p.parse_program();
+        assert!(p.errors.is_empty());
+        let statements: Vec<String> = match program {
+            NodeKind::ProgramNode{statements} => statements.into_iter().map(|s| s.string()).collect(),
+            _ => panic!("expected a program")
+        };
+        assert_eq!(statements, vec!["((p.a).b) = (1 + 2)", "delete (p.a)"]);
+        assert_eq!(parse_errors("a[0] = 1;"), vec!["1:6: expected a slot before '=', found '='"]);
+        assert_eq!(parse_errors("p?.a = 1;"), vec!["1:6: expected a slot before '=', found '='"]);
+        assert_eq!(parse_errors("delete p;"), vec!["1:8: expected a slot to delete, found 'p'"]);
+    }
+
+    #[test]
+    fn test_import_and_export() {
+        let lexer = lexer::Lexer::new(String::from("import \"lib/math.ld\"; import \"../my-util\" as util; export const pi := 3;"));
+        let mut p = Parser::new(lexer);
+        let program = p.parse_program();
+        assert!(p.errors.is_empty());
+        let statements: Vec<String> = match program {
+            NodeKind::ProgramNode{statements} => statements.into_iter().map(|s| s.string()).collect(),
+            _ => panic!("expected a program")
+        };
+        assert_eq!(statements, vec!["import \"lib/math.ld\"", "import \"../my-util\" as util", "export const pi = 3"]);
+        assert_eq!(parse_errors("import \"my-util\";"), vec!["1:8: expected 'as' and a name for this module, found \"my-util\""]);
+        assert_eq!(parse_errors("import util;"), vec!["1:8: expected 'STRING', found 'util'"]);
+        assert_eq!(parse_errors("export 1;"), vec!["1:8: expected 'let', 'const', 'struct' or 'enum' after 'export', found '1'"]);
+    }
+}
@@ -0,0 +1,768 @@
+use super::ast::NodeKind;
+use super::ast::ExpressionKind;
+use super::ast::StatementKind;
+use super::ast::PatternKind;
+use super::ast::MatchArm;
+use super::operator::Associativity;
+use super::parser;
+use super::visit;
+use super::visit::Visitor;
+
+use super::token::Comment;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+const INDENT: &str = "    ";
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// Prints an AST back to Lodestone source in the canonical `lodestone fmt`
+/// style. Unlike `NodeKind::string`, the output parses back to the same tree.
+pub struct Printer {
+    width: usize,
+    comments: RefCell<VecDeque<Comment>>,
+}
+
+impl Printer {
+    pub fn new(width: usize) -> Printer {
+        Printer { width, comments: RefCell::new(VecDeque::new()) }
+    }
+
+    /// Re-emits `comments` (as collected by the lexer) next to the statements
+    /// they were written beside. A comment goes before the first statement that
+    /// starts below it, or after the statement it trails, so comments at the
+    /// end of a block move down to the statement following the block. Inside
+    /// list, hash and match expressions, comments stay with the element or arm
+    /// they were written beside, and the expression is laid out one element
+    /// per line.
+    pub fn with_comments(self, comments: Vec<Comment>) -> Printer {
+        Printer { width: self.width, comments: RefCell::new(comments.into_iter().collect()) }
+    }
+
+    /// Prints a single expression at the top level.
+    pub fn print_expression(&self, expression: &ExpressionKind) -> String {
+        self.expression(expression, 0)
+    }
+
+    pub fn print_program(&self, program: &NodeKind) -> String {
+        let mut out = String::new();
+        match program {
+            NodeKind::ProgramNode{statements} => {
+                for s in statements {
+                    out.push_str(&self.commented_statement(s, 0));
+                }
+                out.push_str(&self.comments_before(None, 0));
+            },
+            NodeKind::StatementNode{statementKind} => {
+                out.push_str(&self.statement(statementKind, 0));
+                out.push('\n');
+            },
+            NodeKind::ExpressionNode{expressionKind} => {
+                out.push_str(&self.expression(expressionKind, 0));
+            }
+        }
+        out
+    }
+
+    fn statement(&self, statement: &StatementKind, depth: usize) -> String {
+        match statement {
+            StatementKind::LetStatement{name, annotation, value, constant, ..} => {
+                let mut out = String::from(if *constant { "const " } else { "let " });
+                out.push_str(&self.expression(name, depth));
+                if let Some(a) = annotation {
+                    out.push_str(": ");
+                    out.push_str(&a.name);
+                }
+                out.push_str(" := ");
+                match value {
+                    Some(v) => {
+                        out.push_str(&self.expression(v, depth));
+                    },
+                    None => {
+                        out.push_str("null");
+                    }
+                }
+                out.push(';');
+                out
+            },
+            StatementKind::ReturnStatement{return_value, ..} => {
+                let value = match return_value {
+                    Some(ref rv) => {
+                        match **rv {
+                            StatementKind::ExpressionStatement{expression: Some(ref e), ..} => {
+                                self.expression(e, depth)
+                            },
+                            _ => {
+                                String::from("null")
+                            }
+                        }
+                    },
+                    None => {
+                        String::from("null")
+                    }
+                };
+                format!("return {};", value)
+            },
+            StatementKind::ExpressionStatement{expression, ..} => {
+                match expression {
+                    Some(e) => {
+                        format!("{};", self.expression(e, depth))
+                    },
+                    None => {
+                        String::new()
+                    }
+                }
+            },
+            StatementKind::BlockStatement{..} => {
+                self.block(statement, depth)
+            },
+            StatementKind::StructStatement{name, fields, ..} => {
+                if fields.is_empty() {
+                    format!("struct {} {{}}", self.expression(name, depth))
+                } else {
+                    format!("struct {} {{ {} }}", self.expression(name, depth), fields.join(", "))
+                }
+            },
+            StatementKind::EnumStatement{name, variants, ..} => {
+                let variants: Vec<String> = variants.iter().map(|v| v.to_string()).collect();
+                if variants.is_empty() {
+                    format!("enum {} {{}}", self.expression(name, depth))
+                } else {
+                    format!("enum {} {{ {} }}", self.expression(name, depth), variants.join(", "))
+                }
+            },
+            StatementKind::AssignStatement{target, value, ..} => {
+                format!("{} = {};", self.expression(target, depth), self.expression(value, depth))
+            },
+            StatementKind::DeleteStatement{target, ..} => {
+                format!("delete {};", self.expression(target, depth))
+            },
+            StatementKind::ImportStatement{path, name, alias, ..} => {
+                if *alias {
+                    format!("import \"{}\" as {};", path, self.expression(name, depth))
+                } else {
+                    format!("import \"{}\";", path)
+                }
+            },
+            StatementKind::ExportStatement{statement, ..} => {
+                format!("export {}", self.statement(statement, depth))
+            }
+        }
+    }
+
+    /// An indented statement line with the comments that belong around it.
+    fn commented_statement(&self, statement: &StatementKind, depth: usize) -> String {
+        let line = statement_line(statement);
+        let mut out = self.comments_before(Some(line), depth);
+        out.push_str(&indent(depth));
+        out.push_str(&self.statement(statement, depth));
+        let trailing = {
+            let mut comments = self.comments.borrow_mut();
+            match comments.front() {
+                Some(c) if c.trailing && c.span.line == line => comments.pop_front(),
+                _ => None
+            }
+        };
+        if let Some(c) = trailing {
+            out.push_str(" //");
+            out.push_str(c.text.trim_end());
+        }
+        out.push('\n');
+        out
+    }
+
+    /// Pending comments that start before `line` (all of them for `None`), one per line.
+    fn comments_before(&self, line: Option<u32>, depth: usize) -> String {
+        let comments = self.take_comments(|c| line.is_none_or(|l| c.span.line < l));
+        comments.iter().map(|c| comment_line(c, depth)).collect()
+    }
+
+    /// Removes the pending comments, in order, for as long as `wanted` holds.
+    fn take_comments<F: Fn(&Comment) -> bool>(&self, wanted: F) -> Vec<Comment> {
+        let mut taken = vec![];
+        let mut comments = self.comments.borrow_mut();
+        while comments.front().is_some_and(&wanted) {
+            taken.extend(comments.pop_front());
+        }
+        taken
+    }
+
+    /// Prints each of `elements` of the expression on `line`, together with the
+    /// comments written before it or after it on its lines. A comment on the
+    /// last element's line only counts as after it when the expression started
+    /// on an earlier line, since otherwise it trails the whole statement.
+    fn items<T, L, P>(&self, elements: &[T], line: u32, lines: L, print: P) -> Vec<Item>
+        where L: Fn(&T) -> (u32, u32), P: Fn(&T) -> String {
+        let mut items = vec![];
+        for (i, element) in elements.iter().enumerate() {
+            let (first, last) = lines(element);
+            let before = self.take_comments(|c| c.span.line < first);
+            let text = print(element);
+            let next = elements.get(i + 1).map(|e| lines(e).0);
+            let after = self.take_comments(|c| c.span.line <= last && match next {
+                Some(next) => c.span.line < next,
+                None => c.span.line > line
+            });
+            items.push(Item { before, text, after });
+        }
+        items
+    }
+
+    fn block(&self, block: &StatementKind, depth: usize) -> String {
+        match block {
+            StatementKind::BlockStatement{statements, ..} => {
+                if statements.is_empty() {
+                    return String::from("{}");
+                }
+                let mut out = String::from("{\n");
+                for s in statements {
+                    out.push_str(&self.commented_statement(s, depth + 1));
+                }
+                out.push_str(&indent(depth));
+                out.push('}');
+                out
+            },
+            _ => {
+                self.statement(block, depth)
+            }
+        }
+    }
+
+    fn expression(&self, expression: &ExpressionKind, depth: usize) -> String {
+        match expression {
+            ExpressionKind::Identifier{value, ..} => {
+                value.clone()
+            },
+            ExpressionKind::IntegerLiteral{value, ..} => {
+                value.to_string()
+            },
+            ExpressionKind::StringLiteral{value, ..} => {
+                format!("\"{}\"", value)
+            },
+            ExpressionKind::BooleanExpression{value, ..} => {
+                value.to_string()
+            },
+            ExpressionKind::NullLiteral{..} => {
+                String::from("null")
+            },
+            ExpressionKind::PrefixExpression{operator, right, ..} => {
+                let mut out = operator.clone();
+                if let Some(r) = right {
+                    out.push_str(&self.operand(r, parser::PREFIX, false, depth));
+                }
+                out
+            },
+            ExpressionKind::InfixExpression{left, operator, right, ..} => {
+                let (precedence, associativity) = parser::infix_binding(operator).unwrap_or((parser::LOWEST, Associativity::Left));
+                let mut out = String::new();
+                if let Some(l) = left {
+                    out.push_str(&self.operand(l, precedence, associativity == Associativity::Right, depth));
+                }
+                out.push(' ');
+                out.push_str(operator);
+                out.push(' ');
+                if let Some(r) = right {
+                    out.push_str(&self.operand(r, precedence, associativity == Associativity::Left, depth));
+                }
+                out
+            },
+            ExpressionKind::IfExpression{condition, consequence, alternative, ..} => {
+                let mut out = String::from("if (");
+                if let Some(c) = condition {
+                    out.push_str(&self.expression(c, depth));
+                }
+                out.push_str(") ");
+                match consequence {
+                    Some(c) => {
+                        out.push_str(&self.block(c, depth));
+                    },
+                    None => {
+                        out.push_str("{}");
+                    }
+                }
+                if let Some(a) = alternative {
+                    out.push_str(" else ");
+                    out.push_str(&self.block(a, depth));
+                }
+                out
+            },
+            ExpressionKind::FunctionLiteral{parameters, annotations, return_type, body, ..} => {
+                let params: Vec<String> = parameters.iter().enumerate().map(|(i, p)| {
+                    match annotations.get(i) {
+                        Some(Some(a)) => format!("{}: {}", self.expression(p, depth), a.name),
+                        _ => self.expression(p, depth)
+                    }
+                }).collect();
+                match return_type {
+                    Some(r) => format!("fun({}) -> {} {}", params.join(", "), r.name, self.block(body, depth)),
+                    None => format!("fun({}) {}", params.join(", "), self.block(body, depth))
+                }
+            },
+            ExpressionKind::WhileLiteral{condition, consequence, ..} => {
+                format!("while ({}) {}", self.expression(condition, depth), self.block(consequence, depth))
+            },
+            ExpressionKind::CallExpression{token, function, arguments, ..} => {
+                let mut out = self.operand(function, parser::CALL, false, depth);
+                out.push_str(&self.list("(", arguments, ")", token.span.line, depth));
+                out
+            },
+            ExpressionKind::ArrayLiteral{token, elements, ..} => {
+                self.list("[", elements, "]", token.span.line, depth)
+            },
+            ExpressionKind::IndexExpression{left, index, optional, ..} => {
+                let mut out = self.operand(left, parser::CALL, false, depth);
+                out.push_str(if *optional { "?[" } else { "[" });
+                if let Some(i) = index {
+                    out.push_str(&self.expression(i, depth));
+                }
+                out.push(']');
+                out
+            },
+            ExpressionKind::SlotExpression{left, name, optional, ..} => {
+                let mut out = self.operand(left, parser::CALL, false, depth);
+                out.push_str(if *optional { "?." } else { "." });
+                out.push_str(name);
+                out
+            },
+            ExpressionKind::HashLiteral{token, pairs} => {
+                let items = self.items(pairs, token.span.line, |(k, v)| (lines(k).0, lines(v).1), |(k, v)| {
+                    format!("{}: {}", self.expression(k, depth + 1), self.expression(v, depth + 1))
+                });
+                self.wrap("{", items, "}", depth)
+            },
+            ExpressionKind::MatchExpression{token, subject, arms} => {
+                let mut out = String::from("match ");
+                out.push_str(&self.expression(subject, depth));
+                if arms.is_empty() {
+                    out.push_str(" {}");
+                    return out;
+                }
+                let arms = self.items(arms, token.span.line, arm_lines, |a| self.arm(a, depth + 1));
+                out.push(' ');
+                out.push_str(&lines_of_items("{", &arms, "}", depth));
+                out
+            }
+        }
+    }
+
+    /// Prints `expression` as an operand of an operator binding at
+    /// `precedence`, adding parentheses when the parser would otherwise
+    /// group it differently. `tie_needs_parens` is set on the side that an
+    /// operator of equal precedence must not be re-associated to.
+    fn operand(&self, expression: &ExpressionKind, precedence: u8, tie_needs_parens: bool, depth: usize) -> String {
+        let inner = binding_precedence(expression);
+        let printed = self.expression(expression, depth);
+        if inner < precedence || (inner == precedence && tie_needs_parens) {
+            format!("({})", printed)
+        } else {
+            printed
+        }
+    }
+
+    fn arm(&self, arm: &MatchArm, depth: usize) -> String {
+        let mut out = pattern(&arm.pattern);
+        if let Some(ref g) = arm.guard {
+            out.push_str(" if ");
+            out.push_str(&self.expression(g, depth));
+        }
+        out.push_str(" => ");
+        out.push_str(&self.expression(&arm.body, depth));
+        out
+    }
+
+    fn list(&self, open: &str, elements: &[Box<ExpressionKind>], close: &str, line: u32, depth: usize) -> String {
+        let items = self.items(elements, line, |e| lines(e), |e| self.expression(e, depth + 1));
+        self.wrap(open, items, close, depth)
+    }
+
+    /// Joins `items` on one line when that fits in the configured width and
+    /// no comments need a line of their own, otherwise puts each item on its
+    /// own indented line.
+    fn wrap(&self, open: &str, items: Vec<Item>, close: &str, depth: usize) -> String {
+        let texts: Vec<&str> = items.iter().map(|i| i.text.as_str()).collect();
+        let flat = format!("{}{}{}", open, texts.join(", "), close);
+        let fits = depth * INDENT.len() + flat.len() <= self.width && !flat.contains('\n');
+        let commented = items.iter().any(|i| !i.before.is_empty() || !i.after.is_empty());
+        if items.is_empty() || (fits && !commented) {
+            return flat;
+        }
+        lines_of_items(open, &items, close, depth)
+    }
+}
+
+/// An element of a list, hash or match, with the comments that go around it.
+struct Item {
+    before: Vec<Comment>,
+    text: String,
+    after: Vec<Comment>,
+}
+
+/// Puts each item on its own indented line between `open` and `close`. The
+/// first comment after an item stays on its line when it trailed it.
+fn lines_of_items(open: &str, items: &[Item], close: &str, depth: usize) -> String {
+    let mut out = String::from(open);
+    out.push('\n');
+    for (i, item) in items.iter().enumerate() {
+        for c in &item.before {
+            out.push_str(&comment_line(c, depth + 1));
+        }
+        out.push_str(&indent(depth + 1));
+        out.push_str(&item.text);
+        if i + 1 < items.len() {
+            out.push(',');
+        }
+        let mut after = item.after.iter().peekable();
+        if let Some(c) = after.next_if(|c| c.trailing) {
+            out.push_str(" //");
+            out.push_str(c.text.trim_end());
+        }
+        out.push('\n');
+        for c in after {
+            out.push_str(&comment_line(c, depth + 1));
+        }
+    }
+    out.push_str(&indent(depth));
+    out.push_str(close);
+    out
+}
+
+fn comment_line(comment: &Comment, depth: usize) -> String {
+    format!("{}//{}\n", indent(depth), comment.text.trim_end())
+}
+
+/// The first and last lines that tokens of the visited nodes are on.
+struct Lines {
+    first: u32,
+    last: u32,
+}
+
+impl Lines {
+    fn new() -> Lines {
+        Lines { first: u32::MAX, last: 0 }
+    }
+
+    fn add(&mut self, line: u32) {
+        self.first = self.first.min(line);
+        self.last = self.last.max(line);
+    }
+}
+
+impl Visitor for Lines {
+    fn visit_statement(&mut self, statement: &StatementKind) {
+        self.add(statement_line(statement));
+        visit::walk_statement(self, statement);
+    }
+
+    fn visit_expression(&mut self, expression: &ExpressionKind) {
+        self.add(expression_line(expression));
+        visit::walk_expression(self, expression);
+    }
+
+    fn visit_match_arm(&mut self, arm: &MatchArm) {
+        self.add(arm.token.span.line);
+        visit::walk_match_arm(self, arm);
+    }
+
+    fn visit_pattern(&mut self, pattern: &PatternKind) {
+        self.add(pattern_line(pattern));
+        visit::walk_pattern(self, pattern);
+    }
+}
+
+fn lines(expression: &ExpressionKind) -> (u32, u32) {
+    let mut lines = Lines::new();
+    lines.visit_expression(expression);
+    (lines.first, lines.last)
+}
+
+fn arm_lines(arm: &MatchArm) -> (u32, u32) {
+    let mut lines = Lines::new();
+    lines.visit_match_arm(arm);
+    (lines.first, lines.last)
+}
+
+fn statement_line(statement: &StatementKind) -> u32 {
+    match statement {
+        StatementKind::LetStatement{token, ..} |
+        StatementKind::ReturnStatement{token, ..} |
+        StatementKind::ExpressionStatement{token, ..} |
+        StatementKind::BlockStatement{token, ..} |
+        StatementKind::StructStatement{token, ..} |
+        StatementKind::EnumStatement{token, ..} |
+        StatementKind::AssignStatement{token, ..} |
+        StatementKind::DeleteStatement{token, ..} |
+        StatementKind::ImportStatement{token, ..} |
+        StatementKind::ExportStatement{token, ..} => token.span.line
+    }
+}
+
+fn expression_line(expression: &ExpressionKind) -> u32 {
+    match expression {
+        ExpressionKind::Identifier{token, ..} |
+        ExpressionKind::PrefixExpression{token, ..} |
+        ExpressionKind::InfixExpression{token, ..} |
+        ExpressionKind::BooleanExpression{token, ..} |
+        ExpressionKind::IfExpression{token, ..} |
+        ExpressionKind::FunctionLiteral{token, ..} |
+        ExpressionKind::CallExpression{token, ..} |
+        ExpressionKind::StringLiteral{token, ..} |
+        ExpressionKind::IntegerLiteral{token, ..} |
+        ExpressionKind::WhileLiteral{token, ..} |
+        ExpressionKind::ArrayLiteral{token, ..} |
+        ExpressionKind::IndexExpression{token, ..} |
+        ExpressionKind::HashLiteral{token, ..} |
+        ExpressionKind::MatchExpression{token, ..} |
+        ExpressionKind::NullLiteral{token} |
+        ExpressionKind::SlotExpression{token, ..} => token.span.line
+    }
+}
+
+fn pattern_line(pattern: &PatternKind) -> u32 {
+    match pattern {
+        PatternKind::WildcardPattern{token} |
+        PatternKind::LiteralPattern{token, ..} |
+        PatternKind::BindingPattern{token, ..} |
+        PatternKind::ArrayPattern{token, ..} |
+        PatternKind::HashPattern{token, ..} |
+        PatternKind::OrPattern{token, ..} |
+        PatternKind::VariantPattern{token, ..} => token.span.line
+    }
+}
+
+fn indent(depth: usize) -> String {
+    INDENT.repeat(depth)
+}
+
+fn binding_precedence(expression: &ExpressionKind) -> u8 {
+    match expression {
+        ExpressionKind::InfixExpression{operator, ..} => {
+            parser::infix_binding(operator).map(|b| b.0).unwrap_or(parser::LOWEST)
+        },
+        ExpressionKind::PrefixExpression{..} => {
+            parser::PREFIX
+        },
+        ExpressionKind::CallExpression{..} => {
+            parser::CALL
+        },
+        ExpressionKind::IndexExpression{..} | ExpressionKind::SlotExpression{..} => {
+            parser::INDEX
+        },
+        _ => {
+            u8::MAX
+        }
+    }
+}
+
+fn pattern(pattern: &PatternKind) -> String {
+    let printer = Printer::new(DEFAULT_WIDTH);
+    match pattern {
+        PatternKind::WildcardPattern{..} => {
+            String::from("_")
+        },
+        PatternKind::LiteralPattern{value, ..} => {
+            printer.expression(value, 0)
+        },
+        PatternKind::BindingPattern{name, ..} => {
+            name.clone()
+        },
+        PatternKind::ArrayPattern{elements, ..} => {
+            let elements: Vec<String> = elements.iter().map(self::pattern).collect();
+            format!("[{}]", elements.join(", "))
+        },
+        PatternKind::HashPattern{pairs, ..} => {
+            let pairs: Vec<String> = pairs.iter().map(|(k, v)| {
+                format!("{}: {}", printer.expression(k, 0), self::pattern(v))
+            }).collect();
+            format!("{{{}}}", pairs.join(", "))
+        },
+        PatternKind::OrPattern{alternatives, ..} => {
+            let alternatives: Vec<String> = alternatives.iter().map(self::pattern).collect();
+            alternatives.join(" | ")
+        },
+        PatternKind::VariantPattern{enum_name, variant, fields, ..} => {
+            match fields {
+                Some(fields) => {
+                    let fields: Vec<String> = fields.iter().map(self::pattern).collect();
+                    format!("{}.{}({})", enum_name, variant, fields.join(", "))
+                },
+                None => format!("{}.{}", enum_name, variant)
+            }
+        }
+    }
+}
+
+/// Formats `source` in the canonical style, or returns its syntax errors.
+pub fn format_source(source: &str, width: usize) -> Result<String, Vec<parser::ParseError>> {
+    let lexer = super::lexer::Lexer::new(String::from(source));
+    let mut p = parser::Parser::new(lexer);
+    let program = p.parse_program();
+    if !p.errors.is_empty() {
+        return Err(p.errors);
+    }
+    let comments = p.comments().to_vec();
+    Ok(Printer::new(width).with_comments(comments).print_program(&program))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::ast_json;
+    use super::super::json::JsonValue;
+    use super::super::lexer::Lexer;
+
+    fn parse(input: &str) -> NodeKind {
+        let mut p = parser::Parser::new(Lexer::new(String::from(input)));
+        let program = p.parse_program();
+        assert_eq!(p.errors.len(), 0, "errors parsing {}", input);
+        program
+    }
+
+    /// The JSON form of `program` without spans, which formatting moves.
+    fn structure(program: &NodeKind) -> JsonValue {
+        fn strip(value: JsonValue) -> JsonValue {
+            match value {
+                JsonValue::Object(members) => {
+                    JsonValue::Object(members.into_iter().filter(|(k, _)| k != "span").map(|(k, v)| (k, strip(v))).collect())
+                },
+                JsonValue::Array(items) => JsonValue::Array(items.into_iter().map(strip).collect()),
+                other => other
+            }
+        }
+        strip(ast_json::to_json(program))
+    }
+
+    fn assert_round_trips(input: &str) {
+        let program = parse(input);
+        let printed = Printer::new(DEFAULT_WIDTH).print_program(&program);
+        let reparsed = parse(&printed);
+        assert_eq!(structure(&reparsed), structure(&program), "formatting changed the AST of {}", input);
+        assert_eq!(Printer::new(DEFAULT_WIDTH).print_program(&reparsed), printed);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let tests = vec![
+            "let x := 5;",
+            "let add := fun(a, b) { a + b; }; add(1, 2)",
+            "if (x > 1) { \"big\" } else { \"small\" }",
+            "(a + b) * c - (d - e)",
+            "-(a + b) * !c",
+            "(-a)[0]",
+            "f(1)[0](2)",
+            "let s := \"hello world\"; s",
+            "match x { 0 | 1 => \"low\", [a, b] => a, {\"k\": v} => v, n if n > 10 => n, null => 0, _ => null }",
+            "xs |> map(f) |> filter(g)",
+            "(a ?? b) ?? c",
+            "a?.b?[0]",
+            "{\"a\": [1, 2], \"b\": {\"c\": true}}",
+            "return fun() { return 1; };",
+            "let f: fun := fun(a: int, b) -> bool { a > b; };",
+            "const limit: int := 10;",
+            "struct Point { x, y }\nstruct Unit {}\nPoint(1, 2).x",
+            "p.x = 1;\np.a.b = f(2);\ndelete p.x;",
+            "import \"lib/math.ld\";\nimport \"../util\" as u;\nexport let f := fun(x) { u.twice(x); };\nexport struct P { x }",
+            "enum Shape { Circle(r), Rect(w, h), Empty }\nmatch s { Shape.Circle(r) | Shape.Rect(r, _) => r, Shape.Empty => 0 }",
+        ];
+        for test in tests {
+            assert_round_trips(test);
+        }
+    }
+
+    #[test]
+    fn test_canonical_style() {
+        let input = "let add:=fun(a,b){let c:=a+b;return c}
+if(add(1,2)>2){\"yes\"}else{\"no\"}";
+        let expected = "let add := fun(a, b) {
+    let c := a + b;
+    return c;
+};
+if (add(1, 2) > 2) {
+    \"yes\";
+} else {
+    \"no\";
+};
+";
+        assert_eq!(format_source(input, DEFAULT_WIDTH).ok().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_fixes_lossy_string_output() {
+        let printed = format_source("let f := fun(a, b) { \"x\" }", DEFAULT_WIDTH).ok().unwrap();
+        assert_eq!(printed, "let f := fun(a, b) {\n    \"x\";\n};\n");
+    }
+
+    #[test]
+    fn test_wraps_long_lists() {
+        let input = "let xs := [\"aaaaaaaaaa\", \"bbbbbbbbbb\", \"cccccccccc\", \"dddddddddd\", \"eeeeeeeeee\", \"ffffffffff\"];";
+        let expected = "let xs := [
+    \"aaaaaaaaaa\",
+    \"bbbbbbbbbb\",
+    \"cccccccccc\",
+    \"dddddddddd\",
+    \"eeeeeeeeee\",
+    \"ffffffffff\"
+];
+";
+        assert_eq!(format_source(input, DEFAULT_WIDTH).ok().unwrap(), expected);
+        assert_round_trips(input);
+    }
+
+    #[test]
+    fn test_match_layout() {
+        let expected = "match x {
+    0 => \"zero\",
+    _ => \"other\"
+};
+";
+        assert_eq!(format_source("match x { 0 => \"zero\", _ => \"other\" }", DEFAULT_WIDTH).ok().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_keeps_comments() {
+        let input = "// header\nlet x := 1;   // one   \nlet f := fun() {\n  // inside\n  x\n};\n// end\n";
+        let expected = "// header
+let x := 1; // one
+let f := fun() {
+    // inside
+    x;
+};
+// end
+";
+        let formatted = format_source(input, DEFAULT_WIDTH).ok().unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format_source(&formatted, DEFAULT_WIDTH).ok().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_keeps_comments_inside_expressions() {
+        let input = "let a := [1, // one\n 2 // two\n];\nlet b := [1, 2]; // both\nf(x, // ex\n  {\"k\": 1, // kay\n  // next\n  \"l\": 2});\nlet m := match a { [x, y] => x, // pair\n  // rest\n  _ => 1 // arm\n};\n";
+        let expected = "let a := [
+    1, // one
+    2 // two
+];
+let b := [1, 2]; // both
+f(
+    x, // ex
+    {
+        \"k\": 1, // kay
+        // next
+        \"l\": 2
+    }
+);
+let m := match a {
+    [x, y] => x, // pair
+    // rest
+    _ => 1 // arm
+};
+";
+        let formatted = format_source(input, DEFAULT_WIDTH).ok().unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(structure(&parse(&formatted)), structure(&parse(input)));
+        assert_eq!(format_source(&formatted, DEFAULT_WIDTH).ok().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_reports_syntax_errors() {
+        assert!(format_source("let x := ;", DEFAULT_WIDTH).is_err());
+    }
+}
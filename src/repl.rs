@@ -1,19 +1,22 @@
 use std::io;
-use std::collections::HashMap;
 
-use super::lexer::Lexer;
-use super::parser::Parser;
-use super::evaluator::eval;
-use super::environment::Environment;
+use super::interpreter::{Interpreter, Error};
 
+/// Reads and runs one line at a time against a single `Interpreter`, so
+/// each line sees the globals defined by the ones before it.
 pub fn repl() {
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).expect(
-        "Failed to read line",
-    );
-    let l = Lexer::new(input);
-    let mut p = Parser::new(l);
-    let mut program = p.parse_program();
-    let evaluated = eval(program, &mut Environment{store: HashMap::new()});
-    println!("You inputted: {}", evaluated);
+    let mut interpreter = Interpreter::new();
+    loop {
+        let mut input = String::new();
+        match io::stdin().read_line(&mut input) {
+            Ok(0) => return,
+            Ok(_) => {},
+            Err(e) => panic!("Failed to read line: {}", e)
+        }
+        match interpreter.eval_str(&input) {
+            Ok(evaluated) => println!("You inputted: {}", evaluated),
+            Err(Error::Runtime(message)) => println!("You inputted: {}", message),
+            Err(error) => println!("{}", error)
+        }
+    }
 }
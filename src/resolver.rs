@@ -0,0 +1,389 @@
+//! Static name resolution, run between parsing and evaluation.
+//!
+//! Every identifier is bound to the global store or to a slot in an enclosing
+//! frame (a function call or a match arm), so undefined names and duplicate
+//! parameters or pattern bindings are reported before anything runs. A name is
+//! visible from the point it is declared: closures capture the environment
+//! when they are created, so a function cannot see a `let` that comes after it.
+//! A `const` may not be declared again in its own scope, though inner frames
+//! may shadow it.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use super::ast::NodeKind;
+use super::ast::ExpressionKind;
+use super::ast::StatementKind;
+use super::ast::PatternKind;
+use super::ast::MatchArm;
+use super::ast::Binding;
+use super::fold;
+use super::fold::Folder;
+use super::builtins;
+use super::token::Span;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolveError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.message)
+    }
+}
+
+/// Resolves every identifier in `program`. `globals` are names already
+/// defined in the environment the program will run in.
+pub fn resolve(program: NodeKind, globals: &[String]) -> Result<NodeKind, Vec<ResolveError>> {
+    let mut resolver = Resolver {
+        globals: globals.iter().cloned().collect(),
+        scopes: vec![],
+        constants: vec![vec![]],
+        blocks: 0,
+        errors: vec![]
+    };
+    let resolved = resolver.fold_node(program);
+    if resolver.errors.is_empty() {
+        Ok(resolved)
+    } else {
+        Err(resolver.errors)
+    }
+}
+
+struct Resolver {
+    globals: HashSet<String>,
+    /// One entry per frame; a name's index is its slot.
+    scopes: Vec<Vec<String>>,
+    /// Constants declared in each scope, starting with the top level.
+    constants: Vec<Vec<String>>,
+    /// How many blocks enclose the statement being resolved.
+    blocks: usize,
+    errors: Vec<ResolveError>,
+}
+
+impl Resolver {
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(ResolveError { span, message });
+    }
+
+    fn push_scope(&mut self, scope: Vec<String>) {
+        self.scopes.push(scope);
+        self.constants.push(vec![]);
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+        self.constants.pop();
+    }
+
+    fn lookup(&self, name: &str) -> Option<Binding> {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(slot) = scope.iter().position(|n| n == name) {
+                return Some(Binding::Local{depth, slot});
+            }
+        }
+        if self.globals.contains(name) {
+            return Some(Binding::Global);
+        }
+        // Builtins are found by name when the program runs.
+        if builtins::lookup(name).is_some() {
+            return Some(Binding::Unresolved);
+        }
+        None
+    }
+
+    /// Declares a `let`. Declaring a name again in the same scope reuses its
+    /// slot, since re-`let` is how a variable is updated.
+    fn declare(&mut self, name: &str) -> Binding {
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                let slot = match scope.iter().position(|n| n == name) {
+                    Some(slot) => slot,
+                    None => {
+                        scope.push(String::from(name));
+                        scope.len() - 1
+                    }
+                };
+                Binding::Local{depth: 0, slot}
+            },
+            None => {
+                self.globals.insert(String::from(name));
+                Binding::Global
+            }
+        }
+    }
+
+    /// Declares the identifier a `let`, `const` or `struct` binds.
+    fn declare_name(&mut self, name: ExpressionKind, constant: bool) -> ExpressionKind {
+        match name {
+            ExpressionKind::Identifier{token, value, ..} => {
+                if self.constants.last().unwrap().contains(&value) {
+                    self.error(token.span, format!("cannot reassign constant '{}'", value));
+                } else if constant {
+                    self.constants.last_mut().unwrap().push(value.clone());
+                }
+                let binding = self.declare(&value);
+                ExpressionKind::Identifier{token, value, binding}
+            },
+            other => other
+        }
+    }
+
+    fn check_pattern(&mut self, pattern: &PatternKind, seen: &mut Vec<String>) {
+        match pattern {
+            PatternKind::BindingPattern{token, name} => {
+                if seen.contains(name) {
+                    self.error(token.span, format!("duplicate binding '{}' in pattern", name));
+                } else {
+                    seen.push(name.clone());
+                }
+            },
+            PatternKind::ArrayPattern{elements, ..} => {
+                for e in elements {
+                    self.check_pattern(e, seen);
+                }
+            },
+            PatternKind::HashPattern{pairs, ..} => {
+                for (_, p) in pairs {
+                    self.check_pattern(p, seen);
+                }
+            },
+            PatternKind::OrPattern{token, alternatives} => {
+                // Each alternative binds the same names on its own.
+                let before = seen.clone();
+                let mut first: Option<Vec<String>> = None;
+                for a in alternatives {
+                    let mut alt_seen = before.clone();
+                    self.check_pattern(a, &mut alt_seen);
+                    alt_seen.sort();
+                    match first {
+                        Some(ref names) if *names != alt_seen => {
+                            self.error(token.span, String::from("alternatives in a pattern must bind the same names"));
+                            break;
+                        },
+                        Some(_) => {},
+                        None => first = Some(alt_seen)
+                    }
+                }
+                for name in first.unwrap_or_default() {
+                    if !seen.contains(&name) {
+                        seen.push(name);
+                    }
+                }
+            },
+            PatternKind::VariantPattern{fields: Some(fields), ..} => {
+                for f in fields {
+                    self.check_pattern(f, seen);
+                }
+            },
+            PatternKind::VariantPattern{fields: None, ..} |
+            PatternKind::WildcardPattern{..} |
+            PatternKind::LiteralPattern{..} => {}
+        }
+    }
+}
+
+impl Folder for Resolver {
+    fn fold_statement(&mut self, statement: StatementKind) -> StatementKind {
+        match statement {
+            StatementKind::LetStatement{token, name, annotation, value, constant} => {
+                let value = value.map(|v| Box::new(self.fold_expression(*v)));
+                let name = self.declare_name(name, constant);
+                StatementKind::LetStatement{token, name, annotation, value, constant}
+            },
+            StatementKind::StructStatement{token, name, fields} => {
+                for (i, field) in fields.iter().enumerate() {
+                    if fields[..i].contains(field) {
+                        self.error(token.span, format!("duplicate field '{}'", field));
+                    }
+                }
+                let name = self.declare_name(name, false);
+                StatementKind::StructStatement{token, name, fields}
+            },
+            StatementKind::EnumStatement{token, name, variants} => {
+                for (i, variant) in variants.iter().enumerate() {
+                    if variants[..i].iter().any(|v| v.name == variant.name) {
+                        self.error(variant.token.span, format!("duplicate variant '{}'", variant.name));
+                    }
+                    for (j, field) in variant.fields.iter().enumerate() {
+                        if variant.fields[..j].contains(field) {
+                            self.error(variant.token.span, format!("duplicate field '{}'", field));
+                        }
+                    }
+                }
+                let name = self.declare_name(name, false);
+                StatementKind::EnumStatement{token, name, variants}
+            },
+            StatementKind::ImportStatement{token, path, name, alias} => {
+                let name = self.declare_name(name, false);
+                StatementKind::ImportStatement{token, path, name, alias}
+            },
+            StatementKind::ExportStatement{token, statement} => {
+                if self.blocks > 0 || !self.scopes.is_empty() {
+                    self.error(token.span, String::from("export is only allowed at the top level of a module"));
+                }
+                StatementKind::ExportStatement{token, statement: Box::new(self.fold_statement(*statement))}
+            },
+            StatementKind::BlockStatement{..} => {
+                self.blocks += 1;
+                let block = fold::fold_statement(self, statement);
+                self.blocks -= 1;
+                block
+            },
+            other => fold::fold_statement(self, other)
+        }
+    }
+
+    fn fold_expression(&mut self, expression: ExpressionKind) -> ExpressionKind {
+        match expression {
+            ExpressionKind::Identifier{token, value, ..} => {
+                let binding = match self.lookup(&value) {
+                    Some(b) => b,
+                    None => {
+                        self.error(token.span, format!("undefined variable '{}'", value));
+                        Binding::Unresolved
+                    }
+                };
+                ExpressionKind::Identifier{token, value, binding}
+            },
+            ExpressionKind::FunctionLiteral{token, parameters, annotations, return_type, body} => {
+                let mut scope: Vec<String> = vec![];
+                let mut resolved = vec![];
+                for param in parameters {
+                    match param {
+                        ExpressionKind::Identifier{token, value, ..} => {
+                            if scope.contains(&value) {
+                                self.error(token.span, format!("duplicate parameter '{}'", value));
+                            }
+                            scope.push(value.clone());
+                            let binding = Binding::Local{depth: 0, slot: scope.len() - 1};
+                            resolved.push(ExpressionKind::Identifier{token, value, binding});
+                        },
+                        other => resolved.push(other)
+                    }
+                }
+                // The slot after the parameters holds the receiver of a method call.
+                if !scope.iter().any(|p| p == "self") {
+                    scope.push(String::from("self"));
+                }
+                self.push_scope(scope);
+                let body = Box::new(self.fold_statement(*body));
+                self.pop_scope();
+                ExpressionKind::FunctionLiteral{token, parameters: resolved, annotations, return_type, body}
+            },
+            other => fold::fold_expression(self, other)
+        }
+    }
+
+    fn fold_match_arm(&mut self, arm: MatchArm) -> MatchArm {
+        // Literal patterns and hash-pattern keys are evaluated outside the arm's frame.
+        let pattern = self.fold_pattern(arm.pattern);
+        self.check_pattern(&pattern, &mut vec![]);
+        self.push_scope(pattern.binding_names());
+        let guard = arm.guard.map(|g| Box::new(self.fold_expression(*g)));
+        let body = Box::new(self.fold_expression(*arm.body));
+        self.pop_scope();
+        MatchArm { token: arm.token, pattern, guard, body }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::lexer::Lexer;
+    use super::super::parser::Parser;
+    use super::super::visit;
+    use super::super::visit::Visitor;
+
+    fn resolve_source(input: &str) -> Result<NodeKind, Vec<ResolveError>> {
+        let mut p = Parser::new(Lexer::new(String::from(input)));
+        let program = p.parse_program();
+        resolve(program, &[])
+    }
+
+    fn errors(input: &str) -> Vec<String> {
+        match resolve_source(input) {
+            Ok(_) => vec![],
+            Err(errors) => errors.iter().map(|e| e.to_string()).collect()
+        }
+    }
+
+    struct Bindings {
+        found: Vec<(String, Binding)>,
+    }
+
+    impl Visitor for Bindings {
+        fn visit_expression(&mut self, expression: &ExpressionKind) {
+            if let ExpressionKind::Identifier{value, binding, ..} = expression {
+                self.found.push((value.clone(), *binding));
+            }
+            visit::walk_expression(self, expression);
+        }
+    }
+
+    #[test]
+    fn test_binds_depth_and_slot() {
+        // Slot 2 of `f`'s frame is `self`.
+        let program = resolve_source("let g := 1; let f := fun(a, b) { let c := a; fun(d) { [b, c, d, g] } };").ok().unwrap();
+        let mut bindings = Bindings { found: vec![] };
+        bindings.visit_node(&program);
+        assert_eq!(bindings.found, vec![
+            (String::from("g"), Binding::Global),
+            (String::from("f"), Binding::Global),
+            (String::from("a"), Binding::Local{depth: 0, slot: 0}),
+            (String::from("b"), Binding::Local{depth: 0, slot: 1}),
+            (String::from("c"), Binding::Local{depth: 0, slot: 3}),
+            (String::from("a"), Binding::Local{depth: 0, slot: 0}),
+            (String::from("d"), Binding::Local{depth: 0, slot: 0}),
+            (String::from("b"), Binding::Local{depth: 1, slot: 1}),
+            (String::from("c"), Binding::Local{depth: 1, slot: 3}),
+            (String::from("d"), Binding::Local{depth: 0, slot: 0}),
+            (String::from("g"), Binding::Global),
+        ]);
+    }
+
+    #[test]
+    fn test_reports_undefined_variables() {
+        assert_eq!(errors("let x := y + 1; z"), vec!["1:10: undefined variable 'y'", "1:17: undefined variable 'z'"]);
+        assert_eq!(errors("let f := fun() { f() };"), vec!["1:18: undefined variable 'f'"]);
+        assert_eq!(errors("match 1 { n => n }; n"), vec!["1:21: undefined variable 'n'"]);
+        assert_eq!(errors("let x := 1; let x := x + 1; match [x] { [a] | a if a == x => a, _ => x }"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_reports_duplicates() {
+        assert_eq!(errors("fun(a, b, a) { a }"), vec!["1:11: duplicate parameter 'a'"]);
+        assert_eq!(errors("match [1, 1] { [x, x] => x }"), vec!["1:20: duplicate binding 'x' in pattern"]);
+        assert_eq!(errors("struct P { x, y, x }"), vec!["1:1: duplicate field 'x'"]);
+        assert_eq!(errors("match 1 { x | y => y, _ => 0 }"), vec!["1:11: alternatives in a pattern must bind the same names"]);
+        assert_eq!(errors("match 2 { [x] | 2 => x, _ => 0 }"), vec!["1:11: alternatives in a pattern must bind the same names"]);
+    }
+
+    #[test]
+    fn test_constants() {
+        assert_eq!(errors("const x := 1; let x := 2; const x := 3;"), vec![
+            "1:19: cannot reassign constant 'x'",
+            "1:33: cannot reassign constant 'x'",
+        ]);
+        assert_eq!(errors("const x := 1; if (true) { let x := 2 };"), vec!["1:31: cannot reassign constant 'x'"]);
+        assert_eq!(errors("let x := 1; const x := x; let f := fun(x) { const y := x; let x := y; y };"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_imports_and_exports() {
+        assert_eq!(errors("import \"lib/math\"; import \"util\" as u; math.pi + u.x"), Vec::<String>::new());
+        assert_eq!(errors("export let x := 1; if (true) { export let y := 2 }; fun() { export let z := 3; };"), vec![
+            "1:32: export is only allowed at the top level of a module",
+            "1:61: export is only allowed at the top level of a module",
+        ]);
+    }
+
+    #[test]
+    fn test_known_globals() {
+        let mut p = Parser::new(Lexer::new(String::from("answer + 1")));
+        let program = p.parse_program();
+        assert!(resolve(program, &[String::from("answer")]).is_ok());
+    }
+}
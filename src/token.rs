@@ -13,14 +13,37 @@ impl fmt::Debug for TokenType {
     }
 }
 
+/// 1-based line and column of the first character of a token.
+#[derive(Hash, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Span {
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
 #[derive(Hash, Clone)]
 pub struct Token {
     pub t_type: TokenType,
     pub literal: String,
+    pub span: Span,
+}
+
+/// A `//` comment. `text` is everything after the slashes; `trailing` is set
+/// when code precedes the comment on its line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    pub span: Span,
+    pub text: String,
+    pub trailing: bool,
 }
 
 pub fn create_start_token() -> Token {
-    Token { t_type: EOF, literal: String::from("EOF") }
+    Token { t_type: EOF, literal: String::from("EOF"), span: Span::default() }
 }
 
 pub const ILLEGAL: TokenType = TokenType { name: "ILLEGAL" };
@@ -30,13 +53,20 @@ pub const UNDERSCORE: TokenType = TokenType { name: "UNDERSCORE" };
 // Keywords
 pub const FUNCTION: TokenType = TokenType { name: "FUNCTION" };
 pub const IMPORT: TokenType = TokenType { name: "IMPORT" };
+pub const EXPORT: TokenType = TokenType { name: "EXPORT" };
 pub const LET: TokenType = TokenType { name: "LET" };
+pub const CONST: TokenType = TokenType { name: "CONST" };
+pub const STRUCT: TokenType = TokenType { name: "STRUCT" };
+pub const ENUM: TokenType = TokenType { name: "ENUM" };
+pub const DELETE: TokenType = TokenType { name: "DELETE" };
 pub const WHILE: TokenType = TokenType { name: "WHILE" };
 pub const TRUE: TokenType = TokenType { name: "TRUE" };
 pub const FALSE: TokenType = TokenType { name: "FALSE" };
 pub const IF: TokenType = TokenType { name: "IF" };
 pub const ELSE: TokenType = TokenType { name: "ELSE" };
 pub const RETURN: TokenType = TokenType { name: "RETURN" };
+pub const MATCH: TokenType = TokenType { name: "MATCH" };
+pub const NULL: TokenType = TokenType { name: "NULL" };
 // Operators
 pub const ASSIGN: TokenType = TokenType { name: ":=" };
 pub const REASSIGN: TokenType = TokenType { name: "=" };
@@ -51,6 +81,11 @@ pub const LT: TokenType = TokenType { name: "<" };
 pub const GT: TokenType = TokenType { name: ">" };
 pub const EQ: TokenType = TokenType { name: "==" };
 pub const NOT_EQ: TokenType = TokenType { name: "!=" };
+pub const FAT_ARROW: TokenType = TokenType { name: "=>" };
+pub const ARROW: TokenType = TokenType { name: "->" };
+pub const PIPE: TokenType = TokenType { name: "|" };
+pub const COALESCE: TokenType = TokenType { name: "??" };
+pub const PIPELINE: TokenType = TokenType { name: "|>" };
 // Delimiters
 pub const COMMA: TokenType = TokenType { name: "," };
 pub const SEMICOLON: TokenType = TokenType { name: ";" };
@@ -62,6 +97,8 @@ pub const LBRACKET: TokenType = TokenType { name: "[" };
 pub const RBRACKET: TokenType = TokenType { name: "]" };
 // Slots
 pub const SLOT: TokenType = TokenType { name: "." };
+pub const OPTIONAL_SLOT: TokenType = TokenType { name: "?." };
+pub const OPTIONAL_LBRACKET: TokenType = TokenType { name: "?[" };
 // Types
 pub const STRING: TokenType = TokenType { name: "STRING" };
 pub const INT: TokenType = TokenType { name: "INT" };
@@ -70,6 +107,10 @@ pub fn keywords(key: String) -> Option<TokenType> {
     let mut kw_map = HashMap::new();
     kw_map.insert(String::from("fun"), FUNCTION);
     kw_map.insert(String::from("let"), LET);
+    kw_map.insert(String::from("const"), CONST);
+    kw_map.insert(String::from("struct"), STRUCT);
+    kw_map.insert(String::from("enum"), ENUM);
+    kw_map.insert(String::from("delete"), DELETE);
     kw_map.insert(String::from("while"), WHILE);
     kw_map.insert(String::from("true"), TRUE);
     kw_map.insert(String::from("false"), FALSE);
@@ -77,6 +118,9 @@ pub fn keywords(key: String) -> Option<TokenType> {
     kw_map.insert(String::from("else"), ELSE);
     kw_map.insert(String::from("return"), RETURN);
     kw_map.insert(String::from("import"), IMPORT);
+    kw_map.insert(String::from("export"), EXPORT);
+    kw_map.insert(String::from("match"), MATCH);
+    kw_map.insert(String::from("null"), NULL);
     kw_map.get(&key).cloned()
 }
 
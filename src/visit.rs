@@ -0,0 +1,228 @@
+//! Read-only traversal of the AST.
+//!
+//! Implement `Visitor` and override the methods for the nodes a pass cares
+//! about; call the matching `walk_*` function from an override to keep
+//! descending into that node's children.
+
+use super::ast::NodeKind;
+use super::ast::ExpressionKind;
+use super::ast::StatementKind;
+use super::ast::PatternKind;
+use super::ast::MatchArm;
+
+pub trait Visitor {
+    fn visit_node(&mut self, node: &NodeKind) {
+        walk_node(self, node)
+    }
+
+    fn visit_program(&mut self, statements: &[StatementKind]) {
+        walk_program(self, statements)
+    }
+
+    fn visit_statement(&mut self, statement: &StatementKind) {
+        walk_statement(self, statement)
+    }
+
+    fn visit_expression(&mut self, expression: &ExpressionKind) {
+        walk_expression(self, expression)
+    }
+
+    fn visit_match_arm(&mut self, arm: &MatchArm) {
+        walk_match_arm(self, arm)
+    }
+
+    fn visit_pattern(&mut self, pattern: &PatternKind) {
+        walk_pattern(self, pattern)
+    }
+}
+
+pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, node: &NodeKind) {
+    match node {
+        NodeKind::ProgramNode{statements} => {
+            visitor.visit_program(statements);
+        },
+        NodeKind::StatementNode{statementKind} => {
+            visitor.visit_statement(statementKind);
+        },
+        NodeKind::ExpressionNode{expressionKind} => {
+            visitor.visit_expression(expressionKind);
+        }
+    }
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, statements: &[StatementKind]) {
+    for s in statements {
+        visitor.visit_statement(s);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &StatementKind) {
+    match statement {
+        StatementKind::LetStatement{name, value, ..} => {
+            visitor.visit_expression(name);
+            if let Some(v) = value { visitor.visit_expression(v) }
+        },
+        StatementKind::ReturnStatement{return_value, ..} => {
+            if let Some(rv) = return_value { visitor.visit_statement(rv) }
+        },
+        StatementKind::ExpressionStatement{expression, ..} => {
+            if let Some(e) = expression { visitor.visit_expression(e) }
+        },
+        StatementKind::BlockStatement{statements, ..} => {
+            for s in statements {
+                visitor.visit_statement(s);
+            }
+        },
+        StatementKind::StructStatement{name, ..} |
+        StatementKind::EnumStatement{name, ..} |
+        StatementKind::ImportStatement{name, ..} => {
+            visitor.visit_expression(name);
+        },
+        StatementKind::AssignStatement{target, value, ..} => {
+            visitor.visit_expression(target);
+            visitor.visit_expression(value);
+        },
+        StatementKind::DeleteStatement{target, ..} => {
+            visitor.visit_expression(target);
+        },
+        StatementKind::ExportStatement{statement, ..} => {
+            visitor.visit_statement(statement);
+        }
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &ExpressionKind) {
+    match expression {
+        ExpressionKind::Identifier{..} |
+        ExpressionKind::BooleanExpression{..} |
+        ExpressionKind::StringLiteral{..} |
+        ExpressionKind::IntegerLiteral{..} |
+        ExpressionKind::NullLiteral{..} => {},
+        ExpressionKind::PrefixExpression{right, ..} => {
+            if let Some(r) = right { visitor.visit_expression(r) }
+        },
+        ExpressionKind::InfixExpression{left, right, ..} => {
+            if let Some(l) = left { visitor.visit_expression(l) }
+            if let Some(r) = right { visitor.visit_expression(r) }
+        },
+        ExpressionKind::IfExpression{condition, consequence, alternative, ..} => {
+            if let Some(c) = condition { visitor.visit_expression(c) }
+            if let Some(c) = consequence { visitor.visit_statement(c) }
+            if let Some(a) = alternative { visitor.visit_statement(a) }
+        },
+        ExpressionKind::FunctionLiteral{parameters, body, ..} => {
+            for p in parameters {
+                visitor.visit_expression(p);
+            }
+            visitor.visit_statement(body);
+        },
+        ExpressionKind::CallExpression{function, arguments, ..} => {
+            visitor.visit_expression(function);
+            for a in arguments {
+                visitor.visit_expression(a);
+            }
+        },
+        ExpressionKind::WhileLiteral{condition, consequence, ..} => {
+            visitor.visit_expression(condition);
+            visitor.visit_statement(consequence);
+        },
+        ExpressionKind::ArrayLiteral{elements, ..} => {
+            for e in elements {
+                visitor.visit_expression(e);
+            }
+        },
+        ExpressionKind::IndexExpression{left, index, ..} => {
+            visitor.visit_expression(left);
+            if let Some(i) = index { visitor.visit_expression(i) }
+        },
+        ExpressionKind::HashLiteral{pairs, ..} => {
+            for (k, v) in pairs {
+                visitor.visit_expression(k);
+                visitor.visit_expression(v);
+            }
+        },
+        ExpressionKind::MatchExpression{subject, arms, ..} => {
+            visitor.visit_expression(subject);
+            for arm in arms {
+                visitor.visit_match_arm(arm);
+            }
+        },
+        ExpressionKind::SlotExpression{left, ..} => {
+            visitor.visit_expression(left);
+        }
+    }
+}
+
+pub fn walk_match_arm<V: Visitor + ?Sized>(visitor: &mut V, arm: &MatchArm) {
+    visitor.visit_pattern(&arm.pattern);
+    if let Some(ref g) = arm.guard { visitor.visit_expression(g) }
+    visitor.visit_expression(&arm.body);
+}
+
+pub fn walk_pattern<V: Visitor + ?Sized>(visitor: &mut V, pattern: &PatternKind) {
+    match pattern {
+        PatternKind::WildcardPattern{..} |
+        PatternKind::BindingPattern{..} => {},
+        PatternKind::LiteralPattern{value, ..} => {
+            visitor.visit_expression(value);
+        },
+        PatternKind::ArrayPattern{elements, ..} => {
+            for e in elements {
+                visitor.visit_pattern(e);
+            }
+        },
+        PatternKind::HashPattern{pairs, ..} => {
+            for (k, v) in pairs {
+                visitor.visit_expression(k);
+                visitor.visit_pattern(v);
+            }
+        },
+        PatternKind::OrPattern{alternatives, ..} => {
+            for a in alternatives {
+                visitor.visit_pattern(a);
+            }
+        },
+        PatternKind::VariantPattern{fields, ..} => {
+            for f in fields.iter().flat_map(|f| f.iter()) {
+                visitor.visit_pattern(f);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::lexer::Lexer;
+    use super::super::parser::Parser;
+
+    struct IdentifierCounter {
+        names: Vec<String>,
+    }
+
+    impl Visitor for IdentifierCounter {
+        fn visit_expression(&mut self, expression: &ExpressionKind) {
+            if let ExpressionKind::Identifier{value, ..} = expression {
+                self.names.push(value.clone());
+            }
+            walk_expression(self, expression);
+        }
+
+        fn visit_pattern(&mut self, pattern: &PatternKind) {
+            if let PatternKind::BindingPattern{name, ..} = pattern {
+                self.names.push(name.clone());
+            }
+            walk_pattern(self, pattern);
+        }
+    }
+
+    #[test]
+    fn test_visits_every_identifier() {
+        let input = "let f := fun(a) { a + b }; if (c) { f(d)[e] } else { match g { [h] if i => j, _ => k?.l } }";
+        let mut p = Parser::new(Lexer::new(String::from(input)));
+        let program = p.parse_program();
+        let mut counter = IdentifierCounter { names: vec![] };
+        counter.visit_node(&program);
+        assert_eq!(counter.names, vec!["f", "a", "a", "b", "c", "f", "d", "e", "g", "h", "i", "j", "k"]);
+    }
+}